@@ -38,6 +38,8 @@ fn setup(mut commands: Commands, mut font_system: ResMut<CosmicFontSystem>) {
                 CosmicWrap::Wrap,
                 HoverCursor(CursorIcon::System(SystemCursorIcon::Pointer)),
                 SelectedTextColor(Color::WHITE),
+                // inside the border, which the text is also kept clear of
+                CosmicPadding::axes(6., 0.),
             ),
             (
                 TextEdit,
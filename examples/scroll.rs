@@ -4,6 +4,7 @@ use bevy::prelude::*;
 use bevy_cosmic_edit::{
     cosmic_text::{Attrs, AttrsOwned, Metrics},
     prelude::*,
+    scrollbar::Scrollbars,
     CosmicTextAlign, ScrollEnabled,
 };
 
@@ -26,6 +27,7 @@ fn setup(mut commands: Commands, mut font_system: ResMut<CosmicFontSystem>) {
                 attrs,
             ),
             ScrollEnabled(true),
+            Scrollbars::default(),
             CosmicTextAlign::top_left(),
             DefaultAttrs(AttrsOwned::new(
                 Attrs::new().color(bevy::color::palettes::css::LIMEGREEN.to_cosmic()),
@@ -48,6 +50,7 @@ fn setup(mut commands: Commands, mut font_system: ResMut<CosmicFontSystem>) {
             TextEdit2d,
             // MaxLines(1),
             CosmicWrap::InfiniteLine,
+            Scrollbars::default(),
             // Sets size of text box
             Sprite {
                 custom_size: Some(Vec2::new(300., 100.)),
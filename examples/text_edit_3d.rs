@@ -0,0 +1,47 @@
+use bevy::{picking::mesh_picking::MeshPickingPlugin, prelude::*};
+use bevy_cosmic_edit::{
+    cosmic_text::{Attrs, Metrics},
+    prelude::*,
+    CosmicTextAlign,
+};
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut font_system: ResMut<CosmicFontSystem>,
+) {
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0., 1., 4.).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+
+    let attrs = Attrs::new().color(CosmicColor::rgb(0x20, 0x20, 0x20));
+    let terminal = commands
+        .spawn((
+            CosmicEditBuffer::new(&mut font_system, Metrics::new(28., 32.)).with_text(
+                &mut font_system,
+                "An in-world terminal.\nClick to type!",
+                attrs,
+            ),
+            // pixels of the texture the text is rendered into
+            TextEdit3d::new(Vec2::new(512., 256.)),
+            // the texture is stretched over the mesh's UVs
+            Mesh3d(meshes.add(Rectangle::new(2., 1.))),
+            Transform::from_rotation(Quat::from_rotation_y(-0.4)),
+            CosmicTextAlign::top_left(),
+            CosmicPadding::all(12.),
+        ))
+        .observe(focus_on_click)
+        .id();
+
+    commands.insert_resource(FocusedWidget(Some(terminal)));
+}
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, MeshPickingPlugin))
+        .add_plugins(CosmicEditPlugin::default())
+        .add_systems(Startup, setup)
+        .add_systems(Update, deselect_editor_on_esc)
+        .run();
+}
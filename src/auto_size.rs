@@ -0,0 +1,256 @@
+//! Widgets that size themselves to their text
+//!
+//! Add [`AutoSize`] to a widget to grow and shrink it with its content, e.g. for
+//! chat bubbles or comment boxes that get taller as they are typed in.
+//! Sprites have their [`Sprite.custom_size`] set, and UI widgets are measured
+//! by bevy_ui like an image would be, so leave their [`Node`] width and height
+//! as [`Val::Auto`]. `TextEdit3d` widgets keep their size.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::auto_size::AutoSize;
+//!
+//! # fn setup(mut commands: Commands) {
+//! commands.spawn((
+//!     TextEdit2d,
+//!     CosmicEditBuffer::default(),
+//!     AutoSize {
+//!         min_width: 40.,
+//!         max_width: 300.,
+//!         ..default()
+//!     },
+//! ));
+//! # }
+//! ```
+
+use bevy::ui::{widget::update_image_content_size_system, UiSystem};
+use cosmic_text::FontSystem;
+use render_implementations::{CosmicWidgetSizeMut, FixedContentSize, ScaleFactor};
+
+use crate::{prelude::*, render::RenderSet};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.register_type::<AutoSize>().add_systems(
+        PostUpdate,
+        auto_size
+            .pipe(render_implementations::debug_error)
+            .after(update_image_content_size_system)
+            .before(UiSystem::Layout)
+            .before(RenderSet),
+    );
+}
+
+/// Sizes a widget to fit its text, see the [module docs](self).
+///
+/// Sizes are in logical pixels and include [`CosmicPadding`], but not the
+/// padding and border of a UI [`Node`], which bevy_ui adds on top.
+/// With [`CosmicWrap::Wrap`], text wraps at `max_width`; with [`CosmicWrap::InfiniteLine`]
+/// it scrolls once the widget is `max_width` wide. Text taller than `max_height` scrolls.
+///
+/// The text is only measured again when it, or anything it is laid out with, changes
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+#[require(FixedContentSize, MeasuredContent)]
+pub struct AutoSize {
+    pub min_width: f32,
+    pub max_width: f32,
+    pub min_height: f32,
+    pub max_height: f32,
+}
+
+impl Default for AutoSize {
+    fn default() -> Self {
+        Self {
+            min_width: 0.,
+            max_width: f32::INFINITY,
+            min_height: 0.,
+            max_height: f32::INFINITY,
+        }
+    }
+}
+
+/// Size of the text of an [`AutoSize`] widget when it was last measured, in logical pixels
+#[derive(Component, Default, Debug)]
+pub(crate) struct MeasuredContent(Option<Vec2>);
+
+impl AutoSize {
+    /// Size of a widget holding text of `content` size
+    fn fit(&self, content: Vec2, padding: Vec2) -> Vec2 {
+        // leaves room for the caret after the last glyph
+        let content = Vec2::new(content.x.ceil() + 1., content.y.ceil()) + padding;
+        Vec2::new(
+            content.x.min(self.max_width).max(self.min_width),
+            content.y.min(self.max_height).max(self.min_height),
+        )
+    }
+
+    /// Measures the text of `buffer` in logical pixels without changing its size or scroll,
+    /// wrapping it to fit within `max_width` and `padding`.
+    ///
+    /// The buffer is laid out at `scale`, see [`ScaleFactor`]
+    fn measure(
+        &self,
+        buffer: &mut Buffer,
+        font_system: &mut FontSystem,
+        wrap: &CosmicWrap,
+        padding: Vec2,
+        scale: f32,
+    ) -> Vec2 {
+        let (width, height) = buffer.size();
+        let scroll = buffer.scroll();
+
+        let wrap_width = match wrap {
+            CosmicWrap::Wrap if self.max_width.is_finite() => {
+                Some((self.max_width - padding.x).max(0.) * scale)
+            }
+            _ => None,
+        };
+        buffer.set_scroll(default());
+        buffer.set_size(font_system, wrap_width, None);
+        let content = buffer.borrow_with(font_system).expected_size();
+
+        buffer.set_size(font_system, width, height);
+        buffer.set_scroll(scroll);
+        content / scale
+    }
+}
+
+fn auto_size(
+    mut q: Query<(
+        (
+            Ref<AutoSize>,
+            Ref<CosmicWrap>,
+            Option<Ref<CosmicPadding>>,
+            Ref<ScaleFactor>,
+        ),
+        &mut MeasuredContent,
+        EditorBuffer,
+        CosmicWidgetSizeMut,
+    )>,
+    mut font_system: ResMut<CosmicFontSystem>,
+) -> render_implementations::Result<()> {
+    for ((auto_size, wrap, padding, scale), mut measured, mut buffer, mut size) in q.iter_mut() {
+        let padding_size = padding
+            .as_deref()
+            .map(CosmicPadding::size)
+            .unwrap_or_default();
+        // the text, its attributes and metrics can only have changed if the buffer needs a redraw
+        let changed = auto_size.is_changed()
+            || wrap.is_changed()
+            || padding.is_some_and(|padding| padding.is_changed())
+            || scale.is_changed()
+            || buffer.redraw();
+        let content = match measured.0 {
+            Some(content) if !changed => content,
+            _ => {
+                let content =
+                    auto_size.measure(&mut buffer, &mut font_system, &wrap, padding_size, scale.0);
+                measured.0 = Some(content);
+                content
+            }
+        };
+        size.set_logical_size(auto_size.fit(content, padding_size))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmic_text::Attrs;
+
+    use super::*;
+    use crate::primary::create_cosmic_font_system;
+
+    const LIMITS: AutoSize = AutoSize {
+        min_width: 40.,
+        max_width: 100.,
+        min_height: 20.,
+        max_height: 60.,
+    };
+
+    /// Frames in which the widget needed redrawing
+    #[derive(Resource, Default)]
+    struct Redraws(usize);
+
+    /// A sprite widget, with a system that sizes it and then draws it
+    fn setup() -> (World, Schedule, Entity) {
+        let mut world = World::new();
+        let font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        world.insert_resource(CosmicFontSystem(font_system));
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<Redraws>();
+        let entity = world
+            .spawn((TextEdit2d, LIMITS, CosmicWrap::Wrap, ScaleFactor::default()))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                auto_size.pipe(render_implementations::debug_error),
+                // stands in for rendering
+                |mut q: Query<EditorBuffer>, mut redraws: ResMut<Redraws>| {
+                    for mut buffer in q.iter_mut() {
+                        if buffer.redraw() {
+                            redraws.0 += 1;
+                            buffer.set_redraw(false);
+                        }
+                    }
+                },
+            )
+                .chain(),
+        );
+        (world, schedule, entity)
+    }
+
+    fn size_with_text(
+        world: &mut World,
+        schedule: &mut Schedule,
+        entity: Entity,
+        text: &str,
+    ) -> Vec2 {
+        world.resource_scope(|world, mut font_system: Mut<CosmicFontSystem>| {
+            let mut buffer = world.get_mut::<CosmicEditBuffer>(entity).unwrap();
+            buffer.set_text(&mut font_system, text, Attrs::new());
+        });
+        schedule.run(world);
+        world.get::<Sprite>(entity).unwrap().custom_size.unwrap()
+    }
+
+    #[test]
+    fn size_follows_the_text_within_limits() {
+        let (mut world, mut schedule, entity) = setup();
+        let mut size = |text| size_with_text(&mut world, &mut schedule, entity, text);
+
+        assert_eq!(size(""), Vec2::new(40., 20.));
+        assert_eq!(size("ab"), Vec2::new(40., 20.));
+        let word = size("typing");
+        assert!(word.x > 40. && word.x < 100., "{word}");
+        assert_eq!(word.y, 20.);
+        // wraps at the max width, and grows taller until the max height
+        let two_lines = size("typing more");
+        assert_eq!(two_lines.y, 40.);
+        assert!(two_lines.x <= 100., "{two_lines}");
+        let many_lines = size("typing more words than fit on a line");
+        assert_eq!(many_lines.y, 60.);
+        assert!(many_lines.x <= 100., "{many_lines}");
+        assert_eq!(size("typing"), word);
+    }
+
+    #[test]
+    fn unchanged_text_is_not_measured_again() {
+        let (mut world, mut schedule, entity) = setup();
+        size_with_text(&mut world, &mut schedule, entity, "typing");
+        assert_eq!(world.resource::<Redraws>().0, 1);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        // measuring would lay the buffer out again and mark it for a redraw
+        assert_eq!(world.resource::<Redraws>().0, 1);
+
+        world.get_mut::<AutoSize>(entity).unwrap().min_width = 80.;
+        schedule.run(&mut world);
+        let size = world.get::<Sprite>(entity).unwrap().custom_size.unwrap();
+        assert_eq!(size.x, 80.);
+    }
+}
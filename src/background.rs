@@ -0,0 +1,380 @@
+//! Fitting a [`CosmicBackgroundImage`] to its widget
+//!
+//! By default a background image covers its widget, cropping whatever overflows.
+//! Add a [`BackgroundImageStyle`] to contain, stretch, tile or 9-slice it instead,
+//! or to tint it. The [`CosmicBackgroundColor`] is only drawn without an image,
+//! so wherever the image doesn't reach stays transparent.
+//!
+//! Fitted images are cached per image and widget size, and fitted again when the
+//! image is modified, e.g. when it's hot reloaded or animated.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy::sprite::BorderRect;
+//! use bevy_cosmic_edit::{
+//!     background::{BackgroundImageMode, BackgroundImageStyle},
+//!     CosmicBackgroundImage,
+//! };
+//!
+//! # fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+//! commands.spawn((
+//!     TextEdit2d,
+//!     CosmicEditBuffer::default(),
+//!     CosmicBackgroundImage(Some(asset_server.load("panel.png"))),
+//!     BackgroundImageStyle {
+//!         mode: BackgroundImageMode::Sliced(BorderRect::square(8.)),
+//!         opacity: 0.8,
+//!         ..default()
+//!     },
+//! ));
+//! # }
+//! ```
+
+use bevy::{
+    ecs::query::QueryData,
+    sprite::BorderRect,
+    utils::{HashMap, HashSet},
+};
+use image::{
+    imageops::{self, FilterType},
+    RgbaImage,
+};
+use render_implementations::CosmicWidgetSize;
+
+use crate::{prelude::*, render::RenderSet, CosmicBackgroundColor, CosmicBackgroundImage};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.register_type::<BackgroundImageStyle>()
+        .register_required_components::<CosmicBackgroundImage, BackgroundImageStyle>()
+        .init_resource::<FittedImages>()
+        .add_systems(PostUpdate, forget_modified_images.before(RenderSet))
+        .add_systems(Last, forget_unused_fits);
+}
+
+/// How a [`CosmicBackgroundImage`] is drawn, see the [module docs](self)
+#[derive(Component, Reflect, Debug, Clone, PartialEq)]
+pub struct BackgroundImageStyle {
+    pub mode: BackgroundImageMode,
+    /// Multiplies the colours of the image
+    pub tint: Color,
+    /// From 0 for invisible to 1, multiplying the alpha of `tint`
+    pub opacity: f32,
+}
+
+impl Default for BackgroundImageStyle {
+    fn default() -> Self {
+        Self {
+            mode: BackgroundImageMode::default(),
+            tint: Color::WHITE,
+            opacity: 1.,
+        }
+    }
+}
+
+/// How a [`CosmicBackgroundImage`] is fitted to its widget
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundImageMode {
+    /// Scaled to fill the widget, keeping its aspect ratio and cropping what overflows
+    #[default]
+    Cover,
+    /// Scaled to fit within the widget, keeping its aspect ratio, and centered
+    Contain,
+    /// Scaled to the size of the widget, ignoring its aspect ratio
+    Stretch,
+    /// Repeated from the top left at its own size, a pixel of the image per logical pixel
+    Tile,
+    /// Split into nine parts by a border, in pixels of the image. The corners keep their size,
+    /// the edges are stretched along the sides and the center fills the rest, as for frames
+    /// and panels. Borders larger than the widget are shrunk to fit
+    Sliced(BorderRect),
+}
+
+/// Everything a widget's background is drawn from
+#[derive(QueryData)]
+pub(crate) struct Background {
+    image: &'static CosmicBackgroundImage,
+    style: &'static BackgroundImageStyle,
+    color: &'static CosmicBackgroundColor,
+}
+
+/// What a background was drawn with, to redraw it when that changes
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BackgroundKey {
+    color: Color,
+    image: Option<AssetId<Image>>,
+    image_loaded: bool,
+    style: BackgroundImageStyle,
+}
+
+impl BackgroundItem<'_> {
+    pub fn key(&self, images: &Assets<Image>) -> BackgroundKey {
+        BackgroundKey {
+            color: self.color.0,
+            image: self.image.0.as_ref().map(Handle::id),
+            image_loaded: self
+                .image
+                .0
+                .as_ref()
+                .is_some_and(|image| images.contains(image)),
+            style: self.style.clone(),
+        }
+    }
+
+    /// Whether the image is among `modified_images`, see [`modified_images`](crate::render::modified_images)
+    pub fn image_modified(&self, modified_images: &HashSet<AssetId<Image>>) -> bool {
+        self.image
+            .0
+            .as_ref()
+            .is_some_and(|image| modified_images.contains(&image.id()))
+    }
+
+    /// Pixels of a widget's image of physical `size` showing only its background,
+    /// for a widget rendered at `scale`
+    pub fn draw(
+        &self,
+        size: UVec2,
+        scale: f32,
+        images: &Assets<Image>,
+        fitted_images: &mut FittedImages,
+    ) -> Vec<u8> {
+        let pixel_count = size.x as usize * size.y as usize;
+        let Some(image) = &self.image.0 else {
+            let bg = self.color.0.to_cosmic();
+            return [bg.r(), bg.g(), bg.b(), bg.a()].repeat(pixel_count);
+        };
+        let fit = Fit {
+            mode: self.style.mode,
+            scale,
+        };
+        let Some(mut pixels) = fitted_images.fit(image.id(), size, fit, images) else {
+            // not loaded yet
+            return vec![0; pixel_count * 4];
+        };
+        tint(&mut pixels, self.style.tint, self.style.opacity);
+        pixels
+    }
+}
+
+/// How an image was fitted, besides the size it was fitted to
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Fit {
+    mode: BackgroundImageMode,
+    /// Tiles and slices are sized in logical pixels
+    scale: f32,
+}
+
+/// Background images fitted to the widgets showing them
+#[derive(Resource, Default)]
+pub(crate) struct FittedImages {
+    /// Pixels of each fit of an image to a physical size
+    fits: HashMap<(AssetId<Image>, UVec2), Vec<(Fit, Vec<u8>)>>,
+}
+
+impl FittedImages {
+    /// Pixels of `image` fitted to `size`, fitting it the first time.
+    /// `None` while the image isn't loaded, or if it can't be converted to RGBA
+    fn fit(
+        &mut self,
+        image: AssetId<Image>,
+        size: UVec2,
+        fit: Fit,
+        images: &Assets<Image>,
+    ) -> Option<Vec<u8>> {
+        let cached = self
+            .fits
+            .get(&(image, size))
+            .and_then(|fits| fits.iter().find(|(cached, _)| *cached == fit));
+        if let Some((_, pixels)) = cached {
+            return Some(pixels.clone());
+        }
+
+        let source = match images.get(image)?.clone().try_into_dynamic() {
+            Ok(source) => source.into_rgba8(),
+            Err(err) => {
+                warn_once!("Background image {image} can't be drawn: {err:?}");
+                return None;
+            }
+        };
+        let pixels = fit_image(&source, size, fit).into_raw();
+        self.fits
+            .entry((image, size))
+            .or_default()
+            .push((fit, pixels.clone()));
+        Some(pixels)
+    }
+}
+
+fn fit_image(source: &RgbaImage, size: UVec2, fit: Fit) -> RgbaImage {
+    let mut fitted = RgbaImage::new(size.x, size.y);
+    let source_size = UVec2::from(source.dimensions()).as_vec2();
+    match fit.mode {
+        BackgroundImageMode::Cover | BackgroundImageMode::Contain => {
+            let ratios = size.as_vec2() / source_size;
+            let ratio = match fit.mode {
+                BackgroundImageMode::Cover => ratios.max_element(),
+                _ => ratios.min_element(),
+            };
+            let scaled = (source_size * ratio).round().as_uvec2().max(UVec2::ONE);
+            // centered, so a covering image is cropped evenly on both sides
+            let offset = (size.as_ivec2() - scaled.as_ivec2()) / 2;
+            let scaled = resized(source, scaled);
+            imageops::replace(&mut fitted, &scaled, offset.x as i64, offset.y as i64);
+        }
+        BackgroundImageMode::Stretch => {
+            imageops::replace(&mut fitted, &resized(source, size), 0, 0);
+        }
+        BackgroundImageMode::Tile => {
+            let tile = (source_size * fit.scale).round().as_uvec2().max(UVec2::ONE);
+            imageops::tile(&mut fitted, &resized(source, tile));
+        }
+        BackgroundImageMode::Sliced(border) => slice(source, &mut fitted, border, fit.scale),
+    }
+    fitted
+}
+
+fn resized(source: &RgbaImage, size: UVec2) -> RgbaImage {
+    match UVec2::from(source.dimensions()) == size {
+        true => source.clone(),
+        false => imageops::resize(source, size.x, size.y, FilterType::Triangle),
+    }
+}
+
+/// Draws the nine parts of `source`, split by `border`, stretched over `fitted`
+fn slice(source: &RgbaImage, fitted: &mut RgbaImage, border: BorderRect, scale: f32) {
+    let source_size = UVec2::from(source.dimensions()).as_vec2();
+    let size = UVec2::from(fitted.dimensions()).as_vec2();
+    // opposite borders can't overlap
+    let near = Vec2::new(border.left, border.top).clamp(Vec2::ZERO, source_size);
+    let far = Vec2::new(border.right, border.bottom).clamp(Vec2::ZERO, source_size - near);
+    let shrink = (size / ((near + far) * scale)).min_element().min(1.);
+    let (near_to, far_to) = (near * scale * shrink, far * scale * shrink);
+
+    let source_cuts = [Vec2::ZERO, near, source_size - far, source_size].map(|cut| cut.round());
+    let cuts = [Vec2::ZERO, near_to, size - far_to, size].map(|cut| cut.round());
+    let part = |cuts: &[Vec2; 4], row: usize, column: usize| {
+        let min = Vec2::new(cuts[column].x, cuts[row].y);
+        let max = Vec2::new(cuts[column + 1].x, cuts[row + 1].y);
+        (min.as_uvec2(), (max - min).max(Vec2::ZERO).as_uvec2())
+    };
+    for row in 0..3 {
+        for column in 0..3 {
+            let (from, from_size) = part(&source_cuts, row, column);
+            let (to, to_size) = part(&cuts, row, column);
+            if from_size.min_element() == 0 || to_size.min_element() == 0 {
+                continue;
+            }
+            let piece = imageops::crop_imm(source, from.x, from.y, from_size.x, from_size.y);
+            let piece = resized(&piece.to_image(), to_size);
+            imageops::replace(fitted, &piece, to.x as i64, to.y as i64);
+        }
+    }
+}
+
+/// Multiplies the channels of RGBA `pixels` by `tint`, and their alpha by `opacity` too
+fn tint(pixels: &mut [u8], tint: Color, opacity: f32) {
+    let tint = tint.to_srgba();
+    let tint = tint.with_alpha(tint.alpha * opacity);
+    if tint == Srgba::WHITE {
+        return;
+    }
+    let factors = tint.to_f32_array();
+    for pixel in pixels.chunks_exact_mut(4) {
+        for (channel, factor) in pixel.iter_mut().zip(factors) {
+            *channel = (*channel as f32 * factor).round() as u8;
+        }
+    }
+}
+
+/// Forgets the fits of modified images, so they are fitted again
+fn forget_modified_images(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut fitted_images: ResMut<FittedImages>,
+) {
+    for event in image_events.read() {
+        if let AssetEvent::Modified { id } | AssetEvent::Removed { id } = event {
+            fitted_images.fits.retain(|(image, _), _| image != id);
+        }
+    }
+}
+
+/// Forgets fits no widget shows anymore, e.g. after it was resized
+fn forget_unused_fits(
+    q: Query<(Background, CosmicWidgetSize)>,
+    mut fitted_images: ResMut<FittedImages>,
+) {
+    if fitted_images.fits.is_empty() {
+        return;
+    }
+    let shown: Vec<_> = q
+        .iter()
+        .filter_map(|(background, size)| {
+            let image = background.image.0.as_ref()?.id();
+            let fit = Fit {
+                mode: background.style.mode,
+                scale: size.scale_factor(),
+            };
+            Some((image, size.physical_size().ok()?, fit))
+        })
+        .collect();
+    fitted_images.fits.retain(|&(image, size), fits| {
+        fits.retain(|(fit, _)| shown.contains(&(image, size, *fit)));
+        !fits.is_empty()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    /// A 4x2 image, red on the left and blue on the right
+    fn halves() -> RgbaImage {
+        RgbaImage::from_fn(4, 2, |x, _| if x < 2 { RED } else { BLUE })
+    }
+
+    fn fit(mode: BackgroundImageMode, size: UVec2) -> RgbaImage {
+        fit_image(&halves(), size, Fit { mode, scale: 2. })
+    }
+
+    #[test]
+    fn contain_centers_and_leaves_the_rest_transparent() {
+        let fitted = fit(BackgroundImageMode::Contain, UVec2::new(8, 8));
+        // scaled to 8x4, in the middle rows
+        assert_eq!(fitted.get_pixel(0, 1)[3], 0);
+        assert_eq!(*fitted.get_pixel(0, 2), RED);
+        assert_eq!(*fitted.get_pixel(7, 5), BLUE);
+        assert_eq!(fitted.get_pixel(7, 6)[3], 0);
+    }
+
+    #[test]
+    fn tiles_at_logical_size() {
+        let fitted = fit(BackgroundImageMode::Tile, UVec2::new(20, 4));
+        // each tile is 8x4 physical pixels
+        assert_eq!(*fitted.get_pixel(1, 0), RED);
+        assert_eq!(*fitted.get_pixel(6, 0), BLUE);
+        assert_eq!(*fitted.get_pixel(9, 3), RED);
+        assert_eq!(*fitted.get_pixel(14, 3), BLUE);
+    }
+
+    #[test]
+    fn sliced_borders_keep_their_size() {
+        let border = BorderRect::rectangle(1., 0.);
+        let fitted = fit(BackgroundImageMode::Sliced(border), UVec2::new(40, 10));
+        // the left border is 2 physical pixels of red, the right one of blue
+        assert_eq!(*fitted.get_pixel(1, 5), RED);
+        assert_eq!(*fitted.get_pixel(38, 0), BLUE);
+        assert_eq!(*fitted.get_pixel(39, 9), BLUE);
+    }
+
+    #[test]
+    fn tint_and_opacity() {
+        let mut pixels = vec![200, 100, 50, 255];
+        tint(&mut pixels, Color::srgba(0.5, 1., 1., 1.), 0.5);
+        assert_eq!(pixels, [100, 100, 50, 128]);
+    }
+}
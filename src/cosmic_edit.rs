@@ -9,37 +9,26 @@ pub(crate) fn plugin(app: &mut App) {
         .register_type::<CursorColor>()
         .register_type::<SelectionColor>()
         .register_type::<MaxLines>()
+        .register_type::<MaxLinesMode>()
         .register_type::<MaxChars>()
-        .register_type::<ScrollEnabled>();
+        .register_type::<ScrollEnabled>()
+        .register_type::<CosmicPadding>()
+        .register_type::<LimitReached>()
+        .add_event::<LimitReached>();
 }
 
 /// Enum representing text wrapping in a cosmic [`Buffer`]
-#[derive(Component, Reflect, Clone, PartialEq, Default)]
-#[component(on_add = check_align_sanity)]
+#[derive(Component, Reflect, Debug, Clone, PartialEq, Default)]
 pub enum CosmicWrap {
+    /// Lines are never wrapped.
+    ///
+    /// The widget scrolls horizontally to keep the cursor in view,
+    /// and can be scrolled sideways with shift and the mouse wheel
     InfiniteLine,
     #[default]
     Wrap,
 }
 
-fn check_align_sanity(
-    world: bevy::ecs::world::DeferredWorld,
-    target: Entity,
-    _: bevy::ecs::component::ComponentId,
-) {
-    if let Some(CosmicWrap::InfiniteLine) = world.get(target) {
-        let Some(align) = world.get::<CosmicTextAlign>(target) else {
-            return;
-        };
-        if matches!(
-            align.horizontal,
-            Some(HorizontalAlign::End | HorizontalAlign::Right | HorizontalAlign::Center)
-        ) {
-            warn!(message = "Having a widget with `CosmicWrap::InfiniteLine` while using a horizontal alignment like `HorizontalAlign::Center` will likely obscure the text");
-        }
-    }
-}
-
 /// Where to render the [`CosmicEditBuffer`] within the given size.
 ///
 /// [`cosmic_text`] can [`Align`](cosmic_text::Align) items per line already,
@@ -107,7 +96,7 @@ impl CosmicTextAlign {
 
 /// Enum representing the text alignment in a cosmic [`Buffer`].
 /// Defaults to [`CosmicTextAlign::Center`]
-#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VerticalAlign {
     /// If [bevy_cosmic_edit] made no manual calcualtions, this would
     /// effecively be the default
@@ -140,6 +129,40 @@ impl From<HorizontalAlign> for Align {
     }
 }
 
+/// Space between the edges of a widget and its text, in logical pixels.
+///
+/// Text is wrapped, aligned and clipped within the remaining space.
+/// UI widgets are also inset by their [`Node::padding`] and [`Node::border`]
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq)]
+pub struct CosmicPadding {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+impl CosmicPadding {
+    /// The same padding on every side
+    pub const fn all(padding: f32) -> Self {
+        Self::axes(padding, padding)
+    }
+
+    /// `horizontal` padding on the left and right, `vertical` on the top and bottom
+    pub const fn axes(horizontal: f32, vertical: f32) -> Self {
+        Self {
+            left: horizontal,
+            right: horizontal,
+            top: vertical,
+            bottom: vertical,
+        }
+    }
+
+    /// Total padding along each axis
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.left + self.right, self.top + self.bottom)
+    }
+}
+
 /// Tag component to disable writing to a [`CosmicEditBuffer`]
 // TODO: Code example
 #[derive(Component, Default)]
@@ -189,16 +212,43 @@ impl Default for SelectionColor {
 #[derive(Component, Reflect, Default, Deref)]
 pub struct SelectedTextColor(pub Color);
 
-/// Maximum number of lines allowed in a buffer
-// TODO: Actually test this? I'm not sure this does anything afaik
+/// Maximum number of lines allowed in a buffer, `0` for no limit.
+///
+/// Counts lines separated by new lines, unless [`MaxLinesMode::Visual`] is set
 #[derive(Component, Reflect, Default)]
 pub struct MaxLines(pub usize);
 
-/// Maximum number of characters allowed in a buffer
-// TODO: Check this functionality with widechars; Use graphemes to test?
+/// How [`MaxLines`] counts lines
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxLinesMode {
+    /// Lines separated by new lines
+    #[default]
+    Logical,
+    /// Lines as displayed, after wrapping
+    Visual,
+}
+
+/// Maximum number of characters allowed in a buffer, `0` for no limit.
+///
+/// Characters are counted as graphemes (what a user would call a character),
+/// so an emoji or an accented letter counts once. New lines count as a character too
 #[derive(Component, Reflect, Default)]
 pub struct MaxChars(pub usize);
 
+/// Sent when input is cut short by [`MaxChars`] or [`MaxLines`]
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct LimitReached {
+    pub entity: Entity,
+    pub limit: Limit,
+}
+
+/// Which limit a [`LimitReached`] event is about
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    MaxChars,
+    MaxLines,
+}
+
 /// Should [`CosmicEditBuffer`] respond to scroll events?
 #[derive(Component, Reflect, Deref)]
 pub struct ScrollEnabled(pub bool);
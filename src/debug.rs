@@ -1,12 +1,43 @@
 //! Internal debugging only
 
+use std::time::Duration;
+
 use crate::prelude::*;
 
 pub(crate) fn plugin(app: &mut App) {
-    app.add_systems(Last, change_detection)
+    app.init_resource::<RedrawCounter>()
+        .add_systems(Last, (change_detection, log_redraws))
         .add_systems(PreUpdate, change_detection);
 }
 
+/// How many times widgets were redrawn, logged about once a second
+#[derive(Resource, Default, Debug)]
+pub(crate) struct RedrawCounter {
+    redraws: u32,
+    since: Duration,
+}
+
+impl RedrawCounter {
+    pub fn count(&mut self) {
+        self.redraws += 1;
+    }
+}
+
+fn log_redraws(mut counter: ResMut<RedrawCounter>, time: Res<Time<Real>>) {
+    let now = time.elapsed();
+    if now.saturating_sub(counter.since) < Duration::from_secs(1) {
+        return;
+    }
+    if counter.redraws > 0 {
+        debug!(
+            redraws = counter.redraws,
+            "Widgets redrawn in the last second"
+        );
+    }
+    counter.redraws = 0;
+    counter.since = now;
+}
+
 /// Query filters like [`Changed<T>`] and [`Added<T>`] ensure only entities matching these filters
 /// will be returned by the query.
 ///
@@ -0,0 +1,504 @@
+//! Underlines, strikethroughs, squiggles and highlights over ranges of text
+//!
+//! Add a [`TextDecorations`] to a widget to mark parts of its text, e.g. for
+//! spell checking, error squiggles, search hits or other people's selections.
+//! Ranges are byte offsets into the text of the widget, with lines joined by `\n`
+//! (see [`BufferRefExtras::get_text`](crate::BufferRefExtras::get_text)), and they move
+//! along with edits. A decoration whose text is deleted entirely is dropped.
+//!
+//! Highlights are drawn under the selection and the glyphs, every other style over them.
+//! Decorations aren't drawn while a [`Placeholder`] or [`Password`] is shown.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::{
+//!     cosmic_text::{Attrs, Metrics},
+//!     decorations::{Decoration, DecorationStyle, TextDecorations},
+//! };
+//!
+//! # fn setup(mut commands: Commands, mut font_system: ResMut<CosmicFontSystem>) {
+//! let text = "Teh quick brown fox";
+//! commands.spawn((
+//!     TextEdit2d,
+//!     CosmicEditBuffer::new(&mut font_system, Metrics::new(20., 20.)).with_text(
+//!         &mut font_system,
+//!         text,
+//!         Attrs::new(),
+//!     ),
+//!     TextDecorations::new([
+//!         Decoration::new(0..3, DecorationStyle::WavyUnderline, Color::srgb(1., 0., 0.)),
+//!         Decoration::from_chars(
+//!             text,
+//!             10..15,
+//!             DecorationStyle::Highlight,
+//!             Color::srgba(1., 1., 0., 0.5),
+//!         ),
+//!     ]),
+//! ));
+//! # }
+//! ```
+//!
+//! [`Password`]: crate::password::Password
+
+use std::ops::Range;
+
+use cosmic_text::{Cursor, LayoutRun};
+
+use crate::{
+    edit_log::{cursor_to_offset, offset_to_cursor, EditLog, EditLogSet, LoggedEdit},
+    overlay::{is_selected, selection_rects},
+    password::{Password, PasswordSet},
+    prelude::*,
+    render::RenderSet,
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.add_systems(
+        PostUpdate,
+        track_decorations
+            .after(EditLogSet)
+            .before(PasswordSet)
+            .before(RenderSet),
+    );
+}
+
+/// How a [`Decoration`] is drawn
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecorationStyle {
+    Underline,
+    DoubleUnderline,
+    /// A zigzag under the text, e.g. for spelling mistakes
+    WavyUnderline,
+    Strikethrough,
+    /// Fills the lines behind the text, like the selection
+    Highlight,
+}
+
+/// A range of text drawn with a [`DecorationStyle`], see the [module docs](self)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoration {
+    /// Byte offsets into the text of the widget, with lines joined by `\n`
+    pub range: Range<usize>,
+    pub style: DecorationStyle,
+    pub color: Color,
+}
+
+impl Decoration {
+    pub fn new(range: Range<usize>, style: DecorationStyle, color: Color) -> Self {
+        Self {
+            range,
+            style,
+            color,
+        }
+    }
+
+    /// Decorates the characters (not bytes) `chars` of `text`
+    pub fn from_chars(
+        text: &str,
+        chars: Range<usize>,
+        style: DecorationStyle,
+        color: Color,
+    ) -> Self {
+        let offset = |char_index| {
+            text.char_indices()
+                .nth(char_index)
+                .map_or(text.len(), |(offset, _)| offset)
+        };
+        Self::new(offset(chars.start)..offset(chars.end), style, color)
+    }
+
+    /// Decorates `text` between two buffer positions, i.e. lines and byte indices within them
+    pub fn from_cursors(
+        text: &str,
+        start: Cursor,
+        end: Cursor,
+        style: DecorationStyle,
+        color: Color,
+    ) -> Self {
+        let offset = |cursor| cursor_to_offset(text, cursor).unwrap_or(text.len());
+        Self::new(offset(start)..offset(end), style, color)
+    }
+}
+
+/// Ranges of a widget's text drawn with underlines, strikethroughs or highlights,
+/// see the [module docs](self)
+#[derive(Component, Debug, Default)]
+pub struct TextDecorations {
+    decorations: Vec<Decoration>,
+    /// How many of the first `decorations` were there before this frame's edits,
+    /// those pushed since are relative to the current text already
+    tracked: usize,
+    /// Where the decorations are in the buffer, in their drawing order
+    resolved: Vec<ResolvedDecoration>,
+    /// Whether `resolved` was cleared because of a [`Password`]
+    hidden: bool,
+}
+
+/// A [`Decoration`] as buffer positions
+#[derive(Debug, Clone, Copy)]
+struct ResolvedDecoration {
+    start: Cursor,
+    end: Cursor,
+    style: DecorationStyle,
+    color: cosmic_text::Color,
+}
+
+impl TextDecorations {
+    pub fn new(decorations: impl IntoIterator<Item = Decoration>) -> Self {
+        Self {
+            decorations: decorations.into_iter().collect(),
+            ..default()
+        }
+    }
+
+    /// Adds a decoration, with its range relative to the current text.
+    /// Later decorations are drawn over earlier ones
+    pub fn push(&mut self, decoration: Decoration) {
+        self.decorations.push(decoration);
+    }
+
+    /// The decorations, with their ranges moved along with any edits
+    /// up to the last frame
+    pub fn iter(&self) -> impl Iterator<Item = &Decoration> {
+        self.decorations.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.decorations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decorations.is_empty()
+    }
+
+    /// Keeps only the decorations for which `keep` returns true,
+    /// e.g. to remove the marks of one spell checking pass
+    pub fn retain(&mut self, mut keep: impl FnMut(&Decoration) -> bool) {
+        let mut index = 0;
+        let mut removed_tracked = 0;
+        self.decorations.retain(|decoration| {
+            let kept = keep(decoration);
+            if !kept && index < self.tracked {
+                removed_tracked += 1;
+            }
+            index += 1;
+            kept
+        });
+        self.tracked -= removed_tracked;
+    }
+
+    pub fn clear(&mut self) {
+        self.decorations.clear();
+        self.tracked = 0;
+    }
+
+    /// Moves the decorations along with `edits`, and works out where they are in `text`
+    /// unless it's `hidden`
+    fn observe(&mut self, edits: &[LoggedEdit], text: &str, hidden: bool) {
+        self.hidden = hidden;
+        for edit in edits {
+            let edit = (edit.offset, edit.removed.len(), edit.inserted.len());
+            let mut index = 0;
+            let tracked = self.tracked;
+            self.decorations.retain_mut(|decoration| {
+                index += 1;
+                if index > tracked {
+                    return true;
+                }
+                match track_edit(decoration.range.clone(), edit) {
+                    Some(range) => {
+                        decoration.range = range;
+                        true
+                    }
+                    None => {
+                        self.tracked -= 1;
+                        false
+                    }
+                }
+            });
+        }
+        self.tracked = self.decorations.len();
+
+        // the text under a password still changes, only drawing waits for it to show
+        if hidden {
+            self.resolved.clear();
+            return;
+        }
+        self.resolved = self
+            .decorations
+            .iter()
+            .filter(|decoration| {
+                let range = &decoration.range;
+                range.start < range.end
+                    && text.is_char_boundary(range.start)
+                    && range.end <= text.len()
+                    && text.is_char_boundary(range.end)
+            })
+            .map(|decoration| ResolvedDecoration {
+                start: offset_to_cursor(text, decoration.range.start),
+                end: offset_to_cursor(text, decoration.range.end),
+                style: decoration.style,
+                color: decoration.color.to_cosmic(),
+            })
+            .collect();
+    }
+
+    /// Calls `fill` with each rectangle, in buffer coordinates, of the highlights in `run`,
+    /// which are drawn under the glyphs
+    pub(crate) fn highlight_rects(
+        &self,
+        buffer: &Buffer,
+        run: &LayoutRun,
+        mut fill: impl FnMut(IRect, cosmic_text::Color),
+    ) {
+        for decoration in self
+            .resolved
+            .iter()
+            .filter(|decoration| decoration.style == DecorationStyle::Highlight)
+        {
+            selection_rects(buffer, run, (decoration.start, decoration.end), |rect| {
+                fill(rect, decoration.color)
+            });
+        }
+    }
+
+    /// Calls `fill` with each rectangle, in buffer coordinates, of the underlines
+    /// and strikethroughs in `run`, which are drawn over the glyphs
+    pub(crate) fn line_rects(
+        &self,
+        run: &LayoutRun,
+        mut fill: impl FnMut(IRect, cosmic_text::Color),
+    ) {
+        let thickness = (run.line_height / 16.).max(1.) as i32;
+        let baseline = run.line_y as i32;
+        for decoration in self
+            .resolved
+            .iter()
+            .filter(|decoration| decoration.style != DecorationStyle::Highlight)
+        {
+            let mut fill = |rect| fill(rect, decoration.color);
+            glyph_spans(
+                run,
+                (decoration.start, decoration.end),
+                |min, max, font_size| {
+                    let line = |y| IRect::new(min, y, max, y + thickness);
+                    match decoration.style {
+                        DecorationStyle::Underline => fill(line(baseline + thickness)),
+                        DecorationStyle::DoubleUnderline => {
+                            fill(line(baseline + thickness));
+                            fill(line(baseline + 3 * thickness));
+                        }
+                        DecorationStyle::WavyUnderline => {
+                            for (i, x) in (min..max).step_by(thickness as usize).enumerate() {
+                                let y = baseline + thickness * [1, 2, 3, 2][i % 4];
+                                fill(IRect::new(x, y, (x + thickness).min(max), y + thickness));
+                            }
+                        }
+                        DecorationStyle::Strikethrough => {
+                            fill(line(baseline - (font_size * 0.3) as i32))
+                        }
+                        DecorationStyle::Highlight => {}
+                    }
+                },
+            );
+        }
+    }
+}
+
+/// Calls `span` with the horizontal extent and largest font size of each stretch
+/// of glyphs in `run` between `start` and `end`
+fn glyph_spans(
+    run: &LayoutRun,
+    (start, end): (Cursor, Cursor),
+    mut span: impl FnMut(i32, i32, f32),
+) {
+    let mut current: Option<(f32, f32, f32)> = None;
+    for glyph in run.glyphs.iter() {
+        if is_selected(run.line_i, glyph, (start, end)) {
+            current = Some(match current {
+                Some((min, max, size)) => (
+                    min.min(glyph.x),
+                    max.max(glyph.x + glyph.w),
+                    size.max(glyph.font_size),
+                ),
+                None => (glyph.x, glyph.x + glyph.w, glyph.font_size),
+            });
+        } else if let Some((min, max, size)) = current.take() {
+            span(min as i32, max.ceil() as i32, size);
+        }
+    }
+    if let Some((min, max, size)) = current {
+        span(min as i32, max.ceil() as i32, size);
+    }
+}
+
+/// Moves `range` along with an edit that replaced `removed` bytes at `offset`
+/// with `inserted` bytes, returning `None` if none of its text is left.
+///
+/// Text inserted at either end of the range isn't decorated
+fn track_edit(
+    range: Range<usize>,
+    (offset, removed, inserted): (usize, usize, usize),
+) -> Option<Range<usize>> {
+    let edit_end = offset + removed;
+    let start = match range.start < offset {
+        true => range.start,
+        false => range.start.max(edit_end) - removed + inserted,
+    };
+    let end = match range.end {
+        end if end <= offset => end,
+        end if end >= edit_end => end - removed + inserted,
+        _ => offset,
+    };
+    (start < end).then_some(start..end)
+}
+
+fn track_decorations(mut q: Query<(&mut TextDecorations, &EditLog, EditorBuffer, Has<Password>)>) {
+    for (mut decorations, log, mut buffer, password) in q.iter_mut() {
+        let changed = decorations.is_changed() || decorations.hidden != password;
+        if !changed && log.edits().is_empty() {
+            continue;
+        }
+        if changed {
+            buffer.set_redraw(true);
+        }
+        decorations.bypass_change_detection().observe(
+            log.edits(),
+            log.text().unwrap_or_default(),
+            password,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmic_text::ChangeItem;
+
+    use super::*;
+
+    #[test]
+    fn ranges_follow_edits() {
+        let edit = |range, edit| track_edit(range, edit);
+        // typing before, inside and after "quick" in "the quick fox"
+        assert_eq!(edit(4..9, (0, 0, 2)), Some(6..11));
+        assert_eq!(edit(4..9, (6, 0, 1)), Some(4..10));
+        assert_eq!(edit(4..9, (10, 0, 3)), Some(4..9));
+        // typing right at either end doesn't extend it
+        assert_eq!(edit(4..9, (4, 0, 1)), Some(5..10));
+        assert_eq!(edit(4..9, (9, 0, 1)), Some(4..9));
+        // deleting part or all of it
+        assert_eq!(edit(4..9, (2, 4, 0)), Some(2..5));
+        assert_eq!(edit(4..9, (7, 4, 0)), Some(4..7));
+        assert_eq!(edit(4..9, (3, 7, 1)), None);
+    }
+
+    /// Runs a frame in which the text was turned into `text` by `changes`, or by anything else
+    fn frame(
+        decorations: &mut TextDecorations,
+        log: &mut EditLog,
+        changes: Vec<ChangeItem>,
+        text: &str,
+    ) {
+        hidden_frame(decorations, log, changes, text, false);
+    }
+
+    /// Like [`frame`], with the text `hidden` by a [`Password`]
+    fn hidden_frame(
+        decorations: &mut TextDecorations,
+        log: &mut EditLog,
+        changes: Vec<ChangeItem>,
+        text: &str,
+        hidden: bool,
+    ) {
+        log.record(changes, text.into(), false);
+        decorations.observe(log.edits(), text, hidden);
+        log.clear();
+    }
+
+    #[test]
+    fn pushed_decorations_are_relative_to_the_current_text() {
+        let color = Color::WHITE;
+        let mut decorations =
+            TextDecorations::new([Decoration::new(4..9, DecorationStyle::Underline, color)]);
+        let mut log = EditLog::default();
+        frame(&mut decorations, &mut log, Vec::new(), "the quick fox");
+
+        decorations.push(Decoration::from_chars(
+            "a very quick fox",
+            7..12,
+            DecorationStyle::Strikethrough,
+            color,
+        ));
+        frame(&mut decorations, &mut log, Vec::new(), "a very quick fox");
+        let ranges: Vec<_> = decorations.iter().map(|d| d.range.clone()).collect();
+        assert_eq!(ranges, [7..12, 7..12]);
+
+        frame(&mut decorations, &mut log, Vec::new(), "a very quick\nfox");
+        assert_eq!(decorations.resolved.len(), 2);
+        assert_eq!(decorations.resolved[0].end, Cursor::new(0, 12));
+
+        decorations.retain(|d| d.style == DecorationStyle::Strikethrough);
+        frame(&mut decorations, &mut log, Vec::new(), "quick\nfox");
+        assert_eq!(decorations.len(), 1);
+        assert_eq!(decorations.iter().next().unwrap().range, 0..5);
+    }
+
+    #[test]
+    fn edits_at_both_ends_keep_the_decorations_between() {
+        let mut decorations = TextDecorations::new([Decoration::new(
+            4..9,
+            DecorationStyle::Highlight,
+            Color::WHITE,
+        )]);
+        let mut log = EditLog::default();
+        frame(&mut decorations, &mut log, Vec::new(), "the quick fox");
+
+        let insert = |index, text: &str| ChangeItem {
+            start: Cursor::new(0, index),
+            end: Cursor::new(0, index + text.len()),
+            text: text.into(),
+            insert: true,
+        };
+        let changes = vec![insert(0, "see "), insert(17, "!")];
+        frame(&mut decorations, &mut log, changes, "see the quick fox!");
+        assert_eq!(decorations.iter().next().unwrap().range, 8..13);
+    }
+
+    #[test]
+    fn hidden_decorations_still_follow_edits() {
+        let mut decorations = TextDecorations::new([Decoration::new(
+            4..9,
+            DecorationStyle::Underline,
+            Color::WHITE,
+        )]);
+        let mut log = EditLog::default();
+        frame(&mut decorations, &mut log, Vec::new(), "the quick fox");
+
+        let changes = vec![ChangeItem {
+            start: Cursor::new(0, 0),
+            end: Cursor::new(0, 4),
+            text: "see ".into(),
+            insert: true,
+        }];
+        hidden_frame(
+            &mut decorations,
+            &mut log,
+            changes,
+            "see the quick fox",
+            true,
+        );
+        assert!(decorations.resolved.is_empty());
+        assert_eq!(decorations.iter().next().unwrap().range, 8..13);
+
+        // shown again where the text moved to
+        hidden_frame(
+            &mut decorations,
+            &mut log,
+            Vec::new(),
+            "see the quick fox",
+            false,
+        );
+        assert_eq!(decorations.resolved[0].start, Cursor::new(0, 8));
+    }
+}
@@ -0,0 +1,435 @@
+//! Redraws only the rows of a widget's image whose text changed, e.g. the line
+//! being typed in.
+//!
+//! The redrawn rows are written into the widget's [`Image`] asset, which bevy then
+//! uploads to the GPU in full. Add [`GpuRowUploads`] to a widget to instead write them
+//! straight into its GPU texture, uploading just those rows:
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::dirty_rows::GpuRowUploads;
+//!
+//! # fn setup(mut commands: Commands) {
+//! commands.spawn((
+//!     TextEdit2d,
+//!     CosmicEditBuffer::default(),
+//!     Sprite {
+//!         custom_size: Some(Vec2::new(1920., 1080.)),
+//!         ..default()
+//!     },
+//!     GpuRowUploads,
+//! ));
+//! # }
+//! ```
+//!
+//! The asset of such a widget is **not** authoritative: its pixels are those of its last
+//! full redraw (e.g. after a resize or a change of colors), so reading it back on the CPU,
+//! e.g. from a [`CosmicRenderTarget`], can show stale text. Without a renderer, e.g. in
+//! headless apps, there is nothing to upload rows to and the asset is written instead.
+//!
+//! [`CosmicRenderTarget`]: crate::render_implementations::CosmicRenderTarget
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Range,
+};
+
+use bevy::render::{
+    render_asset::RenderAssets,
+    render_resource::{Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect},
+    renderer::RenderQueue,
+    texture::GpuImage,
+    Extract, ExtractSchedule, Render, RenderApp,
+};
+use cosmic_text::Cursor;
+
+use crate::{
+    decorations::TextDecorations,
+    overlay::{is_selected, selection_rects},
+    prelude::*,
+    render::{RenderSet, WidgetBufferCoordTransformation},
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.register_type::<GpuRowUploads>();
+    let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+        // without a renderer, images are always written in full
+        return;
+    };
+    render_app
+        .init_resource::<RowUploads>()
+        .add_systems(ExtractSchedule, extract_row_uploads)
+        .add_systems(
+            Render,
+            upload_rows.in_set(bevy::render::RenderSet::PrepareResources),
+        );
+    app.init_resource::<RowUploads>()
+        .add_systems(PostUpdate, clear_row_uploads.before(RenderSet));
+}
+
+/// Uploads the redrawn rows of a widget's image straight to its GPU texture,
+/// leaving its [`Image`] asset stale, see the [module docs](self)
+#[derive(Component, Reflect, Default, Debug, Clone, Copy)]
+pub struct GpuRowUploads;
+
+/// Bytes per pixel of the images text is drawn into
+pub(crate) const PIXEL_SIZE: usize = 4;
+
+/// What a layout run was drawn with, in the rows of the widget it covers
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RunKey {
+    rows: Range<i32>,
+    hash: u64,
+}
+
+/// What besides the glyphs is drawn along with the text
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RunExtras<'a> {
+    /// Highlighted by [`SelectionColor`]
+    pub selection: Option<(Cursor, Cursor)>,
+    /// Drawn in [`SelectedTextColor`]
+    pub recolored: Option<(Cursor, Cursor)>,
+    /// Position of a visible caret
+    pub caret: Option<(i32, i32)>,
+    /// Drawn over the glyphs
+    pub decorations: Option<&'a TextDecorations>,
+    /// Whether the highlights of [`RunExtras::decorations`] are drawn under the glyphs,
+    /// rather than as overlays
+    pub highlights: bool,
+}
+
+/// Keys of the layout runs of `buffer` as placed by `transformation`
+pub(crate) fn run_keys(
+    buffer: &Buffer,
+    transformation: &WidgetBufferCoordTransformation,
+    extras: RunExtras<'_>,
+) -> Vec<RunKey> {
+    let origin = transformation.buffer_to_physical(Vec2::ZERO);
+    buffer
+        .layout_runs()
+        .map(|run| {
+            let mut hasher = DefaultHasher::new();
+            (origin.x.to_bits(), run.line_i, run.line_y.to_bits()).hash(&mut hasher);
+            for glyph in run.glyphs.iter() {
+                let physical = glyph.physical((0., 0.), 1.0);
+                (physical.cache_key, physical.x, physical.y, glyph.color_opt).hash(&mut hasher);
+                let recolored = extras
+                    .recolored
+                    .is_some_and(|recolored| is_selected(run.line_i, glyph, recolored));
+                recolored.hash(&mut hasher);
+            }
+            if let Some(selection) = extras.selection {
+                selection_rects(buffer, &run, selection, |rect| {
+                    (rect.min, rect.max).hash(&mut hasher)
+                });
+            }
+            if let Some(decorations) = extras.decorations {
+                let mut hash_rect = |rect: IRect, color: cosmic_text::Color| {
+                    (rect.min, rect.max, color.0).hash(&mut hasher)
+                };
+                if extras.highlights {
+                    decorations.highlight_rects(buffer, &run, &mut hash_rect);
+                }
+                decorations.line_rects(&run, &mut hash_rect);
+            }
+            if let Some((x, y)) = extras.caret {
+                if y == run.line_top as i32 {
+                    x.hash(&mut hasher);
+                }
+            }
+            let top = origin.y + run.line_top;
+            RunKey {
+                rows: top.floor() as i32..(top + run.line_height).ceil() as i32,
+                hash: hasher.finish(),
+            }
+        })
+        .collect()
+}
+
+/// What was last drawn into a widget's image, to redraw only the rows that change
+#[derive(Component, Default)]
+pub(crate) struct DrawnPixels {
+    image: Option<AssetId<Image>>,
+    size: UVec2,
+    /// What rows are cleared to before text is drawn over them, kept between redraws
+    background: Vec<u8>,
+    runs: Vec<RunKey>,
+    caret: Option<(i32, i32)>,
+}
+
+impl DrawnPixels {
+    /// Whether a caret drawn into the image moved, appeared or disappeared
+    pub fn caret_changed(&self, caret: Option<(i32, i32)>) -> bool {
+        self.caret != caret
+    }
+
+    /// Rows of `image` to redraw for text laid out in `runs`, or `None` to redraw all of it.
+    ///
+    /// Glyphs can reach past their line, e.g. italics and accents,
+    /// so rows a line height around each changed run are redrawn too
+    pub fn dirty_rows(
+        &self,
+        image: AssetId<Image>,
+        size: UVec2,
+        runs: &[RunKey],
+    ) -> Option<Vec<Range<u32>>> {
+        if self.image != Some(image) || self.size != size {
+            return None;
+        }
+        let changed_runs = runs
+            .iter()
+            .filter(|run| !self.runs.contains(run))
+            .chain(self.runs.iter().filter(|run| !runs.contains(run)));
+        let mut rows: Vec<Range<u32>> = changed_runs
+            .map(|run| {
+                let margin = run.rows.len() as i32;
+                let clamp = |row: i32| row.clamp(0, size.y as i32) as u32;
+                clamp(run.rows.start - margin)..clamp(run.rows.end + margin)
+            })
+            .filter(|rows| !rows.is_empty())
+            .collect();
+        rows.sort_by_key(|rows| rows.start);
+        let mut merged: Vec<Range<u32>> = Vec::with_capacity(rows.len());
+        for rows in rows {
+            match merged.last_mut() {
+                Some(last) if last.end >= rows.start => last.end = last.end.max(rows.end),
+                _ => merged.push(rows),
+            }
+        }
+        Some(merged)
+    }
+
+    /// The background rows are cleared to, set on a full redraw
+    pub fn background_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.background
+    }
+
+    /// Clears `rows` to the background of an image `width` pixels wide.
+    /// `pixels` holds the rows of the image from `first_row` on
+    pub fn clear(&self, pixels: &mut [u8], width: u32, first_row: u32, rows: &[Range<u32>]) {
+        let row_size = width as usize * PIXEL_SIZE;
+        for rows in rows {
+            let range = rows.start as usize * row_size..rows.end as usize * row_size;
+            let offset = first_row as usize * row_size;
+            pixels[range.start - offset..range.end - offset]
+                .copy_from_slice(&self.background[range]);
+        }
+    }
+
+    /// Remembers what was drawn
+    pub fn remember(
+        &mut self,
+        image: AssetId<Image>,
+        size: UVec2,
+        runs: Vec<RunKey>,
+        caret: Option<(i32, i32)>,
+    ) {
+        self.image = Some(image);
+        self.size = size;
+        self.runs = runs;
+        self.caret = caret;
+    }
+
+    /// Remembers the layout when nothing needed redrawing
+    pub fn keep(&mut self, runs: Vec<RunKey>, caret: Option<(i32, i32)>) {
+        self.runs = runs;
+        self.caret = caret;
+    }
+}
+
+/// Which rows of a widget are being redrawn, for quick lookups while drawing pixels
+pub(crate) struct RowMask(Option<Vec<bool>>);
+
+impl RowMask {
+    pub fn new(height: u32, dirty: Option<&[Range<u32>]>) -> Self {
+        Self(dirty.map(|dirty| {
+            let mut mask = vec![false; height as usize];
+            for rows in dirty {
+                mask[rows.start as usize..rows.end as usize].fill(true);
+            }
+            mask
+        }))
+    }
+
+    pub fn contains(&self, row: i32) -> bool {
+        match &self.0 {
+            None => true,
+            Some(mask) => usize::try_from(row)
+                .ok()
+                .and_then(|row| mask.get(row))
+                .is_some_and(|dirty| *dirty),
+        }
+    }
+
+    /// Whether any of `rows` is being redrawn
+    pub fn overlaps(&self, rows: Range<i32>) -> bool {
+        match &self.0 {
+            None => true,
+            Some(mask) => {
+                let clamp = |row: i32| row.clamp(0, mask.len() as i32) as usize;
+                mask[clamp(rows.start)..clamp(rows.end)]
+                    .iter()
+                    .any(|dirty| *dirty)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RowUpload {
+    image: AssetId<Image>,
+    width: u32,
+    first_row: u32,
+    pixels: Vec<u8>,
+}
+
+/// Rows redrawn this frame, written into their GPU textures by the render world.
+///
+/// Only present when rendering, otherwise images are written in full
+#[derive(Resource, Default, Debug, Clone)]
+pub(crate) struct RowUploads(Vec<RowUpload>);
+
+impl RowUploads {
+    /// Queues the `dirty` rows of `image` for upload.
+    /// `pixels` holds the rows of the image from `first_row` on
+    pub fn queue(
+        &mut self,
+        image: AssetId<Image>,
+        width: u32,
+        pixels: &[u8],
+        first_row: u32,
+        dirty: &[Range<u32>],
+    ) {
+        let row_size = width as usize * PIXEL_SIZE;
+        for rows in dirty {
+            let start = (rows.start - first_row) as usize * row_size;
+            let end = (rows.end - first_row) as usize * row_size;
+            self.0.push(RowUpload {
+                image,
+                width,
+                first_row: rows.start,
+                pixels: pixels[start..end].to_vec(),
+            });
+        }
+    }
+}
+
+fn clear_row_uploads(mut uploads: ResMut<RowUploads>) {
+    uploads.0.clear();
+}
+
+fn extract_row_uploads(mut uploads: ResMut<RowUploads>, main_world: Extract<Res<RowUploads>>) {
+    uploads.0.clone_from(&main_world.0);
+}
+
+/// Runs after the images modified this frame were prepared, so rows are written
+/// over their latest contents
+fn upload_rows(
+    uploads: Res<RowUploads>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    queue: Res<RenderQueue>,
+) {
+    for upload in uploads.0.iter() {
+        let Some(gpu_image) = gpu_images.get(upload.image) else {
+            continue;
+        };
+        let rows = (upload.pixels.len() / (upload.width as usize * PIXEL_SIZE)) as u32;
+        if gpu_image.size.x != upload.width || upload.first_row + rows > gpu_image.size.y {
+            continue;
+        }
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &gpu_image.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: 0,
+                    y: upload.first_row,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            &upload.pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(upload.width * PIXEL_SIZE as u32),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: upload.width,
+                height: rows,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    fn run(rows: Range<i32>, hash: u64) -> RunKey {
+        RunKey { rows, hash }
+    }
+
+    fn drawn(runs: Vec<RunKey>) -> DrawnPixels {
+        let mut drawn = DrawnPixels::default();
+        drawn.remember(AssetId::default(), UVec2::new(2, 100), runs, None);
+        drawn
+    }
+
+    #[test]
+    fn changed_run_and_its_margin() {
+        let drawn = drawn(vec![run(0..10, 1), run(10..20, 2), run(20..30, 3)]);
+        let runs = [run(0..10, 1), run(10..20, 4), run(20..30, 3)];
+        let dirty = drawn.dirty_rows(AssetId::default(), UVec2::new(2, 100), &runs);
+        assert_eq!(dirty, Some(vec![0..30]));
+        // nothing changed
+        let runs = [run(0..10, 1), run(10..20, 2), run(20..30, 3)];
+        let dirty = drawn.dirty_rows(AssetId::default(), UVec2::new(2, 100), &runs);
+        assert_eq!(dirty, Some(vec![]));
+    }
+
+    #[test]
+    fn removed_runs_are_merged() {
+        let drawn = drawn(vec![run(40..50, 1), run(50..60, 2), run(80..90, 3)]);
+        let runs = [run(40..50, 1)];
+        let dirty = drawn.dirty_rows(AssetId::default(), UVec2::new(2, 100), &runs);
+        // rows a line height around both removed runs, which touch
+        assert_eq!(dirty, Some(vec![40..100]));
+    }
+
+    #[test]
+    fn resized_images_are_redrawn_in_full() {
+        let drawn = drawn(vec![run(0..10, 1)]);
+        let runs = [run(0..10, 1)];
+        assert_eq!(
+            drawn.dirty_rows(AssetId::default(), UVec2::new(3, 100), &runs),
+            None
+        );
+    }
+
+    #[test]
+    fn row_mask() {
+        let mask = RowMask::new(10, Some(&[2..4]));
+        assert!(!mask.contains(1));
+        assert!(mask.contains(3));
+        assert!(!mask.contains(-1));
+        assert!(RowMask::new(10, None).contains(9));
+        assert!(mask.overlaps(-5..3));
+        assert!(!mask.overlaps(4..20));
+        assert!(RowMask::new(10, None).overlaps(20..30));
+    }
+
+    #[test]
+    fn only_dirty_rows_are_cleared() {
+        let mut drawn = drawn(Vec::new());
+        *drawn.background_mut() = (0..100).flat_map(|row| [row; 2 * PIXEL_SIZE]).collect();
+        // rows 10 to 20 of the image
+        let mut pixels = vec![255; 10 * 2 * PIXEL_SIZE];
+        drawn.clear(&mut pixels, 2, 10, &[12..14]);
+        let rows: Vec<u8> = pixels.chunks(2 * PIXEL_SIZE).map(|row| row[0]).collect();
+        assert_eq!(rows, [255, 255, 12, 13, 255, 255, 255, 255, 255, 255]);
+    }
+}
@@ -0,0 +1,457 @@
+//! Programmatic editing of widgets
+//!
+//! Trigger an [`EditCommand`] on a widget entity to change its text, cursor or
+//! selection without touching [`CosmicEditor`] directly:
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! fn type_hello(mut commands: Commands, widget: Single<Entity, With<CosmicEditBuffer>>) {
+//!     commands
+//!         .entity(*widget)
+//!         .trigger(EditCommand::InsertText("Hello".into()));
+//! }
+//! ```
+//!
+//! Commands go through the same path as keyboard input, so [`MaxChars`](crate::MaxChars),
+//! [`MaxLines`](crate::MaxLines), [`InputFilter`](crate::input::filter::InputFilter),
+//! [`ReadOnly`], [`Placeholder`] and [`Password`](crate::password::Password) are respected
+//! and [`CosmicTextChanged`] is sent when the text changes.
+//!
+//! Commands are applied in [`InputSet`] during [`Update`], after keyboard input.
+//! Untargeted commands (`commands.trigger(..)`) apply to the [`FocusedWidget`].
+//!
+//! Unfocused widgets have no cursor of their own, so text is inserted at the end of
+//! their buffer and cursor/selection commands are ignored.
+
+use cosmic_text::{Action, Cursor, Motion, Selection, Shaping};
+
+use crate::{
+    edit_log::{take_changes, EditLog},
+    input::{
+        clipboard::{self, WasmPasteAsyncChannel},
+        keyboard::{insert_text, InputConstraints, InputConstraintsItem, InsertEvents},
+        CosmicTextChanged, InputSet,
+    },
+    placeholder::Placeholder,
+    prelude::*,
+    undo::{undo_or_redo, UndoHistory},
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.init_resource::<EditCommandQueue>()
+        .add_observer(queue_edit_command)
+        .add_systems(
+            Update,
+            apply_edit_commands
+                .after(crate::input::clipboard::kb_clipboard)
+                .after(crate::undo::kb_undo_redo)
+                .in_set(InputSet),
+        );
+}
+
+/// An edit to apply to a widget, see the [module docs](self)
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum EditCommand {
+    /// Inserts text at the cursor, replacing the selection if there is one
+    InsertText(String),
+    /// Replaces the selected text, does nothing if nothing is selected
+    ReplaceSelection(String),
+    /// Replaces the whole text of the widget
+    SetText(String),
+    /// Deletes the text between two cursors
+    DeleteRange {
+        start: Cursor,
+        end: Cursor,
+    },
+    /// Deletes the selection or the grapheme before the cursor
+    Backspace,
+    /// Deletes the selection or the grapheme after the cursor
+    Delete,
+    SetCursor(Cursor),
+    SetSelection(Selection),
+    /// Moves the cursor, clearing the selection
+    MoveCursor(Motion),
+    /// Moves the cursor, extending the selection
+    ExtendSelection(Motion),
+    SelectAll,
+    Cut,
+    Copy,
+    Paste,
+    Undo,
+    Redo,
+}
+
+impl EditCommand {
+    /// Whether this command can change the text, and so is ignored on [`ReadOnly`] widgets
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            EditCommand::SetCursor(_)
+                | EditCommand::SetSelection(_)
+                | EditCommand::MoveCursor(_)
+                | EditCommand::ExtendSelection(_)
+                | EditCommand::SelectAll
+                | EditCommand::Copy
+        )
+    }
+
+    /// Whether this command adds text, and so replaces an active [`Placeholder`]
+    fn is_inserting(&self) -> bool {
+        matches!(
+            self,
+            EditCommand::InsertText(_) | EditCommand::SetText(_) | EditCommand::Paste
+        )
+    }
+}
+
+/// Commands waiting for [`apply_edit_commands`], with their target widget
+/// (`None` for the focused widget)
+#[derive(Resource, Default, Debug)]
+struct EditCommandQueue(Vec<(Option<Entity>, EditCommand)>);
+
+fn queue_edit_command(trigger: Trigger<EditCommand>, mut queue: ResMut<EditCommandQueue>) {
+    let target = Some(trigger.entity()).filter(|e| *e != Entity::PLACEHOLDER);
+    queue.0.push((target, trigger.event().clone()));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_edit_commands(
+    mut queue: ResMut<EditCommandQueue>,
+    active_editor: Res<FocusedWidget>,
+    mut q: Query<(
+        Option<&mut CosmicEditor>,
+        &mut CosmicEditBuffer,
+        InputConstraints,
+        &DefaultAttrs,
+        (&mut UndoHistory, &mut EditLog),
+        Has<ReadOnly>,
+    )>,
+    mut evw_changed: EventWriter<CosmicTextChanged>,
+    mut insert_events: InsertEvents,
+    mut font_system: ResMut<CosmicFontSystem>,
+    channel: Option<Res<WasmPasteAsyncChannel>>,
+    time: Res<Time>,
+) {
+    for (target, command) in queue.0.drain(..) {
+        let Some(entity) = target.or(active_editor.0) else {
+            debug!(
+                message = "Ignoring untargeted EditCommand, no widget is focused",
+                ?command
+            );
+            continue;
+        };
+        let Ok((editor, mut buffer, mut constraints, attrs, (mut history, mut log), readonly)) =
+            q.get_mut(entity)
+        else {
+            warn!(message = "EditCommand target is not a text widget", ?entity);
+            continue;
+        };
+
+        if readonly && command.is_mutating() {
+            debug!(
+                message = "Ignoring EditCommand on ReadOnly widget",
+                ?command
+            );
+            continue;
+        }
+
+        let focused = editor.is_some();
+        let mut unfocused_editor;
+        let editor: &mut CosmicEditor = match editor {
+            Some(editor) => editor.into_inner(),
+            None => {
+                if !command.is_mutating() {
+                    continue;
+                }
+                unfocused_editor = CosmicEditor::clone_from_buffer(&buffer);
+                let end = buffer_end(&unfocused_editor);
+                unfocused_editor.set_cursor(end);
+                &mut unfocused_editor
+            }
+        };
+
+        let placeholder_active = constraints
+            .placeholder
+            .as_deref()
+            .is_some_and(Placeholder::is_active);
+        let old_text = match placeholder_active {
+            true => String::new(),
+            false => editor.get_text(),
+        };
+
+        match command {
+            EditCommand::Undo | EditCommand::Redo => {
+                undo_or_redo(
+                    editor,
+                    (&mut history, &mut log),
+                    attrs,
+                    constraints.placeholder.as_deref_mut(),
+                    &mut font_system.0,
+                    command == EditCommand::Undo,
+                    time.elapsed(),
+                );
+            }
+            _ if placeholder_active && !command.is_inserting() => {
+                // there is no real text to edit or select
+            }
+            command => {
+                if let Some(placeholder) = constraints
+                    .placeholder
+                    .as_deref_mut()
+                    .filter(|_| placeholder_active)
+                {
+                    editor.with_buffer_mut(|b| {
+                        b.set_text(&mut font_system, "", attrs.as_attrs(), Shaping::Advanced)
+                    });
+                    editor.set_cursor(Cursor::new(0, 0));
+                    editor.set_selection(Selection::None);
+                    placeholder.set_active(false);
+                }
+                apply_command(
+                    editor,
+                    command,
+                    &mut font_system,
+                    &constraints,
+                    &mut insert_events,
+                    channel.as_deref(),
+                );
+            }
+        }
+
+        editor.set_redraw(true);
+        editor.cursor_visible = true;
+        editor.cursor_timer.reset();
+
+        let new_text = editor.get_text();
+        if new_text == old_text {
+            continue;
+        }
+        if !focused {
+            // the edits of the temporary editor would be lost with it
+            let text = match constraints
+                .placeholder
+                .as_deref()
+                .is_some_and(Placeholder::is_active)
+            {
+                true => String::new(),
+                false => new_text.clone(),
+            };
+            log.record(take_changes(editor), text, false);
+            *buffer = CosmicEditBuffer::from_downgrading_editor(editor);
+        }
+        evw_changed.send(CosmicTextChanged((entity, new_text)));
+    }
+}
+
+fn apply_command(
+    editor: &mut CosmicEditor,
+    command: EditCommand,
+    font_system: &mut CosmicFontSystem,
+    constraints: &InputConstraintsItem,
+    events: &mut InsertEvents,
+    channel: Option<&WasmPasteAsyncChannel>,
+) {
+    match command {
+        EditCommand::InsertText(text) => {
+            insert_text(editor, font_system, &text, constraints, events);
+        }
+        EditCommand::ReplaceSelection(text) => {
+            if editor.selection_bounds().is_some() {
+                insert_text(editor, font_system, &text, constraints, events);
+            }
+        }
+        EditCommand::SetText(text) => {
+            select_all(editor, font_system);
+            editor.delete_selection();
+            insert_text(editor, font_system, &text, constraints, events);
+        }
+        EditCommand::DeleteRange { start, end } => {
+            if is_valid_cursor(editor, start) && is_valid_cursor(editor, end) {
+                let (start, end) = match (start.line, start.index) <= (end.line, end.index) {
+                    true => (start, end),
+                    false => (end, start),
+                };
+                editor.set_selection(Selection::None);
+                editor.delete_range(start, end);
+                editor.set_cursor(start);
+            } else {
+                warn!(
+                    message = "Invalid range in EditCommand::DeleteRange",
+                    ?start,
+                    ?end
+                );
+            }
+        }
+        EditCommand::Backspace => editor.action(&mut font_system.0, Action::Backspace),
+        EditCommand::Delete => editor.action(&mut font_system.0, Action::Delete),
+        EditCommand::SetCursor(cursor) => {
+            if is_valid_cursor(editor, cursor) {
+                editor.set_cursor(cursor);
+            } else {
+                warn!(
+                    message = "Invalid cursor in EditCommand::SetCursor",
+                    ?cursor
+                );
+            }
+        }
+        EditCommand::SetSelection(selection) => {
+            let valid = match selection {
+                Selection::None => true,
+                Selection::Normal(c) | Selection::Line(c) | Selection::Word(c) => {
+                    is_valid_cursor(editor, c)
+                }
+            };
+            if valid {
+                editor.set_selection(selection);
+            } else {
+                warn!(
+                    message = "Invalid selection in EditCommand::SetSelection",
+                    ?selection
+                );
+            }
+        }
+        EditCommand::MoveCursor(motion) => {
+            editor.action(&mut font_system.0, Action::Motion(motion));
+            editor.set_selection(Selection::None);
+        }
+        EditCommand::ExtendSelection(motion) => {
+            if editor.selection() == Selection::None {
+                let cursor = editor.cursor();
+                editor.set_selection(Selection::Normal(cursor));
+            }
+            editor.action(&mut font_system.0, Action::Motion(motion));
+        }
+        EditCommand::SelectAll => select_all(editor, font_system),
+        EditCommand::Copy => {
+            if let Some(text) = editor.copy_selection() {
+                clipboard::write_clipboard(text);
+            }
+        }
+        EditCommand::Cut => {
+            if let Some(text) = editor.copy_selection() {
+                clipboard::write_clipboard(text);
+                editor.delete_selection();
+            }
+        }
+        EditCommand::Paste => {
+            clipboard::paste(editor, font_system, constraints, events, channel);
+        }
+        EditCommand::Undo | EditCommand::Redo => unreachable!("handled by apply_edit_commands"),
+    }
+}
+
+fn select_all(editor: &mut CosmicEditor, font_system: &mut CosmicFontSystem) {
+    editor.action(&mut font_system.0, Action::Motion(Motion::BufferEnd));
+    let current_cursor = editor.cursor();
+    editor.set_selection(Selection::Normal(Cursor {
+        line: 0,
+        index: 0,
+        affinity: current_cursor.affinity,
+    }));
+}
+
+fn buffer_end(editor: &CosmicEditor) -> Cursor {
+    editor.with_buffer(|b| {
+        let line = b.lines.len().saturating_sub(1);
+        Cursor::new(line, b.lines.get(line).map_or(0, |l| l.text().len()))
+    })
+}
+
+fn is_valid_cursor(editor: &CosmicEditor, cursor: Cursor) -> bool {
+    editor.with_buffer(|b| {
+        b.lines
+            .get(cursor.line)
+            .is_some_and(|l| l.text().is_char_boundary(cursor.index))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use cosmic_text::Metrics;
+
+    use super::*;
+    use crate::{
+        input::filter::CosmicTextRejected, primary::create_cosmic_font_system, LimitReached,
+    };
+
+    /// A world with a focused and an unfocused widget, returned in that order
+    fn setup() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let mut buffer = || CosmicEditBuffer::new(&mut font_system, Metrics::new(14., 18.));
+        world.init_resource::<Assets<Image>>();
+        let focused = buffer();
+        let editor = CosmicEditor::clone_from_buffer(&focused);
+        let focused = world.spawn((focused, editor)).id();
+        let unfocused = world.spawn(buffer()).id();
+
+        world.insert_resource(CosmicFontSystem(font_system));
+        world.insert_resource(FocusedWidget(Some(focused)));
+        world.init_resource::<Time>();
+        world.init_resource::<EditCommandQueue>();
+        world.init_resource::<Events<CosmicTextChanged>>();
+        world.init_resource::<Events<CosmicTextRejected>>();
+        world.init_resource::<Events<LimitReached>>();
+        world.add_observer(queue_edit_command);
+        world.flush();
+        (world, focused, unfocused)
+    }
+
+    fn changes(world: &World) -> Vec<(Entity, String)> {
+        let events = world.resource::<Events<CosmicTextChanged>>();
+        events
+            .iter_current_update_events()
+            .map(|CosmicTextChanged(change)| change.clone())
+            .collect()
+    }
+
+    #[test]
+    fn commands_are_applied_in_order_on_their_target() {
+        let (mut world, focused, unfocused) = setup();
+        world.trigger(EditCommand::InsertText("ab".into()));
+        world.trigger_targets(EditCommand::InsertText("x".into()), unfocused);
+        world.trigger(EditCommand::Backspace);
+        world.trigger_targets(EditCommand::InsertText("y".into()), unfocused);
+        world.trigger_targets(EditCommand::InsertText("c".into()), focused);
+        world.run_system_once(apply_edit_commands).unwrap();
+
+        assert_eq!(
+            changes(&world),
+            [
+                (focused, "ab".into()),
+                (unfocused, "x".into()),
+                (focused, "a".into()),
+                (unfocused, "xy".into()),
+                (focused, "ac".into()),
+            ]
+        );
+        assert_eq!(world.get::<CosmicEditor>(focused).unwrap().get_text(), "ac");
+        assert_eq!(
+            world.get::<CosmicEditBuffer>(unfocused).unwrap().get_text(),
+            "xy"
+        );
+        // the focused widget's buffer is only updated once it loses focus
+        assert_eq!(
+            world.get::<CosmicEditBuffer>(focused).unwrap().get_text(),
+            ""
+        );
+        assert!(world.resource::<EditCommandQueue>().0.is_empty());
+    }
+
+    #[test]
+    fn untargeted_commands_need_a_focused_widget() {
+        let (mut world, focused, unfocused) = setup();
+        world.resource_mut::<FocusedWidget>().0 = None;
+        world.trigger(EditCommand::InsertText("lost".into()));
+        world.trigger_targets(EditCommand::SetText("kept".into()), unfocused);
+        world.run_system_once(apply_edit_commands).unwrap();
+
+        assert_eq!(changes(&world), [(unfocused, "kept".into())]);
+        assert_eq!(world.get::<CosmicEditor>(focused).unwrap().get_text(), "");
+    }
+}
@@ -0,0 +1,334 @@
+//! The edits made to the text of each widget, shared by the [`UndoHistory`] and
+//! [`TextDecorations`]
+//!
+//! Edits made through a [`CosmicEditor`] are recorded by cosmic_text as they happen
+//! (see [`Edit::start_change`]), and are replayed once a frame onto the last known
+//! text of the widget to find their byte offsets. Any other change, e.g.
+//! [`CosmicEditBuffer::set_text`], is found by comparing the text against the last
+//! known one, which is only done when the buffer was marked for a redraw.
+//!
+//! [`UndoHistory`]: crate::undo::UndoHistory
+//! [`TextDecorations`]: crate::decorations::TextDecorations
+
+use cosmic_text::{ChangeItem, Cursor, Edit};
+
+use crate::{password::PasswordSet, placeholder::Placeholder, prelude::*, render::RenderSet};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.add_systems(
+        PostUpdate,
+        collect_edits
+            .in_set(EditLogSet)
+            .before(PasswordSet)
+            .before(RenderSet),
+    )
+    .add_systems(Last, clear_edits);
+}
+
+/// System set for collecting the edits of the frame into each [`EditLog`].
+/// Runs in [`PostUpdate`], readers of the log run after it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct EditLogSet;
+
+/// A single edit of a widget's text.
+///
+/// Offsets are byte offsets into the text of the whole buffer,
+/// with lines joined by `\n` (see [`BufferRefExtras::get_text`](crate::BufferRefExtras::get_text)).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LoggedEdit {
+    pub offset: usize,
+    pub removed: String,
+    pub inserted: String,
+    /// End of the inserted text, where the edit left the cursor
+    pub cursor_after: Cursor,
+    /// Whether the [`UndoHistory`](crate::undo::UndoHistory) already has this edit,
+    /// e.g. because it was made by undoing
+    pub in_history: bool,
+}
+
+/// The edits made to a widget's text this frame, cleared in [`Last`]
+#[derive(Component, Default, Debug)]
+pub(crate) struct EditLog {
+    /// Last known text, `None` until the widget was first seen.
+    /// Empty while a [`Placeholder`] is shown
+    snapshot: Option<String>,
+    edits: Vec<LoggedEdit>,
+    /// Changes taken from the editor by [`untracked`], not recorded yet
+    pending: Vec<ChangeItem>,
+}
+
+impl EditLog {
+    /// The text as of the last edit
+    pub fn text(&self) -> Option<&str> {
+        self.snapshot.as_deref()
+    }
+
+    pub fn edits(&self) -> &[LoggedEdit] {
+        &self.edits
+    }
+
+    pub fn edits_mut(&mut self) -> &mut [LoggedEdit] {
+        &mut self.edits
+    }
+
+    /// Forgets the edits, keeping the text
+    pub fn clear(&mut self) {
+        self.edits.clear();
+    }
+
+    /// Logs `changes` recorded by a [`CosmicEditor`] (see [`take_changes`]),
+    /// and whatever else turned the last known text into `text`
+    pub fn record(&mut self, mut changes: Vec<ChangeItem>, text: String, in_history: bool) {
+        if !self.pending.is_empty() {
+            changes.splice(0..0, self.pending.drain(..));
+        }
+        let Some(old) = self.snapshot.take() else {
+            self.snapshot = Some(text);
+            return;
+        };
+
+        let replayed = match changes.is_empty() {
+            true => old,
+            false => {
+                let logged = self.edits.len();
+                let mut replayed = old.clone();
+                let replayed_all = changes
+                    .iter()
+                    .all(|change| self.replay(&mut replayed, change, in_history));
+                match replayed_all {
+                    true => replayed,
+                    // e.g. made to a placeholder or password glyphs, compare the texts instead
+                    false => {
+                        self.edits.truncate(logged);
+                        old
+                    }
+                }
+            }
+        };
+        if let Some((offset, removed, inserted)) = diff(&replayed, &text) {
+            let cursor_after = offset_to_cursor(&text, offset + inserted.len());
+            self.push(LoggedEdit {
+                offset,
+                removed,
+                inserted,
+                cursor_after,
+                in_history,
+            });
+        }
+        self.snapshot = Some(text);
+    }
+
+    /// Applies `change` to `text`, logging it. Returns false if `change` doesn't fit `text`
+    fn replay(&mut self, text: &mut String, change: &ChangeItem, in_history: bool) -> bool {
+        let Some(offset) = cursor_to_offset(text, change.start) else {
+            return false;
+        };
+        let (removed, inserted) = match change.insert {
+            true => {
+                text.insert_str(offset, &change.text);
+                (String::new(), change.text.clone())
+            }
+            false => {
+                let end = offset + change.text.len();
+                if text.get(offset..end) != Some(change.text.as_str()) {
+                    return false;
+                }
+                text.replace_range(offset..end, "");
+                (change.text.clone(), String::new())
+            }
+        };
+        let cursor_after = offset_to_cursor(text, offset + inserted.len());
+        self.push(LoggedEdit {
+            offset,
+            removed,
+            inserted,
+            cursor_after,
+            in_history,
+        });
+        true
+    }
+
+    fn push(&mut self, edit: LoggedEdit) {
+        // typing over a selection deletes it and then inserts at the same place
+        if let Some(last) = self.edits.last_mut().filter(|last| {
+            last.inserted.is_empty()
+                && edit.removed.is_empty()
+                && last.offset == edit.offset
+                && last.in_history == edit.in_history
+        }) {
+            last.inserted = edit.inserted;
+            last.cursor_after = edit.cursor_after;
+            return;
+        }
+        self.edits.push(edit);
+    }
+}
+
+/// The edits recorded by `editor` since the last call, restarting the recording
+pub(crate) fn take_changes(editor: &mut CosmicEditor) -> Vec<ChangeItem> {
+    let changes = editor.finish_change();
+    editor.start_change();
+    changes.map(|change| change.items).unwrap_or_default()
+}
+
+/// Runs `f` without recording its edits, for text that is only in the buffer
+/// while rendering (e.g. an IME preedit).
+///
+/// Edits made before are kept for the next [`EditLog::record`]
+pub(crate) fn untracked<T>(
+    editor: &mut CosmicEditor,
+    log: &mut EditLog,
+    f: impl FnOnce(&mut CosmicEditor) -> T,
+) -> T {
+    log.pending.extend(
+        editor
+            .finish_change()
+            .map(|change| change.items)
+            .into_iter()
+            .flatten(),
+    );
+    let result = f(editor);
+    editor.start_change();
+    result
+}
+
+pub(crate) fn collect_edits(mut q: Query<(&mut EditLog, EditorBuffer, Option<&Placeholder>)>) {
+    for (mut log, mut buffer, placeholder) in q.iter_mut() {
+        let changes = buffer.editor().map(take_changes).unwrap_or_default();
+        // the text can only have changed if the buffer needs a redraw
+        if changes.is_empty()
+            && log.pending.is_empty()
+            && !buffer.redraw()
+            && log.snapshot.is_some()
+        {
+            continue;
+        }
+        let text = match placeholder {
+            Some(placeholder) if placeholder.is_active() => String::new(),
+            _ => buffer.get_text(),
+        };
+        log.record(changes, text, false);
+    }
+}
+
+pub(crate) fn clear_edits(mut q: Query<&mut EditLog>) {
+    for mut log in q.iter_mut() {
+        if !log.edits.is_empty() {
+            log.clear();
+        }
+    }
+}
+
+/// Finds the single changed region between two strings,
+/// returning `(offset, removed, inserted)`
+pub(crate) fn diff(old: &str, new: &str) -> Option<(usize, String, String)> {
+    if old == new {
+        return None;
+    }
+
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+
+    Some((
+        prefix,
+        old[prefix..old.len() - suffix].to_string(),
+        new[prefix..new.len() - suffix].to_string(),
+    ))
+}
+
+/// Converts a byte offset into text joined by `\n` into a buffer [`Cursor`]
+pub(crate) fn offset_to_cursor(text: &str, offset: usize) -> Cursor {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let index = before.rfind('\n').map_or(offset, |i| offset - i - 1);
+    Cursor::new(line, index)
+}
+
+/// Converts a buffer [`Cursor`] into a byte offset into text joined by `\n`,
+/// or `None` if it's not within `text`
+pub(crate) fn cursor_to_offset(text: &str, cursor: Cursor) -> Option<usize> {
+    let line_start = match cursor.line {
+        0 => 0,
+        line => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let line_end = text[line_start..]
+        .find('\n')
+        .map_or(text.len(), |i| line_start + i);
+    let offset = line_start + cursor.index;
+    (offset <= line_end && text.is_char_boundary(offset)).then_some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(start: Cursor, text: &str, insert: bool) -> ChangeItem {
+        ChangeItem {
+            start,
+            end: start,
+            text: text.into(),
+            insert,
+        }
+    }
+
+    #[test]
+    fn edits_of_one_frame_are_kept_apart() {
+        let mut log = EditLog::default();
+        log.record(Vec::new(), "one\ntwo three".into(), false);
+
+        // typing at both ends of the text in the same frame
+        let changes = vec![
+            change(Cursor::new(0, 0), "zero ", true),
+            change(Cursor::new(1, 3), " and a half", true),
+        ];
+        log.record(changes, "zero one\ntwo and a half three".into(), false);
+        let edits: Vec<_> = log
+            .edits()
+            .iter()
+            .map(|e| (e.offset, &*e.inserted))
+            .collect();
+        assert_eq!(edits, [(0, "zero "), (12, " and a half")]);
+        assert_eq!(log.edits()[1].cursor_after, Cursor::new(1, 14));
+    }
+
+    #[test]
+    fn typing_over_a_selection_is_one_edit() {
+        let mut log = EditLog::default();
+        log.record(Vec::new(), "one two".into(), false);
+        let changes = vec![
+            change(Cursor::new(0, 4), "two", false),
+            change(Cursor::new(0, 4), "2", true),
+        ];
+        log.record(changes, "one 2".into(), false);
+        assert_eq!(log.edits().len(), 1);
+        assert_eq!(log.edits()[0].removed, "two");
+        assert_eq!(log.edits()[0].inserted, "2");
+    }
+
+    #[test]
+    fn unrecorded_changes_are_diffed() {
+        let mut log = EditLog::default();
+        log.record(Vec::new(), "hello".into(), false);
+        // e.g. `CosmicEditBuffer::set_text`, or changes that don't fit the text
+        log.record(
+            vec![change(Cursor::new(3, 0), "?", true)],
+            "help".into(),
+            false,
+        );
+        assert_eq!(log.edits().len(), 1);
+        assert_eq!(log.edits()[0].offset, 3);
+        assert_eq!(log.edits()[0].removed, "lo");
+        assert_eq!(log.edits()[0].inserted, "p");
+    }
+}
@@ -9,7 +9,7 @@ use cosmic_text::{Attrs, BufferRef, FontSystem, Shaping};
 use crate::prelude::*;
 
 pub(crate) fn plugin(app: &mut App) {
-    app.add_systems(First, (buffer::set_initial_scale, buffer::add_font_system))
+    app.add_systems(First, buffer::add_font_system)
         .add_systems(Update, editor::blink_cursor);
 }
 
@@ -49,6 +49,12 @@ impl BufferRefExtras for Buffer {
     }
 }
 
+impl BufferRefExtras for CosmicEditBuffer {
+    fn get_text(&self) -> String {
+        self.0.get_text()
+    }
+}
+
 impl BufferMutExtras for BorrowedWithFontSystem<'_, Buffer> {
     fn height(&mut self) -> f32 {
         self.compute_everything();
@@ -70,7 +76,13 @@ impl BufferMutExtras for BorrowedWithFontSystem<'_, Buffer> {
         let last_line_num = self.lines.len() - 1;
         let last_line_width = self.lines[last_line_num].text().len();
         let end_cursor = cosmic_text::Cursor::new(last_line_num, last_line_width);
+        // shaping until a cursor scrolls to it, which isn't wanted here,
+        // and scrolling back would mark an unchanged buffer for a redraw
+        let scroll = self.scroll();
+        let redraw = self.redraw();
         self.shape_until_cursor(end_cursor, false);
+        self.set_scroll(scroll);
+        self.set_redraw(redraw);
     }
 }
 
@@ -106,7 +118,11 @@ impl BufferMutExtras for BorrowedWithFontSystem<'_, cosmic_text::Editor<'_>> {
     MaxChars,
     CosmicWrap,
     CosmicTextAlign,
+    crate::undo::UndoHistory,
+    crate::edit_log::EditLog,
     crate::input::hover::HoverCursor,
+    crate::input::ime::ImePreedit,
+    crate::input::scroll::CosmicScroll,
     crate::input::InputState
 )]
 pub struct CosmicEditBuffer(pub(super) Buffer);
@@ -271,19 +287,3 @@ pub(in crate::editor_buffer) fn add_font_system(
         }
     }
 }
-
-/// Initialises [`CosmicEditBuffer`] scale factor
-pub(in crate::editor_buffer) fn set_initial_scale(
-    window_q: Query<&Window, With<bevy::window::PrimaryWindow>>,
-    mut cosmic_query: Query<&mut CosmicEditBuffer, Added<CosmicEditBuffer>>,
-    mut font_system: ResMut<CosmicFontSystem>,
-) {
-    if let Ok(window) = window_q.get_single() {
-        let w_scale = window.scale_factor();
-
-        for mut b in &mut cosmic_query.iter_mut() {
-            let m = b.0.metrics().scale(w_scale);
-            b.0.set_metrics(&mut font_system, m);
-        }
-    }
-}
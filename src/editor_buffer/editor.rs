@@ -22,9 +22,8 @@ pub(super) fn blink_cursor(mut q: Query<&mut CosmicEditor, Without<ReadOnly>>, t
     for mut e in q.iter_mut() {
         e.cursor_timer.tick(time.delta());
         if e.cursor_timer.just_finished() {
+            // renderers pick this up without the text being marked for a redraw
             e.cursor_visible = !e.cursor_visible;
-            // trace!("Toggling cursor");
-            e.set_redraw(true);
         }
     }
 }
@@ -46,6 +45,8 @@ impl CosmicEditor {
         cursor_timer.tick(duration - Duration::from_millis(80));
 
         editor.set_redraw(true);
+        // records edits for `crate::edit_log`
+        editor.start_change();
 
         Self {
             editor,
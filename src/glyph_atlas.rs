@@ -0,0 +1,638 @@
+//! Drawing text from a glyph atlas on the GPU
+//!
+//! By default the text of a widget is blended into its image on the CPU, pixel by pixel,
+//! every frame. Add [`GlyphAtlasRendering`] to a [`TextEdit`] or [`TextEdit2d`] to instead
+//! draw each glyph as a quad sampling a texture atlas shared by all widgets, as bevy_text does.
+//! Glyphs are rasterized into the atlas once, the caret, selection and scrollbars are drawn
+//! as plain quads, and the widget's own image only holds its background, which is redrawn
+//! when it changes.
+//!
+//! The quads are child entities of the widget. A UI widget with children isn't measured by
+//! its image, so size it with its [`Node`] rather than [`AutoSize`](crate::auto_size::AutoSize).
+//! `TextEdit3d` widgets and [`CosmicRenderTarget`](crate::render_implementations::CosmicRenderTarget)s
+//! keep being drawn on the CPU.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::glyph_atlas::GlyphAtlasRendering;
+//!
+//! # fn setup(mut commands: Commands) {
+//! commands.spawn((
+//!     TextEdit2d,
+//!     CosmicEditBuffer::default(),
+//!     Sprite {
+//!         custom_size: Some(Vec2::new(300., 40.)),
+//!         ..default()
+//!     },
+//!     GlyphAtlasRendering,
+//! ));
+//! # }
+//! ```
+
+use bevy::{
+    ecs::{component::ComponentId, world::DeferredWorld},
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    text::{FontAtlas, FontSmoothing},
+    utils::HashMap,
+};
+use cosmic_text::{CacheKey, FontSystem, SwashContent};
+use render_implementations::{CosmicWidgetSize, Quad, QuadEntities, QuadTarget, WidgetQuads};
+
+use crate::{
+    background::{Background, BackgroundKey, FittedImages},
+    cosmic_edit::*,
+    decorations::TextDecorations,
+    input::{ime::ImePreedit, scroll::MeasuredScroll},
+    overlay::{cursor_and_selection, is_selected, layer, to_bevy},
+    prelude::*,
+    render::{
+        layout_text, modified_images, physical_rect, preedit_underlines, write_pixels,
+        RenderInputs, RenderSet, RenderedInputs, Renderer, TextColors,
+    },
+    scrollbar::{ScrollbarState, Scrollbars},
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.register_type::<GlyphAtlasRendering>()
+        .init_resource::<GlyphAtlas>()
+        .add_systems(PostUpdate, draw_glyph_quads.in_set(RenderSet));
+}
+
+/// Draws a widget's text from a glyph atlas, see the [module docs](self)
+#[derive(Component, Reflect, Default, Debug, Clone, Copy)]
+#[require(WidgetQuads, DrawnBackground, DrawnQuads)]
+#[component(on_remove = remove_quads)]
+pub struct GlyphAtlasRendering;
+
+/// Hands the widget back to the CPU renderer
+fn remove_quads(mut world: DeferredWorld, entity: Entity, _: ComponentId) {
+    let Some(mut quads) = world
+        .get_mut::<WidgetQuads>(entity)
+        .map(|mut quads| std::mem::take(&mut *quads))
+    else {
+        return;
+    };
+    quads.clear(&mut world.commands());
+    if let Some(mut background) = world.get_mut::<DrawnBackground>(entity) {
+        background.0 = None;
+    }
+    if let Some(mut drawn) = world.get_mut::<DrawnQuads>(entity) {
+        *drawn = DrawnQuads::default();
+    }
+}
+
+/// What the background in a widget's image was drawn with, and at which size
+#[derive(Component, Default, Debug)]
+struct DrawnBackground(Option<(UVec2, BackgroundKey)>);
+
+/// The quads last built for a widget, with its caret apart from the rest
+/// so that it blinks without the glyph quads being built again
+#[derive(Component, Default, Debug)]
+struct DrawnQuads {
+    quads: Vec<Quad>,
+    caret: Option<Quad>,
+    caret_shown: bool,
+}
+
+impl DrawnQuads {
+    fn with_caret(&self, caret_visible: bool) -> Vec<Quad> {
+        let caret = self.caret.iter().filter(|_| caret_visible);
+        self.quads.iter().chain(caret).cloned().collect()
+    }
+}
+
+/// A glyph in the [`GlyphAtlas`]
+#[derive(Debug, Clone)]
+struct AtlasGlyph {
+    image: Handle<Image>,
+    /// Part of `image` holding the glyph
+    part: URect,
+    /// From the glyph's origin to the top left of its image, with y up
+    offset: IVec2,
+    /// Emoji keep their own colours, rather than being drawn in the text colour
+    is_color: bool,
+}
+
+/// The rasterized glyphs of all widgets
+#[derive(Resource, Default)]
+struct GlyphAtlas {
+    pages: Vec<FontAtlas>,
+    /// `None` for glyphs without any pixels, e.g. spaces
+    glyphs: HashMap<CacheKey, Option<AtlasGlyph>>,
+}
+
+/// Size of a new page, unless a glyph doesn't fit
+const PAGE_SIZE: u32 = 512;
+
+impl GlyphAtlas {
+    /// Finds a glyph, rasterizing it into the atlas the first time it is drawn
+    fn glyph(
+        &mut self,
+        cache_key: CacheKey,
+        font_system: &mut FontSystem,
+        swash_cache: &mut cosmic_text::SwashCache,
+        images: &mut Assets<Image>,
+        layouts: &mut Assets<TextureAtlasLayout>,
+    ) -> Option<&AtlasGlyph> {
+        if !self.glyphs.contains_key(&cache_key) {
+            let glyph = self.add(cache_key, font_system, swash_cache, images, layouts);
+            self.glyphs.insert(cache_key, glyph);
+        }
+        self.glyphs.get(&cache_key)?.as_ref()
+    }
+
+    fn add(
+        &mut self,
+        cache_key: CacheKey,
+        font_system: &mut FontSystem,
+        swash_cache: &mut cosmic_text::SwashCache,
+        images: &mut Assets<Image>,
+        layouts: &mut Assets<TextureAtlasLayout>,
+    ) -> Option<AtlasGlyph> {
+        let (image, offset, is_color) = rasterize(font_system, swash_cache, cache_key)?;
+        let page = match self.pages.iter_mut().position(|page| {
+            page.add_glyph(images, layouts, cache_key, &image, offset)
+                .is_ok()
+        }) {
+            Some(page) => page,
+            None => {
+                let longest_side = image.width().max(image.height());
+                let size = longest_side.next_power_of_two().max(PAGE_SIZE);
+                let mut page = FontAtlas::new(
+                    images,
+                    layouts,
+                    UVec2::splat(size),
+                    FontSmoothing::AntiAliased,
+                );
+                page.add_glyph(images, layouts, cache_key, &image, offset)
+                    .ok()?;
+                self.pages.push(page);
+                self.pages.len() - 1
+            }
+        };
+        let page = &self.pages[page];
+        let location = page.get_glyph_index(cache_key)?;
+        let part = *layouts
+            .get(&page.texture_atlas)?
+            .textures
+            .get(location.glyph_index)?;
+        Some(AtlasGlyph {
+            image: page.texture.clone_weak(),
+            part,
+            offset: location.offset,
+            is_color,
+        })
+    }
+}
+
+/// An RGBA image of a glyph, where the text colour is applied by tinting it,
+/// its offset and whether it has colours of its own
+fn rasterize(
+    font_system: &mut FontSystem,
+    swash_cache: &mut cosmic_text::SwashCache,
+    cache_key: CacheKey,
+) -> Option<(Image, IVec2, bool)> {
+    let image = swash_cache.get_image_uncached(font_system, cache_key)?;
+    let placement = image.placement;
+    if placement.width == 0 || placement.height == 0 {
+        return None;
+    }
+    let (data, is_color): (Vec<u8>, bool) = match image.content {
+        SwashContent::Mask => (
+            image
+                .data
+                .iter()
+                .flat_map(|alpha| [255, 255, 255, *alpha])
+                .collect(),
+            false,
+        ),
+        SwashContent::Color => (image.data, true),
+        SwashContent::SubpixelMask => (
+            image
+                .data
+                .chunks_exact(4)
+                .flat_map(|rgba| {
+                    let coverage = (rgba[0] as u16 + rgba[1] as u16 + rgba[2] as u16) / 3;
+                    [255, 255, 255, coverage as u8]
+                })
+                .collect(),
+            false,
+        ),
+    };
+    let image = Image::new(
+        Extent3d {
+            width: placement.width,
+            height: placement.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD,
+    );
+    Some((image, IVec2::new(placement.left, placement.top), is_color))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_glyph_quads(
+    mut q: Query<
+        (
+            EditorBuffer,
+            &DefaultAttrs,
+            Background,
+            (&CursorColor, &SelectionColor, Option<&SelectedTextColor>),
+            &CosmicRenderOutput,
+            CosmicWidgetSize,
+            Option<&ReadOnly>,
+            (&CosmicTextAlign, &CosmicWrap),
+            (Option<&ImePreedit>, Option<&TextDecorations>),
+            Option<(Ref<Scrollbars>, &mut ScrollbarState, &mut MeasuredScroll)>,
+            QuadTarget,
+            (&mut DrawnBackground, &mut DrawnQuads, &mut RenderedInputs),
+        ),
+        With<GlyphAtlasRendering>,
+    >,
+    mut quad_entities: QuadEntities,
+    mut atlas: ResMut<GlyphAtlas>,
+    mut font_system: ResMut<CosmicFontSystem>,
+    mut images: ResMut<Assets<Image>>,
+    mut fitted_images: ResMut<FittedImages>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+    mut swash_cache_state: ResMut<SwashCache>,
+    time: Res<Time<Real>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    #[cfg(feature = "internal-debugging")] mut redraws: ResMut<crate::debug::RedrawCounter>,
+) {
+    let modified_images = modified_images(&mut image_events);
+    for (
+        mut editor,
+        attrs,
+        background,
+        (cursor_color, selection_color, selected_text_color),
+        canvas,
+        size,
+        readonly,
+        (text_align, wrap),
+        (preedit, decorations),
+        mut scrollbars,
+        mut target,
+        (mut drawn_background, mut drawn, mut rendered),
+    ) in q.iter_mut()
+    {
+        if !target.supports_quads() {
+            continue;
+        }
+        let font_system = &mut font_system.0;
+        // a widget that can't be drawn doesn't stop the others from being drawn
+        let sizes: render_implementations::Result<_> = (|| {
+            Ok((
+                size.logical_size()?,
+                size.content_rect()?,
+                size.physical_size()?,
+            ))
+        })();
+        let (render_target_size, content_rect, image_size) = match sizes {
+            Ok(sizes) => sizes,
+            Err(err) => {
+                debug!(message = "Skipping widget that can't be drawn", ?err);
+                continue;
+            }
+        };
+        let scale = size.scale_factor();
+        if image_size.x == 0 || image_size.y == 0 {
+            continue;
+        }
+
+        let background_key = (image_size, background.key(&images));
+        if drawn_background.0.as_ref() != Some(&background_key)
+            || background.image_modified(&modified_images)
+        {
+            let pixels = background.draw(image_size, scale, &images, &mut fitted_images);
+            write_pixels(&mut images, &canvas.0, &pixels, image_size.as_vec2());
+            drawn_background.0 = Some(background_key);
+        }
+
+        let font_color = attrs
+            .0
+            .color_opt
+            .unwrap_or(cosmic_text::Color::rgb(0, 0, 0));
+        let transformation = layout_text(
+            &mut editor,
+            font_system,
+            content_rect,
+            scale,
+            text_align,
+            wrap,
+        );
+
+        // the caret is built even while it blinks off, see `DrawnQuads`
+        let caret_visible = editor.editor().is_some_and(|editor| editor.cursor_visible);
+        let (colors, selection, cursor) = match editor.editor() {
+            Some(editor) => {
+                let colors = TextColors::new(
+                    font_color,
+                    cursor_color,
+                    selection_color,
+                    selected_text_color,
+                    readonly.is_none(),
+                );
+                editor.borrow_with(font_system).shape_as_needed(false);
+                (colors, editor.selection_bounds(), editor.cursor_position())
+            }
+            None => {
+                editor.shape_until_scroll(font_system, false);
+                let colors = TextColors::new(
+                    font_color,
+                    cursor_color,
+                    selection_color,
+                    selected_text_color,
+                    false,
+                );
+                (colors, None, None)
+            }
+        };
+
+        let scrollbars_changed = match scrollbars {
+            Some((ref scrollbars, ref mut state, ref mut measured)) => {
+                let metrics = editor
+                    .borrow_with(font_system)
+                    .with_buffer_mut(|b| measured.get(b));
+                let moved = state.update(
+                    scrollbars,
+                    metrics,
+                    render_target_size,
+                    content_rect.size() * scale,
+                    time.elapsed(),
+                );
+                moved || scrollbars.is_changed()
+            }
+            None => false,
+        };
+        let inputs_changed = rendered.update(RenderInputs::new(
+            render_target_size,
+            scale,
+            content_rect,
+            (text_align, wrap),
+            background.key(&images),
+            colors,
+            editor.editor().is_some(),
+            preedit,
+            Renderer::GlyphAtlas,
+        ));
+        if !(inputs_changed || scrollbars_changed || editor.redraw()) {
+            // e.g. a sprite's anchor changed
+            if target.placement_changed() || drawn.caret_shown != caret_visible {
+                let quads = drawn.with_caret(caret_visible);
+                if let Err(err) = target.draw(quads, render_target_size, scale, &mut quad_entities)
+                {
+                    debug!(message = "Error in render target", ?err);
+                    continue;
+                }
+                drawn.caret_shown = caret_visible;
+            }
+            continue;
+        }
+
+        let clip = physical_rect(content_rect, scale);
+        let mut quads = Vec::new();
+        let mut push = |rect: IRect, color: Color, texture, layer| {
+            let min = transformation
+                .buffer_to_physical(rect.min.as_vec2())
+                .as_ivec2();
+            let quad = Quad {
+                rect: IRect::from_corners(min, min + rect.size()),
+                color,
+                texture,
+                layer,
+            };
+            quads.extend(quad.clipped(clip));
+        };
+
+        let buffer: &Buffer = &editor;
+        if let Some(decorations) = decorations {
+            for run in buffer.layout_runs() {
+                decorations.highlight_rects(buffer, &run, |rect, color| {
+                    push(rect, to_bevy(color), None, layer::HIGHLIGHTS)
+                });
+                decorations.line_rects(&run, |rect, color| {
+                    push(rect, to_bevy(color), None, layer::DECORATIONS)
+                });
+            }
+        }
+        cursor_and_selection(buffer, selection, cursor, &colors, |rect, color, layer| {
+            push(rect, color, None, layer)
+        });
+        for run in buffer.layout_runs() {
+            for glyph in run.glyphs.iter() {
+                let physical = glyph.physical((0., 0.), 1.0);
+                let Some(atlas_glyph) = atlas.glyph(
+                    physical.cache_key,
+                    font_system,
+                    &mut swash_cache_state.0,
+                    &mut images,
+                    &mut layouts,
+                ) else {
+                    continue;
+                };
+                let mut color = glyph.color_opt.unwrap_or(colors.font);
+                if colors.font != colors.selected_text
+                    && selection.is_some_and(|selection| is_selected(run.line_i, glyph, selection))
+                {
+                    color = colors.selected_text;
+                }
+                let min = IVec2::new(
+                    physical.x + atlas_glyph.offset.x,
+                    run.line_y as i32 + physical.y - atlas_glyph.offset.y,
+                );
+                push(
+                    IRect::from_corners(min, min + atlas_glyph.part.size().as_ivec2()),
+                    match atlas_glyph.is_color {
+                        true => Color::WHITE,
+                        false => to_bevy(color),
+                    },
+                    Some((atlas_glyph.image.clone_weak(), atlas_glyph.part)),
+                    layer::GLYPHS,
+                );
+            }
+        }
+
+        // Underline text that is still being composed with an IME
+        if let Some(preedit) = preedit {
+            preedit_underlines(buffer, preedit, |x, y, w, h| {
+                push(
+                    IRect::new(x, y, x + w as i32, y + h as i32),
+                    to_bevy(font_color),
+                    None,
+                    layer::PREEDIT,
+                )
+            });
+        }
+
+        if let Some((scrollbars, state, _)) = scrollbars {
+            let mut scrollbar_layer = layer::SCROLLBARS;
+            state.draw(&scrollbars, |rect, color| {
+                quads.push(Quad::fill(
+                    physical_rect(rect, scale),
+                    color,
+                    scrollbar_layer,
+                ));
+                scrollbar_layer += 1;
+            });
+        }
+
+        let caret = quads
+            .iter()
+            .position(|quad| quad.layer == layer::CURSOR)
+            .map(|index| quads.remove(index));
+        *drawn = DrawnQuads {
+            quads,
+            caret,
+            caret_shown: caret_visible,
+        };
+        let quads = drawn.with_caret(caret_visible);
+        if let Err(err) = target.draw(quads, render_target_size, scale, &mut quad_entities) {
+            debug!(message = "Error in render target", ?err);
+            continue;
+        }
+        editor.set_redraw(false);
+        #[cfg(feature = "internal-debugging")]
+        redraws.count();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmic_text::{Attrs, Metrics, Shaping};
+
+    use super::*;
+    use crate::{
+        background::BackgroundImageStyle, primary::create_cosmic_font_system,
+        render_implementations::WidgetQuad,
+    };
+
+    fn font_system() -> FontSystem {
+        create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        })
+    }
+
+    #[test]
+    fn glyphs_are_rasterized_once() {
+        let mut font_system = font_system();
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(20., 20.));
+        buffer.set_text(&mut font_system, "ab", Attrs::new(), Shaping::Advanced);
+        buffer.shape_until_scroll(&mut font_system, false);
+        let keys: Vec<CacheKey> = buffer
+            .layout_runs()
+            .flat_map(|run| {
+                run.glyphs
+                    .iter()
+                    .map(|g| g.physical((0., 0.), 1.).cache_key)
+            })
+            .collect();
+
+        let mut atlas = GlyphAtlas::default();
+        let mut swash_cache = cosmic_text::SwashCache::new();
+        let mut images = Assets::<Image>::default();
+        let mut layouts = Assets::<TextureAtlasLayout>::default();
+        let mut glyph = |atlas: &mut GlyphAtlas, key| {
+            atlas
+                .glyph(
+                    key,
+                    &mut font_system,
+                    &mut swash_cache,
+                    &mut images,
+                    &mut layouts,
+                )
+                .map(|glyph| (glyph.image.id(), glyph.part))
+        };
+        let a = glyph(&mut atlas, keys[0]).unwrap();
+        let b = glyph(&mut atlas, keys[1]).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(glyph(&mut atlas, keys[0]), Some(a));
+        // both share the first page
+        assert_eq!(atlas.pages.len(), 1);
+        assert_eq!(atlas.glyphs.len(), 2);
+        assert_eq!(images.len(), 1);
+    }
+
+    /// Quads whose sprite changed in the last frame
+    #[derive(Resource, Default)]
+    struct ChangedQuads(usize);
+
+    #[test]
+    fn blinking_only_touches_the_caret() {
+        let mut world = World::new();
+        let mut font_system = font_system();
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(20., 20.));
+        let mut editor = CosmicEditor::clone_from_buffer(&buffer);
+        editor.with_buffer_mut(|b| {
+            b.set_text(&mut font_system, "blink", Attrs::new(), Shaping::Advanced)
+        });
+        world.insert_resource(CosmicFontSystem(font_system));
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<Assets<TextureAtlasLayout>>();
+        world.init_resource::<Events<AssetEvent<Image>>>();
+        world.init_resource::<FittedImages>();
+        world.init_resource::<SwashCache>();
+        world.init_resource::<GlyphAtlas>();
+        world.init_resource::<Time<Real>>();
+        world.init_resource::<ChangedQuads>();
+        #[cfg(feature = "internal-debugging")]
+        world.init_resource::<crate::debug::RedrawCounter>();
+        let entity = world
+            .spawn((
+                TextEdit2d,
+                Sprite {
+                    custom_size: Some(Vec2::new(200., 40.)),
+                    ..default()
+                },
+                buffer,
+                editor,
+                GlyphAtlasRendering,
+                RenderedInputs::default(),
+                BackgroundImageStyle::default(),
+            ))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                draw_glyph_quads,
+                |q: Query<(), (With<WidgetQuad>, Changed<Sprite>)>,
+                 mut changed: ResMut<ChangedQuads>| changed.0 = q.iter().count(),
+            )
+                .chain(),
+        );
+        let mut blink = |world: &mut World, visible| {
+            world
+                .get_mut::<CosmicEditor>(entity)
+                .unwrap()
+                .cursor_visible = visible;
+            schedule.run(world);
+            world.resource::<ChangedQuads>().0
+        };
+
+        // the five glyphs
+        assert_eq!(blink(&mut world, false), 5);
+        let inputs = |world: &World| world.get::<RenderedInputs>(entity).unwrap().0.clone();
+        let drawn_with = inputs(&world);
+        assert_eq!(blink(&mut world, true), 1);
+        // the glyphs weren't built again
+        assert_eq!(inputs(&world), drawn_with);
+        // hidden rather than despawned
+        assert_eq!(blink(&mut world, false), 0);
+        assert_eq!(blink(&mut world, true), 1);
+        let quads = world
+            .query_filtered::<&Visibility, With<WidgetQuad>>()
+            .iter(&world)
+            .filter(|visibility| **visibility != Visibility::Hidden)
+            .count();
+        assert_eq!(quads, 6);
+        assert_eq!(blink(&mut world, true), 0);
+    }
+}
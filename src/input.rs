@@ -8,8 +8,11 @@ pub mod clipboard;
 pub mod cursor_icon;
 pub mod cursor_visibility;
 pub mod drag;
+pub mod filter;
 pub mod hover;
+pub mod ime;
 pub mod keyboard;
+pub mod keymap;
 pub mod scroll;
 
 /// System set for mouse and keyboard input events. Runs in [`PreUpdate`] and [`Update`]
@@ -17,11 +20,14 @@ pub mod scroll;
 pub struct InputSet;
 
 pub(crate) fn plugin(app: &mut App) {
-    app.add_systems(PreUpdate, scroll::scroll.in_set(InputSet))
+    app.add_plugins((ime::plugin, filter::plugin, scroll::plugin))
         .add_systems(
             Update,
             (
+                drag::auto_scroll_drag,
+                keymap::resolve_keymap,
                 keyboard::kb_move_cursor,
+                ime::read_ime_events,
                 keyboard::kb_input_text,
                 clipboard::kb_clipboard,
                 (
@@ -32,6 +38,11 @@ pub(crate) fn plugin(app: &mut App) {
                 .chain()
                 .in_set(InputSet),
         )
+        .init_resource::<keymap::CosmicKeymap>()
+        .init_resource::<keymap::FiredActions>()
+        .init_resource::<keymap::KeyRepeat>()
+        .register_type::<keymap::KeyRepeat>()
+        .add_event::<keymap::CosmicKeymapEvent>()
         .add_event::<hover::TextHoverIn>()
         .add_event::<hover::TextHoverOut>()
         .add_event::<CosmicTextChanged>()
@@ -62,8 +73,13 @@ pub(crate) enum InputState {
     #[default]
     Idle,
     Hovering,
+    /// Coordinates are from the top left of the widget,
+    /// see [`RelativeQueryItem::compute_widget_coord`](render_implementations::RelativeQueryItem::compute_widget_coord)
     Dragging {
-        initial_buffer_coord: Vec2,
+        /// Where the pointer is now, possibly outside of the widget
+        widget_coord: Vec2,
+        /// Camera the widget was first hit through, to follow the pointer with
+        camera: Entity,
     },
 }
 
@@ -75,7 +91,7 @@ fn add_event_handlers(
     let mut observers = [
         Observer::new(click::handle_focused_click.pipe(render_implementations::debug_error)),
         Observer::new(drag::handle_dragstart.pipe(render_implementations::debug_error)),
-        Observer::new(drag::handle_drag_continue),
+        Observer::new(drag::handle_drag_continue.pipe(render_implementations::debug_error)),
         Observer::new(drag::handle_dragend),
         Observer::new(hover::handle_hover_start),
         Observer::new(hover::handle_hover_continue),
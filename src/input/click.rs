@@ -1,6 +1,7 @@
 use crate::{
     double_click::{ClickCount, ClickState},
     prelude::*,
+    scrollbar::ScrollbarState,
 };
 
 use super::InputState;
@@ -59,7 +60,12 @@ pub fn focus_on_click(
 pub(super) fn handle_focused_click(
     trigger: Trigger<Pointer<Click>>,
     focused: Res<FocusedWidget>,
-    mut editor: Query<(&mut InputState, &mut CosmicEditor, RelativeQuery)>,
+    mut editor: Query<(
+        &mut InputState,
+        &mut CosmicEditor,
+        RelativeQuery,
+        Option<&ScrollbarState>,
+    )>,
     mut font_system: ResMut<CosmicFontSystem>,
     buttons: Res<ButtonInput<KeyCode>>,
     mut click_state: ClickState,
@@ -77,15 +83,26 @@ pub(super) fn handle_focused_click(
         return Ok(());
     }
 
-    let Ok((input_state, mut editor, buffer_relative)) = editor.get_mut(target) else {
+    let Ok((input_state, mut editor, buffer_relative, scrollbars)) = editor.get_mut(target) else {
         // this is expected on first click, idk order of observers
         // warn_no_editor_on_picking_event("handling focussed cursor `Click` event");
         return Ok(());
     };
+    if let Some(scrollbars) = scrollbars {
+        // handled by the scrollbar
+        if scrollbars.contains(buffer_relative.compute_widget_coord(&click.hit)?) {
+            return Ok(());
+        }
+    }
     let mut editor = editor.borrow_with(font_system);
     input_state.handle_click();
 
-    let buffer_coord = buffer_relative.compute_buffer_coord(&click.hit, editor.expected_size())?;
+    let horizontal_scroll = editor.with_buffer(|b| b.scroll().horizontal);
+    let buffer_coord = buffer_relative.compute_buffer_coord(
+        &click.hit,
+        editor.expected_size(),
+        horizontal_scroll,
+    )?;
 
     if !input_state.should_click() {
         return Ok(());
@@ -132,3 +149,85 @@ pub(super) fn handle_focused_click(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{
+        picking::{
+            backend::HitData,
+            pointer::{Location, PointerId},
+        },
+        render::camera::NormalizedRenderTarget,
+    };
+    use cosmic_text::{Attrs, Metrics};
+
+    use super::*;
+    use crate::{primary::tests::headless_app, CosmicTextAlign};
+
+    /// The line and index the cursor goes to after clicking a focused sprite widget 200 by 100 pixels large,
+    /// with 20 pixels of padding on every side, at `widget_coord`
+    fn click_at(widget_coord: Vec2) -> (usize, usize) {
+        let mut app = headless_app();
+        let world = app.world_mut();
+        let mut font_system = world.resource_mut::<CosmicFontSystem>();
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(20., 20.)).with_text(
+            &mut font_system,
+            "one\ntwo",
+            Attrs::new(),
+        );
+        let entity = world
+            .spawn((
+                TextEdit2d,
+                buffer,
+                CosmicTextAlign::top_left(),
+                CosmicPadding::all(20.),
+                Sprite {
+                    custom_size: Some(Vec2::new(200., 100.)),
+                    ..default()
+                },
+            ))
+            .id();
+        world.resource_mut::<FocusedWidget>().0 = Some(entity);
+        // adds the editor, then lays it out
+        app.update();
+        app.update();
+
+        // the sprite is centered on the origin, with y up
+        let world_position = Vec3::new(widget_coord.x - 100., 50. - widget_coord.y, 0.);
+        let click = Click {
+            button: PointerButton::Primary,
+            hit: HitData::new(Entity::PLACEHOLDER, 0., Some(world_position), None),
+            duration: Duration::ZERO,
+        };
+        let location = Location {
+            target: NormalizedRenderTarget::Image(default()),
+            position: Vec2::ZERO,
+        };
+        app.world_mut().trigger_targets(
+            Pointer::new(entity, PointerId::Mouse, location, click),
+            entity,
+        );
+        let cursor = app.world().get::<CosmicEditor>(entity).unwrap().cursor();
+        (cursor.line, cursor.index)
+    }
+
+    #[test]
+    fn clicks_in_the_text() {
+        // past the middle of the second character
+        assert_eq!(click_at(Vec2::new(40., 30.)), (0, 2));
+        // on the second line, past its end
+        assert_eq!(click_at(Vec2::new(150., 50.)), (1, 3));
+    }
+
+    #[test]
+    fn clicks_in_the_padding_go_to_the_closest_cursor() {
+        assert_eq!(click_at(Vec2::new(5., 5.)), (0, 0));
+        // left of the first line
+        assert_eq!(click_at(Vec2::new(5., 30.)), (0, 0));
+        // right of the first line
+        assert_eq!(click_at(Vec2::new(195., 30.)), (0, 3));
+        assert_eq!(click_at(Vec2::new(195., 95.)), (1, 3));
+    }
+}
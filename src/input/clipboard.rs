@@ -1,8 +1,15 @@
-use crate::{input::CosmicTextChanged, prelude::*, MaxChars, MaxLines};
+use crate::{
+    input::{
+        keyboard::{insert_text, InputConstraints, InputConstraintsItem, InsertEvents},
+        keymap::{EditorAction, FiredActions},
+        CosmicTextChanged,
+    },
+    prelude::*,
+};
 
 #[cfg(target_arch = "wasm32")]
 use bevy::tasks::AsyncComputeTaskPool;
-use cosmic_text::{Action, Edit};
+use cosmic_text::Edit;
 #[cfg(target_arch = "wasm32")]
 #[allow(unused_imports)]
 use js_sys::Promise;
@@ -29,107 +36,119 @@ pub(crate) struct WasmPasteAsyncChannel {
 
 pub(crate) fn kb_clipboard(
     active_editor: Res<FocusedWidget>,
-    keys: Res<ButtonInput<KeyCode>>,
+    fired: Res<FiredActions>,
     mut evw_changed: EventWriter<CosmicTextChanged>,
-    #[allow(unused_variables, unused_mut)] mut font_system: ResMut<CosmicFontSystem>,
+    mut insert_events: InsertEvents,
+    mut font_system: ResMut<CosmicFontSystem>,
     mut cosmic_edit_query: Query<(
         &mut CosmicEditor,
-        &MaxLines,
-        &MaxChars,
+        InputConstraints,
         Entity,
         Option<&ReadOnly>,
     )>,
-    _channel: Option<Res<WasmPasteAsyncChannel>>,
+    channel: Option<Res<WasmPasteAsyncChannel>>,
 ) {
     let Some(active_editor_entity) = active_editor.0 else {
         return;
     };
 
-    #[allow(unused_variables)]
-    if let Ok((mut editor, max_lines, max_chars, entity, readonly_opt)) =
+    if let Ok((mut editor, constraints, entity, readonly_opt)) =
         cosmic_edit_query.get_mut(active_editor_entity)
     {
-        let command = crate::input::keyboard::keypress_command(&keys);
-
         let readonly = readonly_opt.is_some();
 
         let mut is_clipboard = false;
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                if command && keys.just_pressed(KeyCode::KeyC) {
+        for action in fired.iter() {
+            match action {
+                EditorAction::Copy => {
                     if let Some(text) = editor.copy_selection() {
-                        clipboard.set_text(text).unwrap();
-                        return;
+                        write_clipboard(text);
                     }
                 }
-                if command && keys.just_pressed(KeyCode::KeyX) && !readonly {
+                EditorAction::Cut if !readonly => {
                     if let Some(text) = editor.copy_selection() {
-                        clipboard.set_text(text).unwrap();
+                        write_clipboard(text);
                         editor.delete_selection();
                     }
                     is_clipboard = true;
                 }
-                if command && keys.just_pressed(KeyCode::KeyV) && !readonly {
-                    if let Ok(text) = clipboard.get_text() {
-                        for c in text.chars() {
-                            if max_chars.0 == 0 || editor.get_text().len() < max_chars.0 {
-                                if c == 0xA as char {
-                                    if max_lines.0 == 0
-                                        || editor.with_buffer(|b| b.lines.len()) < max_lines.0
-                                    {
-                                        editor.action(&mut font_system.0, Action::Insert(c));
-                                    }
-                                } else {
-                                    editor.action(&mut font_system.0, Action::Insert(c));
-                                }
-                            }
-                        }
-                    }
-                    is_clipboard = true;
+                EditorAction::Paste if !readonly => {
+                    is_clipboard |= paste(
+                        &mut editor,
+                        &mut font_system,
+                        &constraints,
+                        &mut insert_events,
+                        channel.as_deref(),
+                    );
                 }
+                _ => {}
             }
         }
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            if command && keys.just_pressed(KeyCode::KeyC) {
-                if let Some(text) = editor.copy_selection() {
-                    write_clipboard_wasm(text.as_str());
-                    return;
-                }
-            }
-
-            if command && keys.just_pressed(KeyCode::KeyX) && !readonly {
-                if let Some(text) = editor.copy_selection() {
-                    write_clipboard_wasm(text.as_str());
-                    editor.delete_selection();
-                }
-                is_clipboard = true;
-            }
-            if command && keys.just_pressed(KeyCode::KeyV) && !readonly {
-                let tx = _channel.unwrap().tx.clone();
-                let _task = AsyncComputeTaskPool::get().spawn(async move {
-                    let promise = read_clipboard_wasm();
-
-                    let result = JsFuture::from(promise).await;
+        if !is_clipboard {
+            return;
+        }
 
-                    if let Ok(js_text) = result {
-                        if let Some(text) = js_text.as_string() {
-                            let _ = tx.try_send(WasmPaste { text, entity });
-                        }
-                    }
-                });
+        evw_changed.send(CosmicTextChanged((entity, editor.get_text())));
+    }
+}
 
-                return;
+/// Puts `text` onto the system clipboard
+pub(crate) fn write_clipboard(text: String) {
+    #[cfg(not(target_arch = "wasm32"))]
+    match arboard::Clipboard::new() {
+        Ok(mut clipboard) => {
+            if let Err(err) = clipboard.set_text(text) {
+                warn!(message = "Couldn't write to the clipboard", ?err);
             }
         }
+        Err(err) => warn!(message = "Couldn't access the clipboard", ?err),
+    }
 
-        if !is_clipboard {
-            return;
-        }
+    #[cfg(target_arch = "wasm32")]
+    write_clipboard_wasm(text.as_str());
+}
 
-        evw_changed.send(CosmicTextChanged((entity, editor.get_text())));
+/// Inserts the clipboard contents at the cursor, see [`insert_text`].
+///
+/// On wasm reading the clipboard is asynchronous, so this returns `false` and
+/// the text is inserted by [`poll_wasm_paste`] later on
+#[allow(unused_variables)] // which arguments are used depends on the target
+pub(crate) fn paste(
+    editor: &mut CosmicEditor,
+    font_system: &mut CosmicFontSystem,
+    constraints: &InputConstraintsItem,
+    events: &mut InsertEvents,
+    channel: Option<&WasmPasteAsyncChannel>,
+) -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let Ok(text) = arboard::Clipboard::new().and_then(|mut c| c.get_text()) else {
+            return false;
+        };
+        insert_text(editor, font_system, &text, constraints, events);
+        true
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let Some(channel) = channel else {
+            return false;
+        };
+        let tx = channel.tx.clone();
+        let entity = constraints.entity;
+        let _task = AsyncComputeTaskPool::get().spawn(async move {
+            let promise = read_clipboard_wasm();
+
+            let result = JsFuture::from(promise).await;
+
+            if let Ok(js_text) = result {
+                if let Some(text) = js_text.as_string() {
+                    let _ = tx.try_send(WasmPaste { text, entity });
+                }
+            }
+        });
+        false
     }
 }
 
@@ -150,29 +169,23 @@ pub fn read_clipboard_wasm() -> Promise {
 #[cfg(target_arch = "wasm32")]
 pub(crate) fn poll_wasm_paste(
     channel: Res<WasmPasteAsyncChannel>,
-    mut editor_q: Query<(&mut CosmicEditor, &MaxChars, &MaxChars), Without<ReadOnly>>,
+    mut editor_q: Query<(&mut CosmicEditor, InputConstraints), Without<ReadOnly>>,
     mut evw_changed: EventWriter<CosmicTextChanged>,
+    mut insert_events: InsertEvents,
     mut font_system: ResMut<CosmicFontSystem>,
 ) {
     let inlet = channel.rx.try_recv();
     match inlet {
         Ok(inlet) => {
             let entity = inlet.entity;
-            if let Ok((mut editor, max_chars, max_lines)) = editor_q.get_mut(entity) {
-                let text = inlet.text;
-                for c in text.chars() {
-                    if max_chars.0 == 0 || editor.get_text().len() < max_chars.0 {
-                        if c == 0xA as char {
-                            if max_lines.0 == 0
-                                || editor.with_buffer(|b| b.lines.len()) < max_lines.0
-                            {
-                                editor.action(&mut font_system.0, Action::Insert(c));
-                            }
-                        } else {
-                            editor.action(&mut font_system.0, Action::Insert(c));
-                        }
-                    }
-                }
+            if let Ok((mut editor, constraints)) = editor_q.get_mut(entity) {
+                insert_text(
+                    &mut editor,
+                    &mut font_system,
+                    &inlet.text,
+                    &constraints,
+                    &mut insert_events,
+                );
 
                 evw_changed.send(CosmicTextChanged((entity, editor.get_text())));
             }
@@ -1,8 +1,8 @@
-use crate::prelude::*;
+use crate::{input::scroll::CosmicScroll, prelude::*, scrollbar::ScrollbarState};
 
 use super::{warn_no_editor_on_picking_event, InputState};
 use cosmic_text::Action;
-use render_implementations::RelativeQuery;
+use render_implementations::{RelativeQuery, RelativeQueryItem};
 
 impl InputState {
     pub fn is_dragging(&self) -> bool {
@@ -10,12 +10,13 @@ impl InputState {
     }
 
     /// Handler for [`DragStart`] event
-    pub fn start_dragging(&mut self, initial_buffer_coord: Vec2) {
+    pub fn start_dragging(&mut self, widget_coord: Vec2, camera: Entity) {
         trace!("Starting a drag");
         match self {
             InputState::Idle | InputState::Hovering => {
                 *self = InputState::Dragging {
-                    initial_buffer_coord,
+                    widget_coord,
+                    camera,
                 };
             }
             InputState::Dragging { .. } => {
@@ -59,24 +60,41 @@ impl InputState {
 
 pub(super) fn handle_dragstart(
     trigger: Trigger<Pointer<DragStart>>,
-    mut editor: Query<(&mut InputState, &mut CosmicEditor, RelativeQuery), With<CosmicEditBuffer>>,
+    mut editor: Query<
+        (
+            &mut InputState,
+            &mut CosmicEditor,
+            RelativeQuery,
+            Option<&ScrollbarState>,
+        ),
+        With<CosmicEditBuffer>,
+    >,
     mut font_system: ResMut<CosmicFontSystem>,
 ) -> render_implementations::Result<()> {
     let font_system = &mut font_system.0;
     let event = trigger.event();
-    let Ok((mut input_state, mut editor, sprite_relative)) = editor.get_mut(trigger.target) else {
+    let Ok((mut input_state, mut editor, sprite_relative, scrollbars)) =
+        editor.get_mut(trigger.target)
+    else {
         warn_no_editor_on_picking_event("handling cursor `DragStart` event");
         return Ok(());
     };
+    let widget_coord = sprite_relative.compute_widget_coord(&event.hit)?;
+    if scrollbars.is_some_and(|scrollbars| scrollbars.contains(widget_coord)) {
+        // dragging a scrollbar thumb doesn't select text
+        return Ok(());
+    }
     let buffer_size = editor.with_buffer_mut(|b| b.borrow_with(font_system).expected_size());
-    let buffer_coord = sprite_relative.compute_buffer_coord(&event.hit, buffer_size)?;
+    let horizontal_scroll = editor.with_buffer(|b| b.scroll().horizontal);
+    let buffer_coord =
+        sprite_relative.widget_coord_to_buffer(widget_coord, buffer_size, horizontal_scroll)?;
     let mut editor = editor.borrow_with(font_system);
 
     if event.button != PointerButton::Primary {
         return Ok(());
     }
 
-    input_state.start_dragging(buffer_coord);
+    input_state.start_dragging(widget_coord, event.hit.camera);
 
     if input_state.is_dragging() {
         editor.action(Action::Click {
@@ -94,37 +112,102 @@ pub(super) fn handle_dragstart(
 
 pub(super) fn handle_drag_continue(
     trigger: Trigger<Pointer<Drag>>,
-    mut editor: Query<(&InputState, &mut CosmicEditor)>,
+    mut editor: Query<(&mut InputState, &mut CosmicEditor, RelativeQuery)>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
     mut font_system: ResMut<CosmicFontSystem>,
-) {
+) -> render_implementations::Result<()> {
     let font_system = &mut font_system.0;
     let event = &trigger.event;
     let entity = trigger.target;
 
     if event.button != PointerButton::Primary {
-        return;
+        return Ok(());
     }
 
-    let Ok((input_state, mut editor)) = editor.get_mut(entity) else {
+    let Ok((mut input_state, mut editor, relative)) = editor.get_mut(entity) else {
         warn_no_editor_on_picking_event("handling cursor `Drag` event");
-        return;
+        return Ok(());
     };
 
     input_state.continue_dragging();
 
     if let InputState::Dragging {
-        initial_buffer_coord,
-    } = *input_state
+        widget_coord,
+        camera,
+    } = input_state.as_mut()
     {
-        let new_buffer_coord = initial_buffer_coord + event.distance;
-        editor.action(
-            font_system,
-            Action::Drag {
-                x: new_buffer_coord.x as i32,
-                y: new_buffer_coord.y as i32,
-            },
-        );
+        let Ok(camera) = cameras.get(*camera) else {
+            return Ok(());
+        };
+        *widget_coord = relative.compute_pointer_widget_coord(&trigger.pointer_location, camera)?;
+        drag_to(&mut editor, &relative, *widget_coord, font_system)?;
     }
+
+    Ok(())
+}
+
+/// Scrolls while a selection is dragged past the edges of a widget,
+/// faster the further out the pointer is, see [`CosmicScroll::auto_scroll_speed`]
+pub(super) fn auto_scroll_drag(
+    mut editor: Query<(
+        &InputState,
+        &mut CosmicEditor,
+        &mut CosmicScroll,
+        RelativeQuery,
+    )>,
+    mut font_system: ResMut<CosmicFontSystem>,
+    time: Res<Time<Real>>,
+) {
+    let font_system = &mut font_system.0;
+    for (input_state, mut editor, mut scroll, relative) in editor.iter_mut() {
+        let InputState::Dragging { widget_coord, .. } = *input_state else {
+            continue;
+        };
+        let Ok(content) = relative.content_rect() else {
+            continue;
+        };
+        let past_edge = (widget_coord - content.min).min(Vec2::ZERO)
+            + (widget_coord - content.max).max(Vec2::ZERO);
+        if past_edge == Vec2::ZERO {
+            continue;
+        }
+
+        // the scroll offset is in physical pixels, like the buffer
+        let speed = scroll.auto_scroll_speed * relative.scale_factor();
+        let range = scroll.range();
+        let offset =
+            (scroll.offset() + past_edge * speed * time.delta_secs()).clamp(range.min, range.max);
+        if offset != scroll.offset() {
+            scroll.set_offset(offset);
+        }
+        // keeps extending the selection while the text moves under a still pointer
+        if let Err(err) = drag_to(&mut editor, &relative, widget_coord, font_system) {
+            debug!(
+                message = "Couldn't extend the selection while auto-scrolling",
+                ?err
+            );
+        }
+    }
+}
+
+fn drag_to(
+    editor: &mut CosmicEditor,
+    relative: &RelativeQueryItem,
+    widget_coord: Vec2,
+    font_system: &mut cosmic_text::FontSystem,
+) -> render_implementations::Result<()> {
+    let buffer_size = editor.with_buffer_mut(|b| b.borrow_with(font_system).expected_size());
+    let horizontal_scroll = editor.with_buffer(|b| b.scroll().horizontal);
+    let buffer_coord =
+        relative.widget_coord_to_buffer(widget_coord, buffer_size, horizontal_scroll)?;
+    editor.action(
+        font_system,
+        Action::Drag {
+            x: buffer_coord.x as i32,
+            y: buffer_coord.y as i32,
+        },
+    );
+    Ok(())
 }
 
 pub(super) fn handle_dragend(
@@ -146,3 +229,103 @@ pub(super) fn handle_dragend(
 
     input_state.end_dragging();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+    use cosmic_text::{Attrs, Metrics, Selection};
+
+    use super::*;
+    use crate::primary::tests::headless_app;
+
+    /// A focused sprite widget with ten lines of text, two of which fit,
+    /// with frames 100ms apart
+    fn app() -> (App, Entity) {
+        let mut app = headless_app();
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            100,
+        )));
+        let world = app.world_mut();
+        let mut font_system = world.resource_mut::<CosmicFontSystem>();
+        let text = (0..10)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(20., 20.)).with_text(
+            &mut font_system,
+            &text,
+            Attrs::new(),
+        );
+        let entity = world
+            .spawn((
+                TextEdit2d,
+                buffer,
+                Sprite {
+                    custom_size: Some(Vec2::new(200., 40.)),
+                    ..default()
+                },
+            ))
+            .id();
+        world.resource_mut::<FocusedWidget>().0 = Some(entity);
+        // adds the editor, then lays it out
+        app.update();
+        app.update();
+        (app, entity)
+    }
+
+    /// Holds the pointer `past` pixels below the widget
+    fn drag_below(app: &mut App, entity: Entity, past: f32) {
+        *app.world_mut().get_mut::<InputState>(entity).unwrap() = InputState::Dragging {
+            widget_coord: Vec2::new(10., 40. + past),
+            camera: Entity::PLACEHOLDER,
+        };
+    }
+
+    fn scroll_offset(app: &App, entity: Entity) -> f32 {
+        app.world().get::<CosmicScroll>(entity).unwrap().offset().y
+    }
+
+    fn cursor_line(app: &App, entity: Entity) -> usize {
+        app.world()
+            .get::<CosmicEditor>(entity)
+            .unwrap()
+            .cursor()
+            .line
+    }
+
+    #[test]
+    fn dragging_past_an_edge_scrolls_and_selects() {
+        let (mut app, entity) = app();
+        drag_below(&mut app, entity, 20.);
+        app.update();
+        // 20 pixels out for 100ms
+        assert_eq!(scroll_offset(&app, entity), 16.);
+        let editor = app.world().get::<CosmicEditor>(entity).unwrap();
+        assert_eq!(editor.selection(), Selection::Normal(Default::default()));
+        // to the last line in view
+        assert_eq!(cursor_line(&app, entity), 1);
+
+        app.update();
+        app.update();
+        assert_eq!(scroll_offset(&app, entity), 48.);
+        assert!(cursor_line(&app, entity) > 1);
+    }
+
+    #[test]
+    fn auto_scrolling_can_be_turned_off() {
+        let (mut app, entity) = app();
+        app.world_mut()
+            .get_mut::<CosmicScroll>(entity)
+            .unwrap()
+            .auto_scroll_speed = 0.;
+        drag_below(&mut app, entity, 20.);
+        app.update();
+        assert_eq!(scroll_offset(&app, entity), 0.);
+        // the selection still follows the pointer, as far as the text in view
+        assert_eq!(cursor_line(&app, entity), 1);
+        app.update();
+        assert_eq!(cursor_line(&app, entity), 1);
+    }
+}
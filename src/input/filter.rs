@@ -0,0 +1,317 @@
+//! Restricting what text can be entered into a widget
+//!
+//! Add an [`InputFilter`] to a widget to filter every insertion, whether it comes from
+//! typing, the clipboard or an [`EditCommand`](crate::edit_command::EditCommand).
+//! Rejected or rewritten insertions send a [`CosmicTextRejected`] event, and the
+//! widget's [`Validity`] tracks whether its whole text passes the filter.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::input::filter::InputFilter;
+//!
+//! # fn setup(mut commands: Commands) {
+//! commands.spawn((
+//!     TextEdit2d,
+//!     CosmicEditBuffer::default(),
+//!     InputFilter::Float,
+//! ));
+//! # }
+//! ```
+
+use std::borrow::Cow;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    edit_log::{EditLog, EditLogSet},
+    prelude::*,
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.add_event::<CosmicTextRejected>()
+        .register_type::<Validity>()
+        .add_systems(PostUpdate, update_validity.after(EditLogSet));
+}
+
+/// Restricts which text can be inserted into a widget
+#[derive(Component, Clone)]
+#[require(Validity)]
+pub enum InputFilter {
+    /// ASCII digits only
+    Digits,
+    /// An optionally signed whole number, e.g. `-42`
+    Integer,
+    /// An optionally signed decimal number, e.g. `-4.2`
+    Float,
+    /// Hexadecimal digits, either case
+    Hex,
+    /// Letters and digits of any script
+    Alphanumeric,
+    /// Only graphemes matching the regex are allowed, e.g. `[a-z_]`
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+    /// Called with the current text and the text being inserted.
+    /// A selection being typed over isn't part of the text.
+    ///
+    /// Returns `None` to reject the insertion, or the text to insert instead
+    Custom(fn(&str, &str) -> Option<String>),
+    /// Like [`InputFilter::Custom`], also given the byte offset into the current text
+    /// the insertion goes at, between the text and the insertion
+    CustomAt(fn(&str, usize, &str) -> Option<String>),
+}
+
+impl std::fmt::Debug for InputFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputFilter::Digits => write!(f, "Digits"),
+            InputFilter::Integer => write!(f, "Integer"),
+            InputFilter::Float => write!(f, "Float"),
+            InputFilter::Hex => write!(f, "Hex"),
+            InputFilter::Alphanumeric => write!(f, "Alphanumeric"),
+            #[cfg(feature = "regex")]
+            InputFilter::Regex(regex) => f.debug_tuple("Regex").field(regex).finish(),
+            InputFilter::Custom(_) => write!(f, "Custom(..)"),
+            InputFilter::CustomAt(_) => write!(f, "CustomAt(..)"),
+        }
+    }
+}
+
+impl InputFilter {
+    /// Filters `insertion` about to be inserted between `before` and `after`.
+    ///
+    /// Built-in filters drop the graphemes they don't allow, so pasting `"1,000"`
+    /// into a [`InputFilter::Digits`] widget inserts `"1000"`.
+    /// Returns `None` if nothing is left to insert
+    pub fn filter<'a>(
+        &self,
+        before: &str,
+        after: &str,
+        insertion: &'a str,
+    ) -> Option<Cow<'a, str>> {
+        let filtered: Cow<'a, str> = match self {
+            InputFilter::Custom(custom) => {
+                Cow::Owned(custom(&format!("{before}{after}"), insertion)?)
+            }
+            InputFilter::CustomAt(custom) => Cow::Owned(custom(
+                &format!("{before}{after}"),
+                before.len(),
+                insertion,
+            )?),
+            _ => {
+                let mut kept = String::with_capacity(insertion.len());
+                for grapheme in insertion.graphemes(true) {
+                    // only signs and separators depend on the text around them
+                    let text = match self {
+                        InputFilter::Integer | InputFilter::Float => {
+                            Cow::Owned(format!("{before}{kept}{grapheme}{after}"))
+                        }
+                        _ => Cow::Borrowed(""),
+                    };
+                    if self.allows(&text, grapheme) {
+                        kept.push_str(grapheme);
+                    }
+                }
+                match kept.len() == insertion.len() {
+                    true => Cow::Borrowed(insertion),
+                    false => Cow::Owned(kept),
+                }
+            }
+        };
+        Some(filtered).filter(|text| !text.is_empty())
+    }
+
+    /// Whether the whole of `text` passes this filter.
+    ///
+    /// Partial input like `"-"` for [`InputFilter::Integer`] can be typed but isn't valid.
+    /// Empty text is always valid
+    pub fn is_valid(&self, text: &str) -> bool {
+        if text.is_empty() {
+            return true;
+        }
+        match self {
+            InputFilter::Integer => text.parse::<i64>().is_ok(),
+            InputFilter::Float => {
+                text.parse::<f64>().is_ok()
+                    && text
+                        .chars()
+                        .all(|c| "+-.".contains(c) || c.is_ascii_digit())
+            }
+            InputFilter::Custom(custom) => {
+                custom("", text).is_some_and(|accepted| accepted == text)
+            }
+            InputFilter::CustomAt(custom) => {
+                custom("", 0, text).is_some_and(|accepted| accepted == text)
+            }
+            _ => text
+                .graphemes(true)
+                .all(|grapheme| self.allows(text, grapheme)),
+        }
+    }
+
+    /// Whether `grapheme` is allowed, `text` being the whole text once it is inserted.
+    /// Only numbers look at `text`
+    fn allows(&self, text: &str, grapheme: &str) -> bool {
+        let mut chars = grapheme.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            // multi-char graphemes are only ever letters or symbols
+            return match self {
+                InputFilter::Alphanumeric => grapheme.chars().all(char::is_alphanumeric),
+                #[cfg(feature = "regex")]
+                InputFilter::Regex(regex) => regex.is_match(grapheme),
+                _ => false,
+            };
+        };
+        match self {
+            InputFilter::Digits => c.is_ascii_digit(),
+            InputFilter::Hex => c.is_ascii_hexdigit(),
+            InputFilter::Alphanumeric => c.is_alphanumeric(),
+            InputFilter::Integer => c.is_ascii_digit() || is_leading_sign(text, c),
+            InputFilter::Float => {
+                c.is_ascii_digit()
+                    || is_leading_sign(text, c)
+                    || (c == '.' && text.matches('.').count() == 1)
+            }
+            #[cfg(feature = "regex")]
+            InputFilter::Regex(regex) => regex.is_match(grapheme),
+            InputFilter::Custom(_) | InputFilter::CustomAt(_) => true,
+        }
+    }
+}
+
+fn is_leading_sign(text: &str, c: char) -> bool {
+    (c == '-' || c == '+') && text.starts_with(c) && !text[1..].contains(['-', '+'])
+}
+
+/// Whether the text of a widget passes its [`InputFilter`], see [`InputFilter::is_valid`]
+#[derive(Component, Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validity {
+    #[default]
+    Valid,
+    Invalid,
+}
+
+impl Validity {
+    pub fn is_valid(&self) -> bool {
+        *self == Validity::Valid
+    }
+}
+
+/// Sent when an [`InputFilter`] rejects or rewrites an insertion
+#[derive(Event, Debug)]
+pub struct CosmicTextRejected {
+    pub entity: Entity,
+    /// The text that was going to be inserted, before filtering
+    pub text: String,
+}
+
+/// Checks the text of widgets whose text or filter changed
+fn update_validity(mut q: Query<(Ref<InputFilter>, &mut Validity, &EditLog)>) {
+    for (filter, mut validity, log) in q.iter_mut() {
+        if !filter.is_changed() && log.edits().is_empty() {
+            continue;
+        }
+        // empty while a placeholder is shown
+        let text = log.text().unwrap_or_default();
+        let new = match filter.is_valid(text) {
+            true => Validity::Valid,
+            false => Validity::Invalid,
+        };
+        validity.set_if_neq(new);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(filter: &InputFilter, before: &str, after: &str, insertion: &str) -> Option<String> {
+        filter.filter(before, after, insertion).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn digits() {
+        let digits = InputFilter::Digits;
+        assert_eq!(filter(&digits, "", "", "1,000").as_deref(), Some("1000"));
+        assert_eq!(filter(&digits, "12", "", "-"), None);
+        assert!(digits.is_valid("0123"));
+        assert!(!digits.is_valid("1.5"));
+    }
+
+    #[test]
+    fn integers_are_signed_only_at_the_start() {
+        let integer = InputFilter::Integer;
+        assert_eq!(filter(&integer, "", "42", "-").as_deref(), Some("-"));
+        assert_eq!(filter(&integer, "4", "2", "-"), None);
+        assert_eq!(filter(&integer, "-", "", "+"), None);
+        assert_eq!(filter(&integer, "", "", "+-12").as_deref(), Some("+12"));
+        assert!(integer.is_valid("-42"));
+        // can be typed, but isn't a number yet
+        assert!(!integer.is_valid("-"));
+    }
+
+    #[test]
+    fn floats_have_one_separator() {
+        let float = InputFilter::Float;
+        assert_eq!(filter(&float, "", "", "1.2.3").as_deref(), Some("1.23"));
+        assert_eq!(filter(&float, "1.", "", "."), None);
+        assert_eq!(filter(&float, "", ".5", "-").as_deref(), Some("-"));
+        assert!(float.is_valid("-4.2"));
+        assert!(!float.is_valid("1e5"));
+        assert!(!float.is_valid("inf"));
+    }
+
+    #[test]
+    fn hex() {
+        let hex = InputFilter::Hex;
+        assert_eq!(filter(&hex, "", "", "0xBeEf").as_deref(), Some("0BeEf"));
+        assert!(hex.is_valid("c0ffee"));
+        assert!(!hex.is_valid("g"));
+    }
+
+    #[test]
+    fn custom_filters_can_rewrite_or_reject() {
+        let filter = InputFilter::Custom(|text, insertion| {
+            (text.len() + insertion.len() <= 3).then(|| insertion.to_uppercase())
+        });
+        assert_eq!(filter.filter("a", "", "b").as_deref(), Some("B"));
+        assert_eq!(filter.filter("ab", "c", "d"), None);
+        assert!(filter.is_valid("ABC"));
+        assert!(!filter.is_valid("abc"));
+    }
+
+    #[test]
+    fn validity_follows_edits() {
+        let mut world = World::new();
+        let entity = world.spawn((InputFilter::Integer, EditLog::default())).id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(update_validity);
+        let mut edit = |world: &mut World, text: &str| {
+            let mut log = world.get_mut::<EditLog>(entity).unwrap();
+            log.clear();
+            log.record(Vec::new(), text.into(), false);
+            schedule.run(world);
+            *world.get::<Validity>(entity).unwrap()
+        };
+        edit(&mut world, "");
+        assert_eq!(edit(&mut world, "-"), Validity::Invalid);
+        assert_eq!(edit(&mut world, "-1"), Validity::Valid);
+
+        // only checked again once the text or filter changes
+        world.entity_mut(entity).insert(Validity::Invalid);
+        assert_eq!(edit(&mut world, "-1"), Validity::Invalid);
+        world.entity_mut(entity).insert(InputFilter::Float);
+        assert_eq!(edit(&mut world, "-1"), Validity::Valid);
+    }
+
+    #[test]
+    fn custom_filters_know_where_the_insertion_goes() {
+        // upper case at the start of the text, lower case anywhere else
+        let filter = InputFilter::CustomAt(|_, offset, insertion| match offset {
+            0 => Some(insertion.to_uppercase()),
+            _ => Some(insertion.to_lowercase()),
+        });
+        assert_eq!(filter.filter("", "bc", "a").as_deref(), Some("A"));
+        assert_eq!(filter.filter("ab", "", "C").as_deref(), Some("c"));
+    }
+}
@@ -0,0 +1,358 @@
+//! Input Method Editor (IME) support
+//!
+//! While a writable widget is focused, [`Window::ime_enabled`] is set on the
+//! primary window and the IME candidate box is placed at the caret.
+//! Composed text arrives as [`Ime::Commit`] and is inserted at the cursor,
+//! while the in-progress composition ([`Ime::Preedit`]) is shown underlined
+//! inline at the cursor.
+
+use bevy::window::{Ime, PrimaryWindow};
+use cosmic_text::{Cursor, Selection};
+use render_implementations::RelativeQuery;
+
+use crate::{
+    edit_log::{untracked, EditLog},
+    focus::FocusSet,
+    input::CosmicTextChanged,
+    password::PasswordSet,
+    prelude::*,
+    render::RenderSet,
+};
+
+use super::keyboard::{insert_text, InputConstraints, InsertEvents};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.init_resource::<ImeCommits>().add_systems(
+        PostUpdate,
+        (
+            splice_preedit.after(PasswordSet).before(RenderSet),
+            (unsplice_preedit, update_ime_window)
+                .chain()
+                .after(RenderSet)
+                .before(FocusSet)
+                .before(crate::password::restore_password_text),
+        ),
+    );
+}
+
+/// The text currently being composed with an IME for a widget
+#[derive(Component, Default, Debug)]
+pub struct ImePreedit {
+    /// Composed text that hasn't been committed yet
+    pub value: String,
+    /// Byte range of the IME's own cursor within [`ImePreedit::value`],
+    /// `None` when the IME hides it
+    pub cursor: Option<(usize, usize)>,
+
+    /// Set while [`ImePreedit::value`] is temporarily inserted into the buffer for rendering
+    spliced: Option<SplicedPreedit>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SplicedPreedit {
+    start: Cursor,
+    end: Cursor,
+    cursor: Cursor,
+    selection: Selection,
+}
+
+impl ImePreedit {
+    pub fn is_composing(&self) -> bool {
+        !self.value.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.value.clear();
+        self.cursor = None;
+    }
+
+    /// Range of the buffer occupied by the preedit text while rendering
+    pub(crate) fn spliced_range(&self) -> Option<(Cursor, Cursor)> {
+        self.spliced.map(|spliced| (spliced.start, spliced.end))
+    }
+}
+
+/// Text committed by the IME this frame.
+///
+/// Some platforms deliver a commit as well as a [`KeyboardInput`](bevy::input::keyboard::KeyboardInput)
+/// for the same character, which must only be inserted once.
+#[derive(Resource, Default, Debug)]
+pub(crate) struct ImeCommits(Vec<String>);
+
+impl ImeCommits {
+    /// Returns true and forgets the commit if `text` was already inserted by the IME
+    pub fn take(&mut self, text: &str) -> bool {
+        match self.0.iter().position(|commit| commit == text) {
+            Some(i) => {
+                self.0.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Reads [`Ime`] events for the focused widget
+pub(crate) fn read_ime_events(
+    active_editor: Res<FocusedWidget>,
+    mut ime_evr: EventReader<Ime>,
+    mut commits: ResMut<ImeCommits>,
+    mut q: Query<(
+        Entity,
+        &mut ImePreedit,
+        Option<&mut CosmicEditor>,
+        InputConstraints,
+        Has<ReadOnly>,
+    )>,
+    mut evw_changed: EventWriter<CosmicTextChanged>,
+    mut insert_events: InsertEvents,
+    mut font_system: ResMut<CosmicFontSystem>,
+) {
+    commits.0.clear();
+
+    // compositions don't carry over to other widgets
+    for (entity, mut preedit, ..) in q.iter_mut() {
+        if active_editor.0 != Some(entity) && preedit.is_composing() {
+            preedit.clear();
+        }
+    }
+
+    let Some(Ok((entity, mut preedit, Some(mut editor), constraints, false))) =
+        active_editor.0.map(|e| q.get_mut(e))
+    else {
+        ime_evr.clear();
+        return;
+    };
+
+    let mut is_edit = false;
+    for ev in ime_evr.read() {
+        match ev {
+            Ime::Preedit { value, cursor, .. } => {
+                preedit.value.clone_from(value);
+                preedit.cursor = *cursor;
+                editor.set_redraw(true);
+            }
+            Ime::Commit { value, .. } => {
+                preedit.clear();
+                insert_text(
+                    &mut editor,
+                    &mut font_system,
+                    value,
+                    &constraints,
+                    &mut insert_events,
+                );
+                commits.0.push(value.clone());
+                is_edit = true;
+            }
+            Ime::Disabled { .. } => {
+                preedit.clear();
+                editor.set_redraw(true);
+            }
+            Ime::Enabled { .. } => {}
+        }
+    }
+
+    if is_edit {
+        evw_changed.send(CosmicTextChanged((entity, editor.get_text())));
+    }
+}
+
+/// Temporarily inserts the preedit text at the cursor, so it is laid out and rendered.
+///
+/// This isn't an edit, so it is kept out of the [`EditLog`]
+fn splice_preedit(mut q: Query<(&mut CosmicEditor, &mut ImePreedit, &mut EditLog)>) {
+    for (mut editor, mut preedit, mut log) in q.iter_mut() {
+        if !preedit.is_composing() || preedit.spliced.is_some() {
+            continue;
+        }
+
+        let cursor = editor.cursor();
+        let selection = editor.selection();
+        let start = editor.selection_bounds().map_or(cursor, |(start, _)| start);
+        let end = untracked(&mut editor, &mut log, |editor| {
+            editor.insert_at(start, &preedit.value, None)
+        });
+        let caret = match preedit.cursor {
+            Some((caret, _)) => Cursor::new(start.line, start.index + caret),
+            None => end,
+        };
+        editor.set_selection(Selection::None);
+        editor.set_cursor(caret);
+        editor.set_redraw(true);
+
+        preedit.spliced = Some(SplicedPreedit {
+            start,
+            end,
+            cursor,
+            selection,
+        });
+    }
+}
+
+/// Reverts [`splice_preedit`]
+fn unsplice_preedit(mut q: Query<(&mut CosmicEditor, &mut ImePreedit, &mut EditLog)>) {
+    for (mut editor, mut preedit, mut log) in q.iter_mut() {
+        let Some(spliced) = preedit.spliced.take() else {
+            continue;
+        };
+        untracked(&mut editor, &mut log, |editor| {
+            editor.delete_range(spliced.start, spliced.end)
+        });
+        editor.set_cursor(spliced.cursor);
+        editor.set_selection(spliced.selection);
+        editor.set_redraw(true);
+    }
+}
+
+/// Enables IME while a writable widget is focused, and moves the
+/// IME candidate box to just below the caret
+fn update_ime_window(
+    active_editor: Res<FocusedWidget>,
+    mut editors: Query<(&mut CosmicEditor, RelativeQuery), Without<ReadOnly>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut font_system: ResMut<CosmicFontSystem>,
+) {
+    let focused = active_editor.0.and_then(|e| editors.get_mut(e).ok());
+    let enabled = focused.is_some();
+    if window.ime_enabled != enabled {
+        window.ime_enabled = enabled;
+    }
+
+    let Some((mut editor, relative)) = focused else {
+        return;
+    };
+    // only reading the layout here
+    let editor = editor.bypass_change_detection();
+    let Some((x, y)) = editor.cursor_position() else {
+        return;
+    };
+    let line_height = editor.with_buffer(|b| b.metrics().line_height);
+    let buffer_size = editor.with_buffer_mut(|b| b.borrow_with(&mut font_system).expected_size());
+    let horizontal_scroll = editor.with_buffer(|b| b.scroll().horizontal);
+    let camera = cameras.iter().find(|(camera, _)| camera.is_active);
+
+    match relative.buffer_coord_to_window(
+        Vec2::new(x as f32, y as f32 + line_height),
+        buffer_size,
+        horizontal_scroll,
+        camera,
+    ) {
+        Ok(position) => {
+            if window.ime_position != position {
+                window.ime_position = position;
+            }
+        }
+        Err(err) => debug!(message = "Couldn't place the IME candidate box", ?err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmic_text::Metrics;
+
+    use super::*;
+    use crate::{
+        edit_log::{clear_edits, collect_edits},
+        input::filter::CosmicTextRejected,
+        primary::create_cosmic_font_system,
+        undo::{record_history, UndoHistory},
+        LimitReached,
+    };
+
+    /// A focused widget with the systems that move IME text in and out of its buffer
+    fn setup() -> (World, Schedule, Entity) {
+        let mut world = World::new();
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(14., 18.));
+        let editor = CosmicEditor::clone_from_buffer(&buffer);
+        world.init_resource::<Assets<Image>>();
+        let entity = world.spawn((buffer, editor)).id();
+
+        world.insert_resource(CosmicFontSystem(font_system));
+        world.insert_resource(FocusedWidget(Some(entity)));
+        world.init_resource::<Time>();
+        world.init_resource::<ImeCommits>();
+        world.init_resource::<Events<Ime>>();
+        world.init_resource::<Events<CosmicTextChanged>>();
+        world.init_resource::<Events<CosmicTextRejected>>();
+        world.init_resource::<Events<LimitReached>>();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                clear_edits,
+                read_ime_events,
+                collect_edits,
+                record_history,
+                splice_preedit,
+                unsplice_preedit,
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+        (world, schedule, entity)
+    }
+
+    fn edits(world: &World, entity: Entity) -> Vec<String> {
+        let log = world.get::<EditLog>(entity).unwrap();
+        log.edits()
+            .iter()
+            .map(|edit| edit.inserted.clone())
+            .collect()
+    }
+
+    #[test]
+    fn composing_is_not_an_edit() {
+        let (mut world, mut schedule, entity) = setup();
+        for value in ["n", "に", "にh", "にほ"] {
+            world.send_event(Ime::Preedit {
+                window: Entity::PLACEHOLDER,
+                value: value.into(),
+                cursor: Some((value.len(), value.len())),
+            });
+            schedule.run(&mut world);
+            assert!(world.get::<ImePreedit>(entity).unwrap().is_composing());
+            assert_eq!(edits(&world, entity), [""; 0]);
+        }
+        schedule.run(&mut world);
+        assert_eq!(edits(&world, entity), [""; 0]);
+        assert_eq!(world.get::<CosmicEditor>(entity).unwrap().get_text(), "");
+        assert!(!world.get::<UndoHistory>(entity).unwrap().can_undo());
+    }
+
+    #[test]
+    fn committing_is_one_edit() {
+        let (mut world, mut schedule, entity) = setup();
+        world.send_event(Ime::Preedit {
+            window: Entity::PLACEHOLDER,
+            value: "にほ".into(),
+            cursor: None,
+        });
+        schedule.run(&mut world);
+        world.send_event(Ime::Commit {
+            window: Entity::PLACEHOLDER,
+            value: "日本".into(),
+        });
+        schedule.run(&mut world);
+
+        assert_eq!(edits(&world, entity), ["日本"]);
+        let history = world.get::<UndoHistory>(entity).unwrap();
+        let undo: Vec<_> = history
+            .undo_entries()
+            .iter()
+            .map(|entry| (entry.removed.as_str(), entry.inserted.as_str()))
+            .collect();
+        assert_eq!(undo, [("", "日本")]);
+
+        schedule.run(&mut world);
+        assert_eq!(edits(&world, entity), [""; 0]);
+        assert_eq!(
+            world.get::<CosmicEditor>(entity).unwrap().get_text(),
+            "日本"
+        );
+    }
+}
@@ -1,34 +1,29 @@
-use bevy::input::keyboard::{Key, KeyboardInput};
-use cosmic_text::{Action, Cursor, Motion, Selection};
+use std::borrow::Cow;
 
-use crate::{input::CosmicTextChanged, prelude::*, MaxChars, MaxLines};
+use bevy::{
+    ecs::{query::QueryData, system::SystemParam},
+    input::keyboard::{Key, KeyboardInput},
+};
+use cosmic_text::{Action, Cursor, Edit, Editor, Motion, Selection};
+use unicode_segmentation::UnicodeSegmentation;
 
-pub(super) fn keypress_command(keys: &ButtonInput<KeyCode>) -> bool {
-    #[cfg(target_os = "macos")]
-    let command = keys.any_pressed([KeyCode::SuperLeft, KeyCode::SuperRight]);
-
-    #[cfg(not(target_os = "macos"))]
-    let command = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
-
-    #[cfg(target_arch = "wasm32")]
-    let command = if web_sys::window()
-        .unwrap()
-        .navigator()
-        .user_agent()
-        .unwrap_or("NoUA".into())
-        .contains("Macintosh")
-    {
-        keys.any_pressed([KeyCode::SuperLeft, KeyCode::SuperRight])
-    } else {
-        command
-    };
-
-    command
-}
+use crate::{
+    input::{
+        filter::{CosmicTextRejected, InputFilter},
+        ime::{ImeCommits, ImePreedit},
+        keymap::{EditorAction, FiredActions, Modifiers},
+        CosmicTextChanged,
+    },
+    placeholder::Placeholder,
+    prelude::*,
+    single_line::SingleLine,
+    Limit, LimitReached, MaxChars, MaxLines, MaxLinesMode,
+};
 
 pub(crate) fn kb_move_cursor(
     active_editor: Res<FocusedWidget>,
     keys: Res<ButtonInput<KeyCode>>,
+    fired: Res<FiredActions>,
     mut cosmic_edit_query: Query<(&mut CosmicEditor,)>,
     mut font_system: ResMut<CosmicFontSystem>,
 ) {
@@ -36,138 +31,37 @@ pub(crate) fn kb_move_cursor(
         return;
     };
     if let Ok((mut editor,)) = cosmic_edit_query.get_mut(active_editor_entity) {
-        if keys.get_just_pressed().len() != 0 {
+        if keys.get_just_pressed().len() != 0 || fired.iter().next().is_some() {
             editor.cursor_visible = true;
             editor.cursor_timer.reset();
         }
 
-        let command = keypress_command(&keys);
-
-        #[cfg(target_arch = "wasm32")]
-        let command = if web_sys::window()
-            .unwrap()
-            .navigator()
-            .user_agent()
-            .unwrap_or("NoUA".into())
-            .contains("Macintosh")
-        {
-            keys.any_pressed([KeyCode::SuperLeft, KeyCode::SuperRight])
-        } else {
-            command
-        };
-
-        #[cfg(target_os = "macos")]
-        let option = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
-
-        let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
-
-        // if shift key is pressed
-        let already_has_selection = editor.selection() != Selection::None;
-        if shift && !already_has_selection {
-            let cursor = editor.cursor();
-            editor.set_selection(Selection::Normal(cursor));
-        }
-
-        #[cfg(target_os = "macos")]
-        let should_jump = command && option;
-        #[cfg(not(target_os = "macos"))]
-        let should_jump = command;
-
-        if should_jump && keys.just_pressed(KeyCode::ArrowLeft) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::PreviousWord));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if should_jump && keys.just_pressed(KeyCode::ArrowRight) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::NextWord));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if should_jump && keys.just_pressed(KeyCode::Home) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::BufferStart));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if should_jump && keys.just_pressed(KeyCode::End) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::BufferEnd));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-
-        if keys.just_pressed(KeyCode::ArrowLeft) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::Left));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if keys.just_pressed(KeyCode::ArrowRight) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::Right));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if keys.just_pressed(KeyCode::ArrowUp) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::Up));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if keys.just_pressed(KeyCode::ArrowDown) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::Down));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if keys.just_pressed(KeyCode::Escape) {
-            editor.action(&mut font_system.0, Action::Escape);
-        }
-        if command && keys.just_pressed(KeyCode::KeyA) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::BufferEnd));
-            let current_cursor = editor.cursor();
-            editor.set_selection(Selection::Normal(Cursor {
-                line: 0,
-                index: 0,
-                affinity: current_cursor.affinity,
-            }));
-            return;
-        }
-        if keys.just_pressed(KeyCode::Home) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::Home));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if keys.just_pressed(KeyCode::End) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::End));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if keys.just_pressed(KeyCode::PageUp) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::PageUp));
-            if !shift {
-                editor.set_selection(Selection::None);
-            }
-            return;
-        }
-        if keys.just_pressed(KeyCode::PageDown) {
-            editor.action(&mut font_system.0, Action::Motion(Motion::PageDown));
-            if !shift {
-                editor.set_selection(Selection::None);
+        for action in fired.iter() {
+            match action {
+                EditorAction::Motion(motion) => {
+                    editor.action(&mut font_system.0, Action::Motion(motion));
+                    editor.set_selection(Selection::None);
+                }
+                EditorAction::Select(motion) => {
+                    if editor.selection() == Selection::None {
+                        let cursor = editor.cursor();
+                        editor.set_selection(Selection::Normal(cursor));
+                    }
+                    editor.action(&mut font_system.0, Action::Motion(motion));
+                }
+                EditorAction::SelectAll => {
+                    editor.action(&mut font_system.0, Action::Motion(Motion::BufferEnd));
+                    let current_cursor = editor.cursor();
+                    editor.set_selection(Selection::Normal(Cursor {
+                        line: 0,
+                        index: 0,
+                        affinity: current_cursor.affinity,
+                    }));
+                }
+                EditorAction::Escape => {
+                    editor.action(&mut font_system.0, Action::Escape);
+                }
+                _ => {}
             }
         }
     }
@@ -179,104 +73,125 @@ pub(crate) fn kb_input_text(
     mut char_evr: EventReader<KeyboardInput>,
     mut cosmic_edit_query: Query<(
         &mut CosmicEditor,
-        &MaxLines,
-        &MaxChars,
+        InputConstraints,
         Entity,
         Option<&ReadOnly>,
+        &ImePreedit,
     )>,
+    fired: Res<FiredActions>,
+    mut ime_commits: ResMut<ImeCommits>,
     mut evw_changed: EventWriter<CosmicTextChanged>,
+    mut insert_events: InsertEvents,
     mut font_system: ResMut<CosmicFontSystem>,
-    mut is_deleting: Local<bool>,
+    mut dead_key: Local<Option<char>>,
 ) {
     let Some(active_editor_entity) = active_editor.0 else {
         return;
     };
 
-    if let Ok((mut editor, max_lines, max_chars, entity, readonly_opt)) =
+    if let Ok((mut editor, constraints, entity, readonly_opt, preedit)) =
         cosmic_edit_query.get_mut(active_editor_entity)
     {
-        let command = keypress_command(&keys);
-        if keys.get_just_pressed().len() != 0 {
+        let command = Modifiers::from_keys(&keys).contains(Modifiers::command());
+        if keys.get_just_pressed().len() != 0 || fired.iter().next().is_some() {
             editor.cursor_visible = true;
             editor.cursor_timer.reset();
         }
         let readonly = readonly_opt.is_some();
 
-        if keys.just_pressed(KeyCode::Backspace) & !readonly {
-            // fix for issue #8
-            let select = editor.selection();
-            match select {
-                Selection::Line(cursor) => {
-                    if editor.cursor().line == cursor.line && editor.cursor().index == cursor.index
-                    {
-                        editor.set_selection(Selection::None);
+        let mut is_edit = false;
+        let mut is_return = false;
+        for action in fired.iter() {
+            match action {
+                EditorAction::Backspace if !readonly => {
+                    // fix for issue #8
+                    let select = editor.selection();
+                    match select {
+                        Selection::Line(cursor)
+                        | Selection::Normal(cursor)
+                        | Selection::Word(cursor) => {
+                            if editor.cursor().line == cursor.line
+                                && editor.cursor().index == cursor.index
+                            {
+                                editor.set_selection(Selection::None);
+                            }
+                        }
+                        Selection::None => {}
                     }
+
+                    is_edit = true;
+                    editor.action(&mut font_system.0, Action::Backspace);
                 }
-                Selection::Normal(cursor) => {
-                    if editor.cursor().line == cursor.line && editor.cursor().index == cursor.index
-                    {
-                        editor.set_selection(Selection::None);
-                    }
+                EditorAction::Delete if !readonly => {
+                    is_edit = true;
+                    editor.action(&mut font_system.0, Action::Delete);
+                    editor.with_buffer_mut(|b| b.set_redraw(true));
                 }
-                Selection::Word(cursor) => {
-                    if editor.cursor().line == cursor.line && editor.cursor().index == cursor.index
-                    {
-                        editor.set_selection(Selection::None);
-                    }
+                EditorAction::Newline if !readonly => {
+                    is_return = true;
+                    is_edit = true;
+                    // to have new line on wasm rather than E
+                    insert_text(
+                        &mut editor,
+                        &mut font_system,
+                        "\n",
+                        &constraints,
+                        &mut insert_events,
+                    );
                 }
-                Selection::None => {}
+                _ => {}
             }
-
-            *is_deleting = true;
-        }
-
-        if keys.just_released(KeyCode::Backspace) {
-            *is_deleting = false;
-        }
-        if keys.just_pressed(KeyCode::Delete) && !readonly {
-            editor.action(&mut font_system.0, Action::Delete);
-            editor.with_buffer_mut(|b| b.set_redraw(true));
         }
 
         if readonly {
             return;
         }
 
-        let mut is_edit = false;
-        let mut is_return = false;
-        if keys.just_pressed(KeyCode::Enter) {
-            is_return = true;
-            if (max_lines.0 == 0 || editor.with_buffer(|b| b.lines.len()) < max_lines.0)
-                && (max_chars.0 == 0 || editor.get_text().len() < max_chars.0)
-            {
-                // to have new line on wasm rather than E
-                is_edit = true;
-                editor.action(&mut font_system.0, Action::Insert('\n'));
-            }
-        }
-
         if !is_return {
             for char_ev in char_evr.read() {
-                is_edit = true;
-                if *is_deleting {
-                    editor.action(&mut font_system.0, Action::Backspace);
-                } else if !command
-                    && (max_chars.0 == 0 || editor.get_text().len() < max_chars.0)
+                if !command
+                    && !fired.consumed()
+                    && !preedit.is_composing()
                     && matches!(char_ev.state, bevy::input::ButtonState::Pressed)
                 {
-                    match &char_ev.logical_key {
-                        Key::Character(char) => {
-                            let b = char.as_bytes();
-                            for c in b {
-                                let c: char = (*c).into();
-                                editor.action(&mut font_system.0, Action::Insert(c));
+                    let text = match &char_ev.logical_key {
+                        Key::Character(text) => text.as_str(),
+                        Key::Space => " ",
+                        Key::Dead(Some(accent)) => {
+                            // pressing a dead key twice types the accent itself
+                            match dead_key.take() {
+                                Some(previous) if previous == *accent => {
+                                    is_edit = true;
+                                    insert_text(
+                                        &mut editor,
+                                        &mut font_system,
+                                        &accent.to_string(),
+                                        &constraints,
+                                        &mut insert_events,
+                                    );
+                                }
+                                _ => *dead_key = Some(*accent),
                             }
+                            continue;
                         }
-                        Key::Space => {
-                            editor.action(&mut font_system.0, Action::Insert(' '));
-                        }
-                        _ => (),
+                        _ => continue,
+                    };
+                    if ime_commits.take(text) {
+                        // already inserted by the IME
+                        continue;
                     }
+                    let text = match dead_key.take() {
+                        Some(accent) => Cow::Owned(compose_dead_key(accent, text)),
+                        None => Cow::Borrowed(text),
+                    };
+                    is_edit = true;
+                    insert_text(
+                        &mut editor,
+                        &mut font_system,
+                        &text,
+                        &constraints,
+                        &mut insert_events,
+                    );
                 }
             }
         }
@@ -291,3 +206,375 @@ pub(crate) fn kb_input_text(
         )));
     }
 }
+
+/// Everything limiting what can be inserted into a widget, see [`insert_text`]
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub(crate) struct InputConstraints {
+    pub entity: Entity,
+    pub max_chars: &'static MaxChars,
+    pub max_lines: &'static MaxLines,
+    pub max_lines_mode: Option<&'static MaxLinesMode>,
+    pub filter: Option<&'static InputFilter>,
+    pub single_line: Option<&'static SingleLine>,
+    /// Mutable so that [`EditCommand`](crate::edit_command::EditCommand)s can clear it
+    pub placeholder: Option<&'static mut Placeholder>,
+}
+
+/// Events sent by [`insert_text`]
+#[derive(SystemParam)]
+pub(crate) struct InsertEvents<'w> {
+    pub rejected: EventWriter<'w, CosmicTextRejected>,
+    pub limit_reached: EventWriter<'w, LimitReached>,
+}
+
+/// Inserts `text` at the cursor, replacing the selection.
+///
+/// The text first goes through the [`InputFilter`], if any, sending [`CosmicTextRejected`]
+/// if it is rejected or rewritten. It is then cut short at the first grapheme that
+/// doesn't fit within [`MaxChars`] or [`MaxLines`], sending [`LimitReached`]
+pub(crate) fn insert_text(
+    editor: &mut CosmicEditor,
+    font_system: &mut CosmicFontSystem,
+    text: &str,
+    constraints: &InputConstraintsItem,
+    events: &mut InsertEvents,
+) {
+    let entity = constraints.entity;
+    // the placeholder text is about to be replaced, so doesn't count
+    let placeholder_active = constraints
+        .placeholder
+        .as_deref()
+        .is_some_and(Placeholder::is_active);
+
+    let text = match constraints.single_line {
+        Some(single_line) => single_line.newlines.flatten(text),
+        None => Cow::Borrowed(text),
+    };
+    let text = text.as_ref();
+
+    let filtered = match constraints.filter {
+        Some(filter) => {
+            let (before, after) = match placeholder_active {
+                true => Default::default(),
+                false => text_around_selection(editor),
+            };
+            filter.filter(&before, &after, text)
+        }
+        None => Some(Cow::Borrowed(text)),
+    };
+    if filtered.as_deref() != Some(text) {
+        events.rejected.send(CosmicTextRejected {
+            entity,
+            text: text.to_string(),
+        });
+    }
+    let Some(text) = filtered else {
+        return;
+    };
+
+    // cosmic-text refuses control characters other than tabs and new lines
+    let text: String = text
+        .replace("\r\n", "\n")
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n'))
+        .collect();
+
+    editor.delete_selection();
+
+    let (max_chars, max_lines) = (constraints.max_chars.0, constraints.max_lines.0);
+    let visual_lines = constraints.max_lines_mode == Some(&MaxLinesMode::Visual);
+    let (mut chars, mut lines) = match placeholder_active {
+        true => (0, 1),
+        false => editor.with_buffer(|b| {
+            let graphemes: usize = b
+                .lines
+                .iter()
+                .map(|l| l.text().graphemes(true).count())
+                .sum();
+            // new lines count as characters too
+            (graphemes + b.lines.len() - 1, b.lines.len())
+        }),
+    };
+
+    let mut limit = None;
+    let mut kept_len = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        if max_chars != 0 && chars >= max_chars {
+            limit = Some(Limit::MaxChars);
+            break;
+        }
+        if grapheme == "\n" {
+            if !visual_lines && max_lines != 0 && lines >= max_lines {
+                limit = Some(Limit::MaxLines);
+                break;
+            }
+            lines += 1;
+        }
+        chars += 1;
+        kept_len = i + grapheme.len();
+    }
+    let mut kept = &text[..kept_len];
+
+    // each grapheme adds at most one line once wrapped, so there is nothing to probe
+    // while the text can't reach the limit
+    let may_overflow = |editor: &mut CosmicEditor, font_system: &mut CosmicFontSystem| {
+        visual_line_count(&mut **editor, font_system) + kept.graphemes(true).count() > max_lines
+    };
+    if visual_lines && max_lines != 0 && !kept.is_empty() && may_overflow(editor, font_system) {
+        // probes on a copy, so they are neither drawn nor recorded as edits
+        let mut probe = Editor::new(editor.with_buffer(Clone::clone));
+        let start = editor.cursor();
+        let mut fits = |len: usize| {
+            let end = probe.insert_at(start, &kept[..len], None);
+            let lines = visual_line_count(&mut probe, font_system);
+            probe.delete_range(start, end);
+            lines <= max_lines
+        };
+        if !fits(kept.len()) {
+            limit = Some(Limit::MaxLines);
+            // binary search for the most graphemes that still fit once wrapped
+            let ends: Vec<usize> = kept
+                .grapheme_indices(true)
+                .map(|(i, g)| i + g.len())
+                .collect();
+            let (mut fitting, mut overflowing) = (0, ends.len());
+            while overflowing - fitting > 1 {
+                let mid = (fitting + overflowing) / 2;
+                match fits(ends[mid - 1]) {
+                    true => fitting = mid,
+                    false => overflowing = mid,
+                }
+            }
+            kept = &kept[..fitting.checked_sub(1).map_or(0, |i| ends[i])];
+        }
+    }
+
+    if !kept.is_empty() {
+        editor.insert_string(kept, None);
+    }
+    if let Some(limit) = limit {
+        events.limit_reached.send(LimitReached { entity, limit });
+    }
+}
+
+/// Number of lines after wrapping, shaping lines as needed
+fn visual_line_count<'buffer>(
+    editor: &mut impl Edit<'buffer>,
+    font_system: &mut CosmicFontSystem,
+) -> usize {
+    editor.with_buffer_mut(|b| {
+        (0..b.lines.len())
+            .map(|i| b.line_layout(&mut font_system.0, i).map_or(1, |l| l.len()))
+            .sum()
+    })
+}
+
+/// The text before and after the cursor, ignoring the selected text which is about to be replaced
+fn text_around_selection(editor: &CosmicEditor) -> (String, String) {
+    let (start, end) = editor
+        .selection_bounds()
+        .unwrap_or((editor.cursor(), editor.cursor()));
+    editor.with_buffer(|b| {
+        let mut before = String::new();
+        let mut after = String::new();
+        for (i, line) in b.lines.iter().enumerate() {
+            let text = line.text();
+            if i < start.line {
+                before.push_str(text);
+                before.push('\n');
+            } else if i == start.line {
+                before.push_str(&text[..start.index]);
+            }
+            if i == end.line {
+                after.push_str(&text[end.index..]);
+            } else if i > end.line {
+                after.push('\n');
+                after.push_str(text);
+            }
+        }
+        (before, after)
+    })
+}
+
+/// Combines a dead key `accent` (e.g. `´`) with the next typed `text` (e.g. `e`).
+///
+/// Latin letters with a precomposed form become that character (e.g. `é`), any other
+/// letter gets the accent appended as a combining character, which forms a single
+/// grapheme with it. Dead key then space types the accent on its own.
+fn compose_dead_key(accent: char, text: &str) -> String {
+    // the combining character, and the letters it has a precomposed form for
+    let (combining, bases, precomposed) = match accent {
+        '`' => ('\u{300}', "AEINOUWYaeinouwy", "ÀÈÌǸÒÙẀỲàèìǹòùẁỳ"),
+        '´' | '\'' => (
+            '\u{301}',
+            "ACEGIKLMNOPRSUWYZacegiklmnoprsuwyz",
+            "ÁĆÉǴÍḰĹḾŃÓṔŔŚÚẂÝŹáćéǵíḱĺḿńóṕŕśúẃýź",
+        ),
+        '^' | 'ˆ' => (
+            '\u{302}',
+            "ACEGHIJOSUWYZaceghijosuwyz",
+            "ÂĈÊĜĤÎĴÔŜÛŴŶẐâĉêĝĥîĵôŝûŵŷẑ",
+        ),
+        '~' | '˜' => ('\u{303}', "AEINOUVYaeinouvy", "ÃẼĨÑÕŨṼỸãẽĩñõũṽỹ"),
+        '¯' => ('\u{304}', "AEGIOUYaegiouy", "ĀĒḠĪŌŪȲāēḡīōūȳ"),
+        '˘' => ('\u{306}', "AEGIOUaegiou", "ĂĔĞĬŎŬăĕğĭŏŭ"),
+        '˙' => (
+            '\u{307}',
+            "ABCDEFGHIMNOPRSTWXYZabcdefghmnoprstwxyz",
+            "ȦḂĊḊĖḞĠḢİṀṄȮṖṘṠṪẆẊẎŻȧḃċḋėḟġḣṁṅȯṗṙṡṫẇẋẏż",
+        ),
+        '¨' | '"' => ('\u{308}', "AEHIOUWXYaehiotuwxy", "ÄËḦÏÖÜẄẌŸäëḧïöẗüẅẍÿ"),
+        '˚' => ('\u{30A}', "AUauwy", "ÅŮåůẘẙ"),
+        '˝' => ('\u{30B}', "OUou", "ŐŰőű"),
+        'ˇ' => (
+            '\u{30C}',
+            "ACDEGHIKLNORSTUZacdeghijklnorstuz",
+            "ǍČĎĚǦȞǏǨĽŇǑŘŠŤǓŽǎčďěǧȟǐǰǩľňǒřšťǔž",
+        ),
+        '¸' => (
+            '\u{327}',
+            "CDEGHKLNRSTcdeghklnrst",
+            "ÇḐȨĢḨĶĻŅŖŞŢçḑȩģḩķļņŗşţ",
+        ),
+        '˛' => ('\u{328}', "AEIOUaeiou", "ĄĘĮǪŲąęįǫų"),
+        _ if text == " " => return accent.to_string(),
+        _ => return format!("{accent}{text}"),
+    };
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(' '), None) => accent.to_string(),
+        (Some(base), None) if base.is_alphabetic() => {
+            match bases.find(base).and_then(|i| precomposed.chars().nth(i)) {
+                Some(composed) => composed.to_string(),
+                None => format!("{base}{combining}"),
+            }
+        }
+        _ => format!("{accent}{text}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use cosmic_text::Metrics;
+
+    use super::*;
+    use crate::{edit_log::take_changes, primary::create_cosmic_font_system};
+
+    /// A focused widget `width` pixels wide, with the given limits
+    fn widget(limits: impl Bundle, width: f32) -> (World, Entity) {
+        let mut world = World::new();
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(14., 18.));
+        let mut editor = CosmicEditor::clone_from_buffer(&buffer);
+        editor.with_buffer_mut(|b| b.set_size(&mut font_system, Some(width), None));
+        world.insert_resource(CosmicFontSystem(font_system));
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<Events<CosmicTextRejected>>();
+        world.init_resource::<Events<LimitReached>>();
+        let entity = world.spawn((buffer, editor, limits)).id();
+        (world, entity)
+    }
+
+    /// Inserts `text` like typing or pasting does, returning the limits reached
+    fn insert(world: &mut World, text: &'static str) -> Vec<Limit> {
+        world
+            .run_system_once(
+                move |mut q: Query<(&mut CosmicEditor, InputConstraints)>,
+                      mut font_system: ResMut<CosmicFontSystem>,
+                      mut events: InsertEvents| {
+                    let (mut editor, constraints) = q.single_mut();
+                    insert_text(
+                        &mut editor,
+                        &mut font_system,
+                        text,
+                        &constraints,
+                        &mut events,
+                    );
+                },
+            )
+            .unwrap();
+        let mut events = world.resource_mut::<Events<LimitReached>>();
+        events.drain().map(|event| event.limit).collect()
+    }
+
+    fn text(world: &World, entity: Entity) -> String {
+        world.get::<CosmicEditor>(entity).unwrap().get_text()
+    }
+
+    #[test]
+    fn filtered_insertions_are_reported() {
+        let (mut world, entity) = widget(InputFilter::Digits, 1000.);
+        insert(&mut world, "1,000");
+        insert(&mut world, "42");
+        insert(&mut world, "x");
+        assert_eq!(text(&world, entity), "100042");
+        let mut events = world.resource_mut::<Events<CosmicTextRejected>>();
+        let rejected: Vec<_> = events.drain().map(|event| event.text).collect();
+        assert_eq!(rejected, ["1,000", "x"]);
+    }
+
+    #[test]
+    fn max_chars_counts_graphemes() {
+        let (mut world, entity) = widget(MaxChars(4), 1000.);
+        // an accent, a skin tone and a new line each count once
+        assert_eq!(insert(&mut world, "e\u{301}👍🏽\n"), []);
+        assert_eq!(insert(&mut world, "ab"), [Limit::MaxChars]);
+        assert_eq!(text(&world, entity), "e\u{301}👍🏽\na");
+        assert_eq!(insert(&mut world, "b"), [Limit::MaxChars]);
+        assert_eq!(text(&world, entity), "e\u{301}👍🏽\na");
+    }
+
+    #[test]
+    fn pastes_are_cut_at_max_lines() {
+        let (mut world, entity) = widget(MaxLines(2), 1000.);
+        assert_eq!(insert(&mut world, "one\ntwo\nthree"), [Limit::MaxLines]);
+        assert_eq!(text(&world, entity), "one\ntwo");
+    }
+
+    #[test]
+    fn visual_max_lines_count_wrapped_lines() {
+        // about ten characters per line
+        let (mut world, entity) = widget((MaxLines(2), MaxLinesMode::Visual), 90.);
+        assert_eq!(insert(&mut world, "one two"), []);
+        assert_eq!(
+            insert(&mut world, " three four five six seven"),
+            [Limit::MaxLines]
+        );
+        let kept = text(&world, entity);
+        assert!("one two three four five six seven".starts_with(&kept));
+        assert!(kept.len() > "one two three".len(), "{kept:?}");
+
+        let mut editor = world.get_mut::<CosmicEditor>(entity).unwrap();
+        // only the insertions, not the probes, are edits
+        let inserted: Vec<_> = take_changes(&mut editor)
+            .into_iter()
+            .map(|change| (change.insert, change.text))
+            .collect();
+        assert_eq!(
+            inserted,
+            [
+                (true, "one two".to_string()),
+                (true, kept["one two".len()..].to_string())
+            ]
+        );
+        let mut font_system = world.remove_resource::<CosmicFontSystem>().unwrap();
+        let mut editor = world.get_mut::<CosmicEditor>(entity).unwrap();
+        assert_eq!(visual_line_count(&mut **editor, &mut font_system), 2);
+    }
+
+    #[test]
+    fn dead_keys_compose_to_precomposed_letters() {
+        assert_eq!(compose_dead_key('´', "e"), "é");
+        assert_eq!(compose_dead_key('¨', "U"), "Ü");
+        assert_eq!(compose_dead_key('ˇ', "z"), "ž");
+        // no precomposed form
+        assert_eq!(compose_dead_key('˚', "e"), "e\u{30A}");
+        assert_eq!(compose_dead_key('^', " "), "^");
+        assert_eq!(compose_dead_key('^', "1"), "^1");
+    }
+}
@@ -0,0 +1,838 @@
+//! Rebindable keyboard shortcuts for focused editors
+//!
+//! The [`CosmicKeymap`] resource maps [`KeyChord`]s to [`KeyBinding`]s.
+//! Add a [`CosmicKeymap`] component to a widget to override the global
+//! keymap for that widget only.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::input::keymap::*;
+//!
+//! # fn setup(mut commands: Commands) {
+//! let mut keymap = CosmicKeymap::emacs();
+//! keymap.bind(KeyChord::ctrl(KeyCode::KeyS), KeyBinding::event("save"));
+//! keymap.unbind(KeyChord::new(KeyCode::PageUp));
+//! commands.insert_resource(keymap);
+//! # }
+//! ```
+//!
+//! Typed characters are not part of the keymap, but are suppressed
+//! for any key press that triggered a binding.
+//!
+//! Holding a key repeats its [`EditorAction`], see [`KeyRepeat`].
+
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::Duration};
+
+use bevy::input::keyboard::KeyboardInput;
+use cosmic_text::Motion;
+
+use crate::prelude::*;
+
+/// Modifier keys that have to be held for a [`KeyChord`] to match.
+///
+/// Left and right variants of a modifier are treated the same.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Self = Self {
+        ctrl: false,
+        shift: false,
+        alt: false,
+        super_key: false,
+    };
+    pub const CTRL: Self = Self {
+        ctrl: true,
+        ..Self::NONE
+    };
+    pub const SHIFT: Self = Self {
+        shift: true,
+        ..Self::NONE
+    };
+    pub const ALT: Self = Self {
+        alt: true,
+        ..Self::NONE
+    };
+    pub const SUPER: Self = Self {
+        super_key: true,
+        ..Self::NONE
+    };
+
+    /// The platform's primary shortcut modifier: Cmd on macOS, Ctrl everywhere else
+    pub fn command() -> Self {
+        if is_mac() {
+            Self::SUPER
+        } else {
+            Self::CTRL
+        }
+    }
+
+    /// Currently held modifiers
+    pub fn from_keys(keys: &ButtonInput<KeyCode>) -> Self {
+        Self {
+            ctrl: keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+            shift: keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+            alt: keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]),
+            super_key: keys.any_pressed([KeyCode::SuperLeft, KeyCode::SuperRight]),
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            ctrl: self.ctrl || other.ctrl,
+            shift: self.shift || other.shift,
+            alt: self.alt || other.alt,
+            super_key: self.super_key || other.super_key,
+        }
+    }
+
+    /// Whether every modifier of `other` is held in `self`
+    pub fn contains(self, other: Self) -> bool {
+        self.union(other) == self
+    }
+
+    pub fn without_shift(self) -> Self {
+        Self {
+            shift: false,
+            ..self
+        }
+    }
+}
+
+pub(crate) fn is_mac() -> bool {
+    #[cfg(target_arch = "wasm32")]
+    return web_sys::window()
+        .unwrap()
+        .navigator()
+        .user_agent()
+        .unwrap_or("NoUA".into())
+        .contains("Macintosh");
+
+    #[cfg(not(target_arch = "wasm32"))]
+    cfg!(target_os = "macos")
+}
+
+/// A key together with the modifiers that have to be held
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub struct KeyChord {
+    pub key: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl KeyChord {
+    /// A key pressed without any modifiers
+    pub fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    pub fn with_modifiers(key: KeyCode, modifiers: Modifiers) -> Self {
+        Self { key, modifiers }
+    }
+
+    /// `key` with the platform's primary shortcut modifier, see [`Modifiers::command`]
+    pub fn command(key: KeyCode) -> Self {
+        Self::with_modifiers(key, Modifiers::command())
+    }
+
+    pub fn ctrl(key: KeyCode) -> Self {
+        Self::with_modifiers(key, Modifiers::CTRL)
+    }
+
+    pub fn alt(key: KeyCode) -> Self {
+        Self::with_modifiers(key, Modifiers::ALT)
+    }
+
+    /// Additionally require shift to be held
+    pub fn shift(self) -> Self {
+        Self {
+            modifiers: self.modifiers.union(Modifiers::SHIFT),
+            ..self
+        }
+    }
+}
+
+/// Named actions the built-in input systems know how to perform
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorAction {
+    /// Moves the cursor, clearing the selection.
+    ///
+    /// If the chord is pressed with shift and there is no binding for the
+    /// shifted chord, the selection is extended instead.
+    Motion(Motion),
+    /// Moves the cursor, extending the selection
+    Select(Motion),
+    SelectAll,
+    /// Clears the selection
+    Escape,
+    Backspace,
+    Delete,
+    /// Inserts a new line, as long as [`MaxLines`](crate::MaxLines) and
+    /// [`MaxChars`](crate::MaxChars) allow it
+    Newline,
+    Copy,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+}
+
+/// Sent when a [`KeyBinding::Event`] is triggered
+#[derive(Event, Debug, Clone)]
+pub struct CosmicKeymapEvent {
+    /// The focused widget
+    pub entity: Entity,
+    pub name: Cow<'static, str>,
+}
+
+/// What happens when a [`KeyChord`] is pressed
+#[derive(Clone)]
+pub enum KeyBinding {
+    Action(EditorAction),
+    /// Runs the closure with the focused widget
+    Callback(Arc<dyn Fn(Entity, &mut Commands) + Send + Sync>),
+    /// Sends a [`CosmicKeymapEvent`] with this name
+    Event(Cow<'static, str>),
+}
+
+impl KeyBinding {
+    pub fn callback(f: impl Fn(Entity, &mut Commands) + Send + Sync + 'static) -> Self {
+        Self::Callback(Arc::new(f))
+    }
+
+    pub fn event(name: impl Into<Cow<'static, str>>) -> Self {
+        Self::Event(name.into())
+    }
+}
+
+impl From<EditorAction> for KeyBinding {
+    fn from(action: EditorAction) -> Self {
+        Self::Action(action)
+    }
+}
+
+impl std::fmt::Debug for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Action(action) => f.debug_tuple("Action").field(action).finish(),
+            Self::Callback(_) => f.write_str("Callback(..)"),
+            Self::Event(name) => f.debug_tuple("Event").field(name).finish(),
+        }
+    }
+}
+
+/// Maps [`KeyChord`]s to [`KeyBinding`]s for the focused editor.
+///
+/// Used as a resource for all widgets, or as a component to override
+/// the resource for a single widget.
+/// Defaults to [`CosmicKeymap::platform_default`].
+#[derive(Resource, Component, Clone, Debug)]
+pub struct CosmicKeymap {
+    bindings: HashMap<KeyChord, KeyBinding>,
+}
+
+impl Default for CosmicKeymap {
+    fn default() -> Self {
+        Self::platform_default()
+    }
+}
+
+impl CosmicKeymap {
+    /// A keymap without any bindings
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Only plain navigation and editing keys, no shortcuts involving modifiers
+    pub fn minimal() -> Self {
+        use KeyCode::*;
+
+        let mut keymap = Self::empty();
+        keymap
+            .bind(KeyChord::new(ArrowLeft), EditorAction::Motion(Motion::Left))
+            .bind(
+                KeyChord::new(ArrowRight),
+                EditorAction::Motion(Motion::Right),
+            )
+            .bind(KeyChord::new(ArrowUp), EditorAction::Motion(Motion::Up))
+            .bind(KeyChord::new(ArrowDown), EditorAction::Motion(Motion::Down))
+            .bind(KeyChord::new(Home), EditorAction::Motion(Motion::Home))
+            .bind(KeyChord::new(End), EditorAction::Motion(Motion::End))
+            .bind(KeyChord::new(Backspace), EditorAction::Backspace)
+            .bind(KeyChord::new(Delete), EditorAction::Delete)
+            .bind(KeyChord::new(Enter), EditorAction::Newline)
+            .bind(KeyChord::new(Enter).shift(), EditorAction::Newline);
+        keymap
+    }
+
+    /// The usual shortcuts of the current platform
+    pub fn platform_default() -> Self {
+        use KeyCode::*;
+
+        // word and document jumps
+        let jump = if is_mac() {
+            Modifiers::command().union(Modifiers::ALT)
+        } else {
+            Modifiers::command()
+        };
+
+        let mut keymap = Self::minimal();
+        keymap
+            .bind(KeyChord::new(PageUp), EditorAction::Motion(Motion::PageUp))
+            .bind(
+                KeyChord::new(PageDown),
+                EditorAction::Motion(Motion::PageDown),
+            )
+            .bind(
+                KeyChord::with_modifiers(ArrowLeft, jump),
+                EditorAction::Motion(Motion::PreviousWord),
+            )
+            .bind(
+                KeyChord::with_modifiers(ArrowRight, jump),
+                EditorAction::Motion(Motion::NextWord),
+            )
+            .bind(
+                KeyChord::with_modifiers(Home, jump),
+                EditorAction::Motion(Motion::BufferStart),
+            )
+            .bind(
+                KeyChord::with_modifiers(End, jump),
+                EditorAction::Motion(Motion::BufferEnd),
+            )
+            .bind(KeyChord::new(Escape), EditorAction::Escape)
+            .bind(KeyChord::command(KeyA), EditorAction::SelectAll)
+            .bind(KeyChord::command(KeyC), EditorAction::Copy)
+            .bind(KeyChord::command(KeyX), EditorAction::Cut)
+            .bind(KeyChord::command(KeyV), EditorAction::Paste)
+            .bind(KeyChord::command(KeyZ), EditorAction::Undo)
+            .bind(KeyChord::command(KeyZ).shift(), EditorAction::Redo)
+            .bind(KeyChord::command(KeyY), EditorAction::Redo);
+        keymap
+    }
+
+    /// Emacs-style bindings on top of [`CosmicKeymap::minimal`]
+    pub fn emacs() -> Self {
+        use KeyCode::*;
+
+        let mut keymap = Self::minimal();
+        keymap
+            .bind(KeyChord::ctrl(KeyF), EditorAction::Motion(Motion::Right))
+            .bind(KeyChord::ctrl(KeyB), EditorAction::Motion(Motion::Left))
+            .bind(KeyChord::ctrl(KeyN), EditorAction::Motion(Motion::Down))
+            .bind(KeyChord::ctrl(KeyP), EditorAction::Motion(Motion::Up))
+            .bind(KeyChord::ctrl(KeyA), EditorAction::Motion(Motion::Home))
+            .bind(KeyChord::ctrl(KeyE), EditorAction::Motion(Motion::End))
+            .bind(KeyChord::alt(KeyF), EditorAction::Motion(Motion::NextWord))
+            .bind(
+                KeyChord::alt(KeyB),
+                EditorAction::Motion(Motion::PreviousWord),
+            )
+            .bind(KeyChord::ctrl(KeyV), EditorAction::Motion(Motion::PageDown))
+            .bind(KeyChord::alt(KeyV), EditorAction::Motion(Motion::PageUp))
+            .bind(
+                KeyChord::alt(Comma).shift(),
+                EditorAction::Motion(Motion::BufferStart),
+            )
+            .bind(
+                KeyChord::alt(Period).shift(),
+                EditorAction::Motion(Motion::BufferEnd),
+            )
+            .bind(KeyChord::ctrl(KeyD), EditorAction::Delete)
+            .bind(KeyChord::ctrl(KeyH), EditorAction::Backspace)
+            .bind(KeyChord::ctrl(KeyG), EditorAction::Escape)
+            .bind(KeyChord::ctrl(KeyW), EditorAction::Cut)
+            .bind(KeyChord::alt(KeyW), EditorAction::Copy)
+            .bind(KeyChord::ctrl(KeyY), EditorAction::Paste)
+            .bind(KeyChord::ctrl(Slash), EditorAction::Undo)
+            .bind(KeyChord::ctrl(Slash).shift(), EditorAction::Redo);
+        keymap
+    }
+
+    /// Adds or replaces a binding
+    pub fn bind(&mut self, chord: KeyChord, binding: impl Into<KeyBinding>) -> &mut Self {
+        self.bindings.insert(chord, binding.into());
+        self
+    }
+
+    pub fn unbind(&mut self, chord: KeyChord) -> &mut Self {
+        self.bindings.remove(&chord);
+        self
+    }
+
+    pub fn get(&self, chord: &KeyChord) -> Option<&KeyBinding> {
+        self.bindings.get(chord)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&KeyChord, &KeyBinding)> {
+        self.bindings.iter()
+    }
+
+    /// Finds the binding for `chord`, falling back to the action of the unshifted chord,
+    /// e.g. for Shift+Backspace. [`EditorAction::Motion`]s then extend the selection.
+    fn resolve(&self, chord: KeyChord) -> Option<KeyBinding> {
+        if let Some(binding) = self.get(&chord) {
+            return Some(binding.clone());
+        }
+        if !chord.modifiers.shift {
+            return None;
+        }
+        let unshifted = KeyChord::with_modifiers(chord.key, chord.modifiers.without_shift());
+        match self.get(&unshifted) {
+            Some(KeyBinding::Action(EditorAction::Motion(motion))) => {
+                Some(KeyBinding::Action(EditorAction::Select(*motion)))
+            }
+            Some(KeyBinding::Action(action)) => Some(KeyBinding::Action(*action)),
+            _ => None,
+        }
+    }
+}
+
+/// Actions triggered this frame for the focused widget,
+/// waiting to be performed by the input systems
+#[derive(Resource, Default, Debug)]
+pub(crate) struct FiredActions {
+    /// In the order they were triggered, including repeats
+    actions: Vec<EditorAction>,
+    /// Whether any binding fired, including callbacks and events
+    consumed: bool,
+}
+
+impl FiredActions {
+    pub fn iter(&self) -> impl Iterator<Item = EditorAction> + '_ {
+        self.actions.iter().copied()
+    }
+
+    pub fn contains(&self, action: EditorAction) -> bool {
+        self.actions.contains(&action)
+    }
+
+    /// Takes every occurrence of `action`, so it isn't performed.
+    /// Returns whether it fired at all
+    pub fn remove(&mut self, action: EditorAction) -> bool {
+        let len = self.actions.len();
+        self.actions.retain(|fired| *fired != action);
+        self.actions.len() != len
+    }
+
+    /// Whether any binding fired, in which case typed characters are ignored
+    pub fn consumed(&self) -> bool {
+        self.consumed
+    }
+}
+
+/// How [`EditorAction`]s repeat while their key is held down.
+///
+/// Callbacks and events bound with [`KeyBinding::Callback`] and [`KeyBinding::Event`]
+/// only fire once per key press
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct KeyRepeat {
+    /// Defaults to `true`
+    pub enabled: bool,
+    /// Follow the repeat events sent by the platform, if it sends any.
+    ///
+    /// Otherwise, or if this is `false`, [`KeyRepeat::delay`] and
+    /// [`KeyRepeat::interval`] are used. Defaults to `true`
+    pub use_platform_repeat: bool,
+    /// How long a key has to be held before it starts repeating
+    pub delay: Duration,
+    /// Time between repeats
+    pub interval: Duration,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            use_platform_repeat: true,
+            delay: Duration::from_millis(500),
+            interval: Duration::from_millis(33),
+        }
+    }
+}
+
+/// Tracks the held key for [`KeyRepeat`]
+#[derive(Default)]
+pub(crate) struct RepeatState {
+    /// Whether the platform has sent any repeat events
+    platform_repeats: bool,
+    /// The most recently pressed key, which is the one that repeats,
+    /// and when it repeats next
+    held: Option<(KeyCode, Duration)>,
+}
+
+/// Translates key presses into [`FiredActions`], runs callbacks and sends events
+pub(crate) fn resolve_keymap(
+    active_editor: Res<FocusedWidget>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut key_evr: EventReader<KeyboardInput>,
+    global_keymap: Res<CosmicKeymap>,
+    keymap_overrides: Query<&CosmicKeymap>,
+    key_repeat: Res<KeyRepeat>,
+    mut repeat_state: Local<RepeatState>,
+    time: Res<Time<Real>>,
+    mut fired: ResMut<FiredActions>,
+    mut evw_keymap: EventWriter<CosmicKeymapEvent>,
+    mut commands: Commands,
+) {
+    fired.actions.clear();
+    fired.consumed = false;
+
+    let now = time.elapsed();
+    let mut repeated = Vec::new();
+    for ev in key_evr.read() {
+        if !ev.state.is_pressed() {
+            continue;
+        }
+        if !ev.repeat {
+            repeat_state.held = Some((ev.key_code, now + key_repeat.delay));
+        } else if key_repeat.use_platform_repeat {
+            repeat_state.platform_repeats = true;
+            repeated.push(ev.key_code);
+        }
+    }
+    if !key_repeat.use_platform_repeat || !repeat_state.platform_repeats {
+        if let Some((key, mut next)) = repeat_state.held {
+            if keys.pressed(key) {
+                while now >= next {
+                    repeated.push(key);
+                    next += key_repeat.interval.max(Duration::from_millis(1));
+                }
+                repeat_state.held = Some((key, next));
+            }
+        }
+    }
+    if let Some((key, _)) = repeat_state.held {
+        if !keys.pressed(key) {
+            repeat_state.held = None;
+        }
+    }
+
+    let Some(entity) = active_editor.0 else {
+        return;
+    };
+    let keymap = keymap_overrides.get(entity).unwrap_or(&global_keymap);
+    let modifiers = Modifiers::from_keys(&keys);
+
+    for key in keys.get_just_pressed() {
+        let Some(binding) = keymap.resolve(KeyChord::with_modifiers(*key, modifiers)) else {
+            continue;
+        };
+        fired.consumed = true;
+        match binding {
+            KeyBinding::Action(action) => fired.actions.push(action),
+            KeyBinding::Callback(f) => f(entity, &mut commands),
+            KeyBinding::Event(name) => {
+                evw_keymap.send(CosmicKeymapEvent { entity, name });
+            }
+        }
+    }
+
+    if !key_repeat.enabled {
+        return;
+    }
+    for key in repeated {
+        if let Some(KeyBinding::Action(action)) =
+            keymap.resolve(KeyChord::with_modifiers(key, modifiers))
+        {
+            fired.consumed = true;
+            fired.actions.push(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::{
+        keyboard::{Key, NativeKey},
+        ButtonState,
+    };
+    use cosmic_text::Metrics;
+
+    use super::*;
+    use crate::{
+        input::{
+            filter::CosmicTextRejected,
+            ime::{ImeCommits, ImePreedit},
+            keyboard::kb_input_text,
+            CosmicTextChanged,
+        },
+        primary::create_cosmic_font_system,
+        LimitReached, MaxChars, MaxLines,
+    };
+
+    fn action(keymap: &CosmicKeymap, chord: KeyChord) -> Option<EditorAction> {
+        match keymap.resolve(chord)? {
+            KeyBinding::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn platform_default_bindings() {
+        let keymap = CosmicKeymap::platform_default();
+        let undo = KeyChord::command(KeyCode::KeyZ);
+        assert_eq!(action(&keymap, undo), Some(EditorAction::Undo));
+        assert_eq!(action(&keymap, undo.shift()), Some(EditorAction::Redo));
+        // shift extends the selection with a motion that has no shifted binding
+        assert_eq!(
+            action(&keymap, KeyChord::new(KeyCode::PageDown).shift()),
+            Some(EditorAction::Select(Motion::PageDown))
+        );
+        assert_eq!(action(&keymap, KeyChord::new(KeyCode::KeyZ)), None);
+        assert_eq!(action(&keymap, KeyChord::alt(KeyCode::KeyZ)), None);
+    }
+
+    #[test]
+    fn shift_falls_back_to_unshifted_edits() {
+        let keymap = CosmicKeymap::platform_default();
+        for (key, expected) in [
+            (KeyCode::Backspace, EditorAction::Backspace),
+            (KeyCode::Delete, EditorAction::Delete),
+            (KeyCode::Escape, EditorAction::Escape),
+        ] {
+            assert_eq!(action(&keymap, KeyChord::new(key).shift()), Some(expected));
+        }
+        // only bindings that exist are fallen back to
+        assert_eq!(
+            action(&keymap, KeyChord::alt(KeyCode::Backspace).shift()),
+            None
+        );
+    }
+
+    #[test]
+    fn minimal_bindings() {
+        let keymap = CosmicKeymap::minimal();
+        assert_eq!(
+            action(&keymap, KeyChord::new(KeyCode::Home)),
+            Some(EditorAction::Motion(Motion::Home))
+        );
+        // bound explicitly, rather than selecting
+        assert_eq!(
+            action(&keymap, KeyChord::new(KeyCode::Enter).shift()),
+            Some(EditorAction::Newline)
+        );
+        assert_eq!(action(&keymap, KeyChord::command(KeyCode::KeyA)), None);
+        assert_eq!(action(&keymap, KeyChord::new(KeyCode::PageUp)), None);
+    }
+
+    #[test]
+    fn emacs_bindings() {
+        let keymap = CosmicKeymap::emacs();
+        let forward = KeyChord::ctrl(KeyCode::KeyF);
+        assert_eq!(
+            action(&keymap, forward),
+            Some(EditorAction::Motion(Motion::Right))
+        );
+        assert_eq!(
+            action(&keymap, forward.shift()),
+            Some(EditorAction::Select(Motion::Right))
+        );
+        // a motion bound with shift doesn't select
+        assert_eq!(
+            action(&keymap, KeyChord::alt(KeyCode::Period).shift()),
+            Some(EditorAction::Motion(Motion::BufferEnd))
+        );
+        assert_eq!(
+            action(&keymap, KeyChord::ctrl(KeyCode::Slash)),
+            Some(EditorAction::Undo)
+        );
+        // the minimal keys are still there
+        assert_eq!(
+            action(&keymap, KeyChord::new(KeyCode::Backspace)),
+            Some(EditorAction::Backspace)
+        );
+    }
+
+    /// A focused editor, with keys resolved and typed like the input plugin does
+    struct Keyboard {
+        world: World,
+        schedule: Schedule,
+        entity: Entity,
+    }
+
+    impl Keyboard {
+        fn new(keymap: CosmicKeymap, key_repeat: KeyRepeat) -> Self {
+            let mut world = World::new();
+            let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+                load_system_fonts: false,
+                ..default()
+            });
+            let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(14., 18.));
+            let entity = world
+                .spawn((
+                    CosmicEditor::clone_from_buffer(&buffer),
+                    MaxChars::default(),
+                    MaxLines::default(),
+                    ImePreedit::default(),
+                ))
+                .id();
+            world.insert_resource(CosmicFontSystem(font_system));
+            world.insert_resource(FocusedWidget(Some(entity)));
+            world.insert_resource(keymap);
+            world.insert_resource(key_repeat);
+            world.init_resource::<ButtonInput<KeyCode>>();
+            world.init_resource::<Time<Real>>();
+            world.init_resource::<FiredActions>();
+            world.init_resource::<ImeCommits>();
+            world.init_resource::<Events<KeyboardInput>>();
+            world.init_resource::<Events<CosmicKeymapEvent>>();
+            world.init_resource::<Events<CosmicTextChanged>>();
+            world.init_resource::<Events<CosmicTextRejected>>();
+            world.init_resource::<Events<LimitReached>>();
+            world
+                .resource_mut::<Time<Real>>()
+                .update_with_duration(Duration::ZERO);
+
+            let mut schedule = Schedule::default();
+            schedule.add_systems((resolve_keymap, kb_input_text).chain());
+            Self {
+                world,
+                schedule,
+                entity,
+            }
+        }
+
+        fn press(&mut self, key: KeyCode, text: Option<&str>, repeat: bool) {
+            if !repeat {
+                self.world.resource_mut::<ButtonInput<KeyCode>>().press(key);
+            }
+            let logical_key = match text {
+                Some(text) => Key::Character(text.into()),
+                None => Key::Unidentified(NativeKey::Unidentified),
+            };
+            self.world.send_event(KeyboardInput {
+                key_code: key,
+                logical_key,
+                state: ButtonState::Pressed,
+                repeat,
+                window: Entity::PLACEHOLDER,
+            });
+        }
+
+        fn release(&mut self, key: KeyCode) {
+            self.world
+                .resource_mut::<ButtonInput<KeyCode>>()
+                .release(key);
+        }
+
+        /// Runs a frame `millis` after the last one, returning the actions fired in it
+        fn frame(&mut self, millis: u64) -> Vec<EditorAction> {
+            self.world
+                .resource_mut::<Time<Real>>()
+                .update_with_duration(Duration::from_millis(millis));
+            self.schedule.run(&mut self.world);
+            self.world.resource_mut::<ButtonInput<KeyCode>>().clear();
+            self.world.resource_mut::<Events<KeyboardInput>>().update();
+            self.world.resource::<FiredActions>().iter().collect()
+        }
+
+        fn text(&self) -> String {
+            self.world
+                .get::<CosmicEditor>(self.entity)
+                .unwrap()
+                .get_text()
+        }
+    }
+
+    #[test]
+    fn held_keys_repeat_after_a_delay() {
+        let mut keyboard = Keyboard::new(
+            CosmicKeymap::minimal(),
+            KeyRepeat {
+                use_platform_repeat: false,
+                delay: Duration::from_millis(500),
+                interval: Duration::from_millis(100),
+                ..default()
+            },
+        );
+        let left = EditorAction::Motion(Motion::Left);
+        keyboard.press(KeyCode::ArrowLeft, None, false);
+        assert_eq!(keyboard.frame(0), [left]);
+        assert_eq!(keyboard.frame(400), []);
+        assert_eq!(keyboard.frame(100), [left]);
+        // every interval that passed since
+        assert_eq!(keyboard.frame(250), [left, left]);
+        keyboard.release(KeyCode::ArrowLeft);
+        assert_eq!(keyboard.frame(500), []);
+    }
+
+    #[test]
+    fn platform_repeats_are_followed() {
+        let mut keyboard = Keyboard::new(CosmicKeymap::minimal(), KeyRepeat::default());
+        keyboard.press(KeyCode::Backspace, None, false);
+        assert_eq!(keyboard.frame(0), [EditorAction::Backspace]);
+        keyboard.press(KeyCode::Backspace, None, true);
+        keyboard.press(KeyCode::Backspace, None, true);
+        assert_eq!(keyboard.frame(1000), [EditorAction::Backspace; 2]);
+        // no timed repeats once the platform sends its own
+        assert_eq!(keyboard.frame(1000), []);
+    }
+
+    #[test]
+    fn shifted_edit_keys_still_edit() {
+        let mut keyboard = Keyboard::new(CosmicKeymap::platform_default(), KeyRepeat::default());
+        keyboard.press(KeyCode::ShiftLeft, None, false);
+        keyboard.press(KeyCode::Backspace, None, false);
+        assert_eq!(keyboard.frame(0), [EditorAction::Backspace]);
+        keyboard.release(KeyCode::Backspace);
+        keyboard.press(KeyCode::Delete, None, false);
+        assert_eq!(keyboard.frame(16), [EditorAction::Delete]);
+    }
+
+    #[test]
+    fn bound_keys_type_nothing() {
+        let mut keymap = CosmicKeymap::minimal();
+        keymap.bind(KeyChord::new(KeyCode::KeyQ), KeyBinding::event("quit"));
+        let mut keyboard = Keyboard::new(keymap, KeyRepeat::default());
+
+        keyboard.press(KeyCode::KeyQ, Some("q"), false);
+        keyboard.frame(0);
+        assert!(keyboard.world.resource::<FiredActions>().consumed());
+        assert_eq!(
+            keyboard.world.resource::<Events<CosmicKeymapEvent>>().len(),
+            1
+        );
+        assert_eq!(keyboard.text(), "");
+
+        keyboard.release(KeyCode::KeyQ);
+        keyboard.press(KeyCode::KeyW, Some("w"), false);
+        keyboard.frame(16);
+        assert!(!keyboard.world.resource::<FiredActions>().consumed());
+        assert_eq!(keyboard.text(), "w");
+    }
+
+    #[test]
+    fn dead_keys_only_change_the_text_once_composed() {
+        let mut keyboard = Keyboard::new(CosmicKeymap::minimal(), KeyRepeat::default());
+        let changes = |keyboard: &Keyboard| {
+            let events = keyboard.world.resource::<Events<CosmicTextChanged>>();
+            events.len()
+        };
+
+        keyboard.world.send_event(KeyboardInput {
+            key_code: KeyCode::Quote,
+            logical_key: Key::Dead(Some('´')),
+            state: ButtonState::Pressed,
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        });
+        keyboard.frame(0);
+        assert_eq!(changes(&keyboard), 0);
+
+        keyboard.press(KeyCode::KeyE, Some("e"), false);
+        keyboard.frame(16);
+        assert_eq!(changes(&keyboard), 1);
+        assert_eq!(keyboard.text(), "é");
+    }
+}
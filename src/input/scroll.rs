@@ -1,35 +1,702 @@
+//! Scrolling widgets with the mouse wheel, or programmatically with [`CosmicScroll`]
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::input::scroll::CosmicScroll;
+//!
+//! /// Keeps a log view scrolled to its latest line
+//! fn follow_log(mut logs: Query<&mut CosmicScroll, Changed<CosmicEditBuffer>>) {
+//!     for mut scroll in logs.iter_mut() {
+//!         scroll.scroll_to_end();
+//!     }
+//! }
+//! ```
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    time::Duration,
+};
+
 use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
-use cosmic_text::Action;
 
-use crate::{prelude::*, ScrollEnabled};
+use crate::{password::PasswordSet, prelude::*, render::RenderSet, ScrollEnabled};
+use render_implementations::ScaleFactor;
+
+use super::{InputSet, InputState};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.register_type::<CosmicScroll>()
+        .register_type::<CosmicScrolled>()
+        .add_event::<CosmicScrolled>()
+        .add_systems(PreUpdate, scroll.in_set(InputSet))
+        .register_required_components::<CosmicEditBuffer, MeasuredScroll>()
+        .add_systems(
+            PostUpdate,
+            (
+                apply_scroll.before(RenderSet),
+                invalidate_scroll_metrics
+                    .after(PasswordSet)
+                    .before(RenderSet),
+                sync_scroll.after(RenderSet),
+            ),
+        );
+}
+
+/// Scroll position of a widget in pixels, which can also be set from your own systems.
+///
+/// The offset is the distance from the top left of the text to the top left of the
+/// widget, see [`CosmicScroll::offset`]. Like the text's metrics, it's in physical
+/// pixels, see [`Window::scale_factor`].
+/// Changes are applied in [`PostUpdate`], before the widget is rendered
+#[derive(Component, Reflect, Debug, Clone)]
+pub struct CosmicScroll {
+    /// Roughly how long scrolling with the mouse wheel takes to catch up, or `None`
+    /// to scroll instantly. Defaults to 100ms
+    pub smoothing: Option<Duration>,
+    /// Pixels per second to scroll for every pixel the pointer is past the text area
+    /// while dragging a selection, or `0.` not to scroll. Defaults to `8.`
+    pub auto_scroll_speed: f32,
+    offset: Vec2,
+    target: Vec2,
+    range: Rect,
+    #[reflect(ignore)]
+    request: Option<ScrollRequest>,
+    /// Set when [`CosmicScroll::offset`] has to be written to the buffer
+    dirty: bool,
+}
+
+impl Default for CosmicScroll {
+    fn default() -> Self {
+        Self {
+            smoothing: Some(Duration::from_millis(100)),
+            auto_scroll_speed: 8.,
+            offset: Vec2::ZERO,
+            target: Vec2::ZERO,
+            range: Rect::default(),
+            request: None,
+            dirty: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScrollRequest {
+    Cursor,
+    Line(usize),
+    End,
+}
+
+impl CosmicScroll {
+    /// Current offset, `x` is negative while an overflowing centered or
+    /// right aligned line is scrolled to its start
+    pub fn offset(&self) -> Vec2 {
+        self.offset
+    }
+
+    /// Where smooth scrolling is heading, the same as [`CosmicScroll::offset`] when settled
+    pub fn target(&self) -> Vec2 {
+        self.target
+    }
+
+    /// The offsets that can be scrolled to, as of the last render
+    pub fn range(&self) -> Rect {
+        self.range
+    }
+
+    /// Whether the widget is scrolled all the way down
+    pub fn is_at_end(&self) -> bool {
+        self.target.y >= self.range.max.y
+    }
+
+    /// Jumps to `offset`
+    pub fn set_offset(&mut self, offset: Vec2) {
+        self.offset = offset;
+        self.target = offset;
+        self.request = None;
+        self.dirty = true;
+    }
+
+    /// Scrolls by `delta` pixels, smoothly if [`CosmicScroll::smoothing`] is set.
+    ///
+    /// Returns the part of `delta` that is left over past the top, bottom or sides
+    pub fn scroll_by(&mut self, delta: Vec2) -> Vec2 {
+        let unclamped = self.target + delta;
+        self.target = self.clamp(unclamped);
+        self.request = None;
+        self.dirty = true;
+        unclamped - self.target
+    }
+
+    /// Scrolls just enough for the cursor of the focused widget to be visible
+    pub fn scroll_to_cursor(&mut self) {
+        self.request = Some(ScrollRequest::Cursor);
+    }
+
+    /// Scrolls so `line` is at the top of the widget, or as close as possible
+    pub fn scroll_to_line(&mut self, line: usize) {
+        self.request = Some(ScrollRequest::Line(line));
+    }
+
+    /// Scrolls to the bottom, e.g. to follow a log
+    pub fn scroll_to_end(&mut self) {
+        self.request = Some(ScrollRequest::End);
+    }
+
+    fn clamp(&self, offset: Vec2) -> Vec2 {
+        offset.clamp(self.range.min, self.range.max)
+    }
+
+    fn is_animating(&self) -> bool {
+        self.offset != self.target
+    }
+}
+
+/// Sent when a widget's [`CosmicScroll::offset`] changes, for any reason
+#[derive(Event, Reflect, Debug, Clone, Copy)]
+pub struct CosmicScrolled {
+    pub entity: Entity,
+    pub offset: Vec2,
+}
 
+/// Scrolls the widget under the pointer, focused or not.
+///
+/// Scrolling past its top or bottom scrolls the closest bevy_ui [`ScrollPosition`] around it
 pub(crate) fn scroll(
-    mut editor: Query<(&mut CosmicEditor, &ScrollEnabled)>,
-    mut font_system: ResMut<CosmicFontSystem>,
+    mut editor: Query<(
+        Entity,
+        &InputState,
+        &mut CosmicScroll,
+        &ScrollEnabled,
+        &ScaleFactor,
+        EditorBuffer,
+    )>,
+    parents: Query<&Parent>,
+    mut containers: Query<&mut ScrollPosition>,
     mut scroll_evr: EventReader<MouseWheel>,
+    keys: Res<ButtonInput<KeyCode>>,
 ) {
-    let font_system = &mut font_system.0;
-    for (mut editor, scroll_enabled) in editor.iter_mut() {
-        let mut editor = editor.borrow_with(font_system);
-
-        if **scroll_enabled {
-            for ev in scroll_evr.read() {
-                match ev.unit {
-                    MouseScrollUnit::Line => {
-                        // trace!(?ev, "Line");
-                        editor.action(Action::Scroll {
-                            lines: -ev.y as i32,
-                        });
-                    }
-                    MouseScrollUnit::Pixel => {
-                        // trace!(?ev, "Pixel");
-                        let line_height = editor.with_buffer(|b| b.metrics().line_height);
-                        editor.action(Action::Scroll {
-                            lines: -(ev.y / line_height) as i32,
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let events: Vec<&MouseWheel> = scroll_evr.read().collect();
+    if events.is_empty() {
+        return;
+    }
+    for (entity, input_state, mut scroll, scroll_enabled, scale, buffer) in editor.iter_mut() {
+        if !input_state.is_hovering() {
+            continue;
+        }
+        let line_height = buffer.metrics().line_height;
+        let mut leftover = Vec2::ZERO;
+        for ev in &events {
+            // shift turns the vertical wheel sideways
+            let delta = match shift {
+                true => Vec2::new(ev.x + ev.y, 0.),
+                false => Vec2::new(ev.x, ev.y),
+            };
+            let pixels = match ev.unit {
+                MouseScrollUnit::Line => delta * line_height,
+                // the buffer is in physical pixels
+                MouseScrollUnit::Pixel => delta * scale.0,
+            };
+            if pixels == Vec2::ZERO {
+                continue;
+            }
+            // wheel deltas point the way the content moves
+            leftover += match **scroll_enabled {
+                true => scroll.scroll_by(-pixels),
+                false => -pixels,
+            };
+        }
+
+        if leftover == Vec2::ZERO {
+            continue;
+        }
+        let container = parents
+            .iter_ancestors(entity)
+            .find(|ancestor| containers.contains(*ancestor));
+        if let Some(mut position) = container.and_then(|e| containers.get_mut(e).ok()) {
+            // bevy_ui scrolls in logical pixels
+            position.offset_x += leftover.x / scale.0;
+            position.offset_y += leftover.y / scale.0;
+        }
+    }
+}
+
+/// Resolves [`CosmicScroll`] requests and writes its offset to the buffer
+fn apply_scroll(
+    mut q: Query<(&mut CosmicScroll, &mut MeasuredScroll, EditorBuffer)>,
+    mut font_system: ResMut<CosmicFontSystem>,
+    time: Res<Time<Real>>,
+) {
+    for (mut scroll, mut measured, mut buffer) in q.iter_mut() {
+        if let Some(request) = scroll.request.take() {
+            match request {
+                ScrollRequest::Cursor => {
+                    if let Some(editor) = buffer.editor() {
+                        let cursor = editor.cursor();
+                        editor.with_buffer_mut(|b| {
+                            b.shape_until_cursor(&mut font_system.0, cursor, false)
                         });
                     }
+                    // picked up by `sync_scroll`
+                    continue;
                 }
+                ScrollRequest::Line(line) => {
+                    let top = buffer.borrow_with(&mut font_system.0).with_buffer_mut(|b| {
+                        (0..line.min(b.lines.len()))
+                            .map(|line_i| layout_height(b, line_i))
+                            .sum::<f32>()
+                    });
+                    scroll.target.y = top;
+                }
+                ScrollRequest::End => {
+                    let (metrics, height) = buffer
+                        .borrow_with(&mut font_system.0)
+                        .with_buffer_mut(|b| (measured.get(b), b.size().1.unwrap_or_default()));
+                    scroll.target.y = (metrics.content_height - height).max(0.);
+                }
+            }
+            scroll.dirty = true;
+        }
+
+        if !scroll.dirty {
+            continue;
+        }
+        let target = scroll.target;
+        let offset = match scroll.smoothing {
+            Some(smoothing) if !smoothing.is_zero() && scroll.is_animating() => {
+                // exponential approach, settling after roughly `smoothing`
+                let t = 1. - (-4. * time.delta_secs() / smoothing.as_secs_f32()).exp();
+                let offset = scroll.offset.lerp(target, t);
+                match offset.distance(target) < 0.5 {
+                    true => target,
+                    false => offset,
+                }
+            }
+            _ => target,
+        };
+        scroll.offset = offset;
+        scroll.dirty = scroll.is_animating();
+
+        set_vertical_offset(&mut buffer, offset.y);
+        let mut buffer_scroll = buffer.scroll();
+        buffer_scroll.horizontal = offset.x;
+        buffer.set_scroll(buffer_scroll);
+        buffer.set_redraw(true);
+    }
+}
+
+/// Reads back the scroll position cosmic text settled on, e.g. after following the
+/// cursor or clamping to the text
+fn sync_scroll(
+    mut q: Query<(Entity, &mut CosmicScroll, &mut MeasuredScroll, EditorBuffer)>,
+    mut font_system: ResMut<CosmicFontSystem>,
+    mut evw_scrolled: EventWriter<CosmicScrolled>,
+) {
+    for (entity, mut scroll, mut measured, mut buffer) in q.iter_mut() {
+        let (metrics, size) = buffer
+            .borrow_with(&mut font_system.0)
+            .with_buffer_mut(|b| (measured.get(b), b.size()));
+        let view = Vec2::new(size.0.unwrap_or_default(), size.1.unwrap_or_default());
+
+        let horizontal = match metrics.content_right - metrics.content_left > view.x {
+            true => (metrics.content_left, metrics.content_right - view.x),
+            false => (0., 0.),
+        };
+        let range = Rect::new(
+            horizontal.0,
+            0.,
+            horizontal.1,
+            (metrics.content_height - view.y).max(0.),
+        );
+        let offset = Vec2::new(metrics.horizontal, metrics.vertical);
+
+        // avoids triggering change detection every frame
+        let scroll = scroll.bypass_change_detection();
+        scroll.range = range;
+        if scroll.offset != offset {
+            scroll.offset = offset;
+            evw_scrolled.send(CosmicScrolled { entity, offset });
+        }
+        scroll.target = match scroll.dirty {
+            true => scroll.clamp(scroll.target),
+            false => offset,
+        };
+    }
+}
+
+/// Forgets the [`ScrollMetrics`] of widgets whose text changed, e.g. by hiding a password,
+/// for [`sync_scroll`] to measure them again if they weren't while drawing
+fn invalidate_scroll_metrics(mut q: Query<(&mut MeasuredScroll, EditorBuffer)>) {
+    for (mut measured, buffer) in q.iter_mut() {
+        if measured.text_changed(&buffer) {
+            measured.0 = None;
+        }
+    }
+}
+
+/// The [`ScrollMetrics`] of a widget, measured again only when its text
+/// or what it is laid out with changes, as that lays out every line.
+///
+/// Changing the text marks the buffer for a redraw, until it's drawn in [`RenderSet`],
+/// but so do moving the caret and blinking, so the text is compared too
+#[derive(Component, Default, Debug)]
+pub(crate) struct MeasuredScroll(Option<Measured>);
+
+#[derive(Debug, Clone, Copy)]
+struct Measured {
+    key: MeasureKey,
+    /// See [`text_hash`]
+    text: u64,
+    metrics: ScrollMetrics,
+}
+
+/// What [`ScrollMetrics`] depend on besides the text
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MeasureKey {
+    size: (Option<f32>, Option<f32>),
+    metrics: cosmic_text::Metrics,
+    wrap: cosmic_text::Wrap,
+    scroll: cosmic_text::Scroll,
+}
+
+impl MeasuredScroll {
+    pub fn get(
+        &mut self,
+        buffer: &mut cosmic_text::BorrowedWithFontSystem<Buffer>,
+    ) -> ScrollMetrics {
+        let key = MeasureKey {
+            size: buffer.size(),
+            metrics: buffer.metrics(),
+            wrap: buffer.wrap(),
+            scroll: buffer.scroll(),
+        };
+        match self.0 {
+            Some(measured) if measured.key == key && !self.text_changed(buffer) => measured.metrics,
+            _ => {
+                let metrics = ScrollMetrics::measure(buffer);
+                self.0 = Some(Measured {
+                    key,
+                    text: text_hash(buffer),
+                    metrics,
+                });
+                metrics
             }
         }
     }
+
+    /// Whether the text is different from when it was measured,
+    /// only compared while the buffer is marked for a redraw
+    fn text_changed(&self, buffer: &Buffer) -> bool {
+        self.0
+            .is_some_and(|measured| buffer.redraw() && measured.text != text_hash(buffer))
+    }
+}
+
+/// Hash of the lines of `buffer` and everything they're laid out with
+/// that isn't part of a [`MeasureKey`]
+fn text_hash(buffer: &Buffer) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for line in &buffer.lines {
+        let align = line.align().map(|align| align as u8);
+        (line.text(), line.ending().as_str(), align).hash(&mut hasher);
+        let attrs = line.attrs_list();
+        (attrs.defaults(), attrs.spans()).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Scroll position and scrollable area of a buffer, in pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct ScrollMetrics {
+    /// Height of all of the laid out text
+    pub content_height: f32,
+    /// Distance from the top of the text to the top of the view
+    pub vertical: f32,
+    /// Leftmost glyph edge of the visible lines, negative for lines overflowing
+    /// a centered or right aligned buffer
+    pub content_left: f32,
+    /// Rightmost glyph edge of the visible lines
+    pub content_right: f32,
+    /// See [`cosmic_text::Scroll::horizontal`]
+    pub horizontal: f32,
+}
+
+impl ScrollMetrics {
+    fn measure(buffer: &mut cosmic_text::BorrowedWithFontSystem<Buffer>) -> Self {
+        let scroll = buffer.scroll();
+        let mut content_height = 0.;
+        let mut vertical = scroll.vertical;
+        for line_i in 0..buffer.lines.len() {
+            let line_height = layout_height(buffer, line_i);
+            content_height += line_height;
+            if line_i < scroll.line {
+                vertical += line_height;
+            }
+        }
+        let (content_left, content_right) = horizontal_extent(buffer).unwrap_or_default();
+        Self {
+            content_height,
+            vertical,
+            content_left,
+            content_right,
+            horizontal: scroll.horizontal,
+        }
+    }
+}
+
+fn layout_height(buffer: &mut cosmic_text::BorrowedWithFontSystem<Buffer>, line_i: usize) -> f32 {
+    let line_height = buffer.metrics().line_height;
+    buffer.line_layout(line_i).map_or(line_height, |layout| {
+        layout
+            .iter()
+            .map(|line| line.line_height_opt.unwrap_or(line_height))
+            .sum()
+    })
+}
+
+/// Leftmost and rightmost glyph edges of the visible lines,
+/// `None` if there aren't any glyphs
+pub(crate) fn horizontal_extent(buffer: &Buffer) -> Option<(f32, f32)> {
+    buffer
+        .layout_runs()
+        .flat_map(|run| run.glyphs.iter())
+        .map(|glyph| (glyph.x, glyph.x + glyph.w))
+        .reduce(|(left, right), (glyph_left, glyph_right)| {
+            (left.min(glyph_left), right.max(glyph_right))
+        })
+}
+
+/// Scrolls so the top of the view is `offset` pixels below the top of the text.
+///
+/// Cosmic text moves the scroll into the text the next time the buffer is shaped
+pub(crate) fn set_vertical_offset(buffer: &mut Buffer, offset: f32) {
+    let mut scroll = buffer.scroll();
+    scroll.line = 0;
+    scroll.vertical = offset.max(0.);
+    buffer.set_scroll(scroll);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use cosmic_text::{Attrs, Metrics, Shaping};
+
+    use super::*;
+    use crate::primary::create_cosmic_font_system;
+
+    /// A world with a widget, see [`spawn_widget`]
+    fn setup(smoothing: Option<Duration>) -> (World, Entity) {
+        let mut world = World::new();
+        let font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        world.insert_resource(CosmicFontSystem(font_system));
+        world.init_resource::<Assets<Image>>();
+        let mut time = Time::<Real>::default();
+        time.update_with_duration(Duration::ZERO);
+        world.insert_resource(time);
+        world.init_resource::<Events<CosmicScrolled>>();
+        world.init_resource::<Events<MouseWheel>>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        let entity = spawn_widget(&mut world, smoothing);
+        (world, entity)
+    }
+
+    /// A widget with ten lines of text, three of which fit
+    fn spawn_widget(world: &mut World, smoothing: Option<Duration>) -> Entity {
+        let mut font_system = world.resource_mut::<CosmicFontSystem>();
+        let text = (0..10)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut buffer = Buffer::new(&mut font_system.0, Metrics::new(10., 10.));
+        let mut borrowed = buffer.borrow_with(&mut font_system.0);
+        borrowed.set_size(Some(100.), Some(30.));
+        borrowed.set_text(&text, Attrs::new(), Shaping::Advanced);
+        let scroll = CosmicScroll {
+            smoothing,
+            ..default()
+        };
+        let entity = world
+            .spawn((
+                CosmicEditBuffer::from_raw_buffer(buffer),
+                scroll,
+                MeasuredScroll::default(),
+                ScaleFactor(1.),
+            ))
+            .id();
+        frame(world, Duration::ZERO);
+        entity
+    }
+
+    /// Turns the mouse wheel by `lines`, positive lines scrolling up
+    fn wheel(world: &mut World, lines: f32) {
+        world.send_event(MouseWheel {
+            unit: MouseScrollUnit::Line,
+            x: 0.,
+            y: lines,
+            window: Entity::PLACEHOLDER,
+        });
+        world.run_system_once(scroll).unwrap();
+        world.resource_mut::<Events<MouseWheel>>().clear();
+    }
+
+    /// Scrolls, shapes the buffer as drawing it does, then reads the scroll back
+    fn frame(world: &mut World, delta: Duration) {
+        world
+            .resource_mut::<Time<Real>>()
+            .update_with_duration(delta);
+        world.run_system_once(apply_scroll).unwrap();
+        world
+            .run_system_once(
+                |mut q: Query<EditorBuffer>, mut font_system: ResMut<CosmicFontSystem>| {
+                    for mut buffer in q.iter_mut() {
+                        buffer.shape_until_scroll(&mut font_system.0, false);
+                    }
+                },
+            )
+            .unwrap();
+        world.run_system_once(sync_scroll).unwrap();
+    }
+
+    fn scroll_of(world: &mut World, entity: Entity) -> Mut<'_, CosmicScroll> {
+        world.get_mut::<CosmicScroll>(entity).unwrap()
+    }
+
+    #[test]
+    fn scrolling_to_a_line_or_the_end() {
+        let (mut world, entity) = setup(None);
+        assert_eq!(scroll_of(&mut world, entity).range().max.y, 70.);
+
+        scroll_of(&mut world, entity).scroll_to_end();
+        frame(&mut world, Duration::ZERO);
+        let at_end = scroll_of(&mut world, entity);
+        assert_eq!(at_end.offset().y, 70.);
+        assert!(at_end.is_at_end());
+
+        scroll_of(&mut world, entity).scroll_to_line(2);
+        frame(&mut world, Duration::ZERO);
+        let at_line = scroll_of(&mut world, entity);
+        assert_eq!(at_line.offset().y, 20.);
+        assert!(!at_line.is_at_end());
+    }
+
+    #[test]
+    fn smoothing_eases_towards_the_target() {
+        let (mut world, entity) = setup(Some(Duration::from_millis(100)));
+        let leftover = scroll_of(&mut world, entity).scroll_by(Vec2::new(0., 50.));
+        assert_eq!(leftover, Vec2::ZERO);
+
+        frame(&mut world, Duration::from_millis(10));
+        let moving = scroll_of(&mut world, entity);
+        assert!(moving.offset().y > 0. && moving.offset().y < 50.);
+        assert_eq!(moving.target().y, 50.);
+
+        frame(&mut world, Duration::from_secs(1));
+        assert_eq!(scroll_of(&mut world, entity).offset().y, 50.);
+
+        // past the end, with the rest left over for a container around the widget
+        let leftover = scroll_of(&mut world, entity).scroll_by(Vec2::new(0., 50.));
+        assert_eq!(leftover, Vec2::new(0., 30.));
+        assert_eq!(scroll_of(&mut world, entity).target().y, 70.);
+    }
+
+    #[test]
+    fn unsmoothed_scrolling_is_instant() {
+        let (mut world, entity) = setup(None);
+        scroll_of(&mut world, entity).scroll_by(Vec2::new(0., 50.));
+        frame(&mut world, Duration::from_millis(10));
+        assert_eq!(scroll_of(&mut world, entity).offset().y, 50.);
+    }
+
+    #[test]
+    fn the_wheel_scrolls_the_hovered_widget() {
+        let (mut world, hovered) = setup(None);
+        let focused = spawn_widget(&mut world, None);
+        world.insert_resource(FocusedWidget(Some(focused)));
+        *world.get_mut::<InputState>(hovered).unwrap() = InputState::Hovering;
+
+        wheel(&mut world, -2.);
+        assert_eq!(scroll_of(&mut world, hovered).target().y, 20.);
+        assert_eq!(scroll_of(&mut world, focused).target().y, 0.);
+
+        // not hovering anything
+        *world.get_mut::<InputState>(hovered).unwrap() = InputState::Idle;
+        wheel(&mut world, -2.);
+        assert_eq!(scroll_of(&mut world, hovered).target().y, 20.);
+    }
+
+    #[test]
+    fn leftover_scrolling_scrolls_the_container() {
+        let (mut world, entity) = setup(None);
+        let container = world.spawn(ScrollPosition::default()).id();
+        // something between them that doesn't scroll
+        let node = world.spawn_empty().set_parent(container).id();
+        world.entity_mut(entity).set_parent(node);
+        world
+            .entity_mut(entity)
+            .insert((InputState::Hovering, ScaleFactor(2.)));
+
+        // the widget can scroll 70 pixels, the rest goes to the container in logical pixels
+        wheel(&mut world, -9.);
+        assert_eq!(scroll_of(&mut world, entity).target().y, 70.);
+        assert_eq!(
+            world.get::<ScrollPosition>(container).unwrap().offset_y,
+            10.
+        );
+
+        wheel(&mut world, 1.);
+        assert_eq!(scroll_of(&mut world, entity).target().y, 60.);
+        assert_eq!(
+            world.get::<ScrollPosition>(container).unwrap().offset_y,
+            10.
+        );
+
+        // or all of it when the widget doesn't scroll
+        world.entity_mut(entity).insert(ScrollEnabled(false));
+        wheel(&mut world, 1.);
+        assert_eq!(scroll_of(&mut world, entity).target().y, 60.);
+        assert_eq!(world.get::<ScrollPosition>(container).unwrap().offset_y, 5.);
+    }
+
+    #[test]
+    fn metrics_are_measured_again_when_the_text_changes() {
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(10., 10.));
+        let mut buffer = buffer.borrow_with(&mut font_system);
+        buffer.set_size(Some(100.), Some(20.));
+        buffer.set_text("one\ntwo", Attrs::new(), Shaping::Advanced);
+        let mut measured = MeasuredScroll::default();
+        assert_eq!(measured.get(&mut buffer).content_height, 20.);
+
+        // drawn, then marked for a redraw without changing the text, e.g. by blinking
+        buffer.set_redraw(false);
+        measured.0.as_mut().unwrap().metrics.content_height = 0.;
+        buffer.set_redraw(true);
+        assert_eq!(measured.get(&mut buffer).content_height, 0.);
+
+        // changed without anything that would redraw it
+        buffer.set_redraw(false);
+        let line = buffer.lines[0].clone();
+        buffer.lines.push(line);
+        assert_eq!(measured.get(&mut buffer).content_height, 0.);
+        buffer.set_redraw(true);
+        assert_eq!(measured.get(&mut buffer).content_height, 30.);
+
+        buffer.set_text("one\ntwo\nthree\nfour", Attrs::new(), Shaping::Advanced);
+        // as `CosmicEditBuffer::set_text` does
+        buffer.set_redraw(true);
+        assert_eq!(measured.get(&mut buffer).content_height, 40.);
+        buffer.set_size(Some(100.), Some(10.));
+        buffer.set_redraw(false);
+        assert_eq!(measured.get(&mut buffer).content_height, 40.);
+        assert!(measured
+            .0
+            .is_some_and(|measured| measured.key.size.1 == Some(10.)));
+    }
 }
@@ -76,13 +76,16 @@ pub mod prelude {
     // public internal re-exports
     pub use crate::buffer::CosmicEditBuffer; // todo: migrate to builtin bevy CosmicBuffer
     pub use crate::cosmic_edit::CosmicFontSystem; // todo: migrate to using builtin bevy cosmic font system
-    pub use crate::cosmic_edit::{CosmicWrap, DefaultAttrs, ReadOnly};
+    pub use crate::cosmic_edit::{CosmicPadding, CosmicWrap, DefaultAttrs, ReadOnly};
     pub use crate::cosmic_text::{Color as CosmicColor, Style as FontStyle, Weight as FontWeight};
+    pub use crate::edit_command::EditCommand;
     pub use crate::editor::CosmicEditor;
     pub use crate::editor_buffer::EditorBuffer;
     pub use crate::focus::FocusedWidget;
     pub use crate::input::click::focus_on_click;
     pub use crate::primary::{CosmicEditPlugin, CosmicFontConfig};
+    #[cfg(feature = "3d")]
+    pub use crate::render_implementations::TextEdit3d;
     pub use crate::render_implementations::{TextEdit, TextEdit2d};
     pub use crate::utils::{deselect_editor_on_esc, print_editor_text, ColorExtras as _};
 }
@@ -96,6 +99,7 @@ pub use editor_buffer::{buffer, editor};
 pub use focus::*;
 mod cosmic_edit;
 mod double_click;
+mod edit_log;
 mod editor_buffer;
 pub mod focus;
 mod render;
@@ -107,8 +111,18 @@ pub mod render_implementations;
 pub mod utils;
 
 // extra modules
+pub mod auto_size;
+pub mod background;
+pub mod decorations;
+pub mod dirty_rows;
+pub mod edit_command;
+pub mod glyph_atlas;
+pub mod overlay;
 pub mod password;
 pub mod placeholder;
+pub mod scrollbar;
+pub mod single_line;
+pub mod undo;
 pub mod user_select;
 
 #[cfg(feature = "internal-debugging")]
@@ -0,0 +1,250 @@
+//! The caret and selection, drawn as quads over the text of a widget
+//!
+//! By default the caret and selection are drawn into the image of a widget along with
+//! its text. Add [`OverlayRendering`] to a [`TextEdit`] or [`TextEdit2d`] to instead draw
+//! its text into a separate transparent layer that sits above the selection, so the caret
+//! can blink and the selection change without redrawing any glyphs. Highlights of
+//! [`TextDecorations`] are quads under the selection too.
+//!
+//! The layer and quads are child entities of the widget, and its own image only holds
+//! its background. A UI widget with children isn't measured by its image, so UI widgets
+//! with an [`AutoSize`], along with `TextEdit3d` widgets and [`CosmicRenderTarget`]s,
+//! keep drawing the caret and selection into their image.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::overlay::OverlayRendering;
+//!
+//! # fn setup(mut commands: Commands) {
+//! commands.spawn((
+//!     TextEdit,
+//!     CosmicEditBuffer::default(),
+//!     Node {
+//!         width: Val::Px(300.),
+//!         height: Val::Px(40.),
+//!         ..default()
+//!     },
+//!     OverlayRendering,
+//! ));
+//! # }
+//! ```
+//!
+//! [`CosmicRenderTarget`]: render_implementations::CosmicRenderTarget
+//! [`AutoSize`]: crate::auto_size::AutoSize
+//! [`TextDecorations`]: crate::decorations::TextDecorations
+
+use cosmic_text::Cursor;
+use render_implementations::QuadTargetItem;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{prelude::*, render::TextColors};
+
+/// Draws the caret and selection of a widget as quads over its text,
+/// see the [module docs](self)
+#[derive(Component, Reflect, Default, Debug, Clone, Copy)]
+pub struct OverlayRendering;
+
+/// Whether the caret and selection of a widget can be drawn as quads.
+///
+/// bevy_ui only measures nodes without children, so an [`AutoSize`](crate::auto_size::AutoSize)
+/// UI widget draws them into its image
+pub(crate) fn supports_overlays(target: &QuadTargetItem, auto_sized: bool) -> bool {
+    target.supports_quads() && !(auto_sized && target.is_ui())
+}
+
+/// The text of a widget drawn with overlays, on a transparent background
+#[derive(Component, Default, Debug)]
+pub(crate) struct TextLayer {
+    image: Option<Handle<Image>>,
+}
+
+impl TextLayer {
+    pub fn image(&mut self, images: &mut Assets<Image>) -> Handle<Image> {
+        self.image
+            .get_or_insert_with(|| images.add(Image::default()))
+            .clone_weak()
+    }
+}
+
+/// Calls `fill` with each rectangle, in buffer coordinates, of the caret at `cursor`
+/// and the `selection`, along with its colour and layer
+pub(crate) fn cursor_and_selection(
+    buffer: &Buffer,
+    selection: Option<(Cursor, Cursor)>,
+    cursor: Option<(i32, i32)>,
+    colors: &TextColors,
+    mut fill: impl FnMut(IRect, Color, u8),
+) {
+    if let Some(selection) = selection {
+        for run in buffer.layout_runs() {
+            selection_rects(buffer, &run, selection, |rect| {
+                fill(rect, to_bevy(colors.selection), layer::SELECTION)
+            });
+        }
+    }
+    if let (Some((x, y)), true) = (cursor, colors.cursor.a() > 0) {
+        let line_height = buffer.metrics().line_height as i32;
+        fill(
+            IRect::new(x, y, x + 1, y + line_height),
+            to_bevy(colors.cursor),
+            layer::CURSOR,
+        );
+    }
+}
+
+/// Drawing order of the quads over a widget, matching the CPU renderer
+pub(crate) mod layer {
+    /// [`DecorationStyle::Highlight`](crate::decorations::DecorationStyle::Highlight)s
+    pub const HIGHLIGHTS: u8 = 0;
+    pub const SELECTION: u8 = 1;
+    pub const CURSOR: u8 = 2;
+    pub const GLYPHS: u8 = 3;
+    /// Underlines and strikethroughs of [`TextDecorations`](crate::decorations::TextDecorations)
+    pub const DECORATIONS: u8 = 4;
+    pub const PREEDIT: u8 = 5;
+    /// Each track and thumb gets its own layer from here on
+    pub const SCROLLBARS: u8 = 6;
+}
+
+pub(crate) fn to_bevy(color: cosmic_text::Color) -> Color {
+    Color::srgba_u8(color.r(), color.g(), color.b(), color.a())
+}
+
+/// Calls `fill` with each rectangle, in buffer coordinates, highlighting the selection
+/// between `start` and `end` in `run`, like [`cosmic_text::Editor::draw`] does
+pub(crate) fn selection_rects(
+    buffer: &Buffer,
+    run: &cosmic_text::LayoutRun,
+    (start, end): (Cursor, Cursor),
+    mut fill: impl FnMut(IRect),
+) {
+    let line_i = run.line_i;
+    if line_i < start.line || line_i > end.line {
+        return;
+    }
+    let line_top = run.line_top as i32;
+    let line_height = run.line_height as i32;
+    let mut fill_range = |min: i32, max: i32| {
+        fill(IRect::new(
+            min,
+            line_top,
+            max.max(min),
+            line_top + line_height,
+        ));
+    };
+
+    let mut range_opt: Option<(i32, i32)> = None;
+    for glyph in run.glyphs.iter() {
+        // Guess x offset based on characters
+        let cluster = &run.text[glyph.start..glyph.end];
+        let total = cluster.grapheme_indices(true).count();
+        let mut c_x = glyph.x;
+        let c_w = glyph.w / total as f32;
+        for (i, c) in cluster.grapheme_indices(true) {
+            let c_start = glyph.start + i;
+            let c_end = glyph.start + i + c.len();
+            if (start.line != line_i || c_end > start.index)
+                && (end.line != line_i || c_start < end.index)
+            {
+                range_opt = match range_opt.take() {
+                    Some((min, max)) => Some((min.min(c_x as i32), max.max((c_x + c_w) as i32))),
+                    None => Some((c_x as i32, (c_x + c_w) as i32)),
+                };
+            } else if let Some((min, max)) = range_opt.take() {
+                fill_range(min, max);
+            }
+            c_x += c_w;
+        }
+    }
+
+    let line_width = buffer.size().0.unwrap_or(0.0) as i32;
+    if run.glyphs.is_empty() && end.line > line_i {
+        // Highlight all of internal empty lines
+        range_opt = Some((0, line_width));
+    }
+    if let Some((mut min, mut max)) = range_opt.take() {
+        if end.line > line_i {
+            // Draw to end of line
+            if run.rtl {
+                min = 0;
+            } else {
+                max = line_width;
+            }
+        }
+        fill_range(min, max);
+    }
+}
+
+/// Whether `glyph` in line `line_i` is within the selection
+pub(crate) fn is_selected(
+    line_i: usize,
+    glyph: &cosmic_text::LayoutGlyph,
+    (start, end): (Cursor, Cursor),
+) -> bool {
+    line_i >= start.line
+        && line_i <= end.line
+        && (start.line != line_i || glyph.end > start.index)
+        && (end.line != line_i || glyph.start < end.index)
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmic_text::{Attrs, Metrics, Shaping};
+
+    use super::*;
+    use crate::primary::create_cosmic_font_system;
+
+    #[test]
+    fn selection_and_caret_quads() {
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(20., 20.));
+        buffer.set_size(&mut font_system, Some(200.), Some(100.));
+        buffer.set_text(
+            &mut font_system,
+            "one\ntwo",
+            Attrs::new(),
+            Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut font_system, false);
+        let mut colors = TextColors {
+            font: cosmic_text::Color::rgb(0, 0, 0),
+            cursor: cosmic_text::Color::rgb(0, 0, 255),
+            selection: cosmic_text::Color::rgba(0, 255, 0, 128),
+            selected_text: cosmic_text::Color::rgb(0, 0, 0),
+        };
+        // from after the first character to after the second on the next line,
+        // where the caret is, in a monospace font 12 pixels wide
+        let selection = Some((Cursor::new(0, 1), Cursor::new(1, 2)));
+        let caret = Some((24, 20));
+        let mut quads = Vec::new();
+        cursor_and_selection(&buffer, selection, caret, &colors, |rect, color, layer| {
+            quads.push((rect, color, layer))
+        });
+        let selected = Color::srgba_u8(0, 255, 0, 128);
+        assert_eq!(
+            quads,
+            [
+                // to the end of the line it continues past
+                (IRect::new(12, 0, 200, 20), selected, layer::SELECTION),
+                (IRect::new(0, 20, 24, 40), selected, layer::SELECTION),
+                (
+                    IRect::new(24, 20, 25, 40),
+                    Color::srgb_u8(0, 0, 255),
+                    layer::CURSOR
+                ),
+            ]
+        );
+
+        // a hidden caret, e.g. while blinking
+        colors.cursor = cosmic_text::Color::rgba(0, 0, 255, 0);
+        let mut layers = Vec::new();
+        cursor_and_selection(&buffer, None, caret, &colors, |_, _, layer| {
+            layers.push(layer)
+        });
+        assert!(layers.is_empty());
+    }
+}
@@ -160,7 +160,7 @@ fn hide_password_text(
 }
 
 /// Replaces [`CosmicEditBuffer`] contents with [`Password.real_text`]
-fn restore_password_text(
+pub(crate) fn restore_password_text(
     mut q: Query<(
         &Password,
         &mut CosmicEditBuffer,
@@ -50,6 +50,10 @@ impl Placeholder {
     pub fn is_active(&self) -> bool {
         self.active
     }
+
+    pub(crate) fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
 }
 
 pub(crate) fn plugin(app: &mut App) {
@@ -216,10 +220,7 @@ fn remove_placeholder_on_input(
             return;
         };
 
-        editor.set_cursor(cosmic_text::Cursor::new(
-            (lines - 1).max(0),
-            last_line.bytes().len(),
-        ));
+        editor.set_cursor(cosmic_text::Cursor::new(lines - 1, last_line.len()));
 
         placeholder.active = false;
     }
@@ -19,12 +19,25 @@ impl Plugin for CosmicEditPlugin {
             crate::cosmic_edit::plugin,
             crate::editor_buffer::plugin,
             crate::render::plugin,
+            crate::dirty_rows::plugin,
+            crate::render_implementations::plugin,
             crate::input::plugin,
             crate::focus::plugin,
+        ))
+        .add_plugins((
             crate::placeholder::plugin,
             crate::password::plugin,
             crate::user_select::plugin,
             crate::double_click::plugin,
+            crate::edit_log::plugin,
+            crate::undo::plugin,
+            crate::edit_command::plugin,
+            crate::single_line::plugin,
+            crate::scrollbar::plugin,
+            crate::auto_size::plugin,
+            crate::background::plugin,
+            crate::decorations::plugin,
+            crate::glyph_atlas::plugin,
         ))
         // TODO: Use the builtin bevy CosmicFontSystem
         .insert_resource(crate::cosmic_edit::CosmicFontSystem(font_system));
@@ -77,7 +90,9 @@ fn new_image_from_default(
         .unwrap() = CosmicRenderOutput(default_image);
 }
 
-fn create_cosmic_font_system(cosmic_font_config: CosmicFontConfig) -> cosmic_text::FontSystem {
+pub(crate) fn create_cosmic_font_system(
+    cosmic_font_config: CosmicFontConfig,
+) -> cosmic_text::FontSystem {
     let locale = sys_locale::get_locale().unwrap_or_else(|| String::from("en-US"));
     let mut db = cosmic_text::fontdb::Database::new();
     if let Some(dir_path) = cosmic_font_config.fonts_dir_path.clone() {
@@ -95,11 +110,32 @@ fn create_cosmic_font_system(cosmic_font_config: CosmicFontConfig) -> cosmic_tex
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use bevy::input::keyboard::KeyboardInput;
 
     use super::*;
 
+    /// An app with the whole plugin and no window, with what rendering
+    /// needs from bevy_text and bevy_sprite
+    pub(crate) fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            ImagePlugin::default(),
+            bevy::input::InputPlugin,
+            CosmicEditPlugin {
+                font_config: CosmicFontConfig {
+                    load_system_fonts: false,
+                    ..default()
+                },
+            },
+        ))
+        .init_resource::<bevy::text::SwashCache>()
+        .init_asset::<TextureAtlasLayout>();
+        app
+    }
+
     fn test_spawn_cosmic_edit_system(
         mut commands: Commands,
         mut font_system: ResMut<CosmicFontSystem>,
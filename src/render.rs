@@ -1,8 +1,28 @@
-use crate::{cosmic_edit::ReadOnly, prelude::*};
+use crate::{
+    background::{Background, BackgroundKey, FittedImages},
+    cosmic_edit::ReadOnly,
+    decorations::TextDecorations,
+    dirty_rows::{
+        run_keys, DrawnPixels, GpuRowUploads, RowMask, RowUploads, RunExtras, PIXEL_SIZE,
+    },
+    glyph_atlas::GlyphAtlasRendering,
+    input::{
+        ime::ImePreedit,
+        scroll::{horizontal_extent, MeasuredScroll},
+    },
+    overlay::{
+        cursor_and_selection, is_selected, layer, selection_rects, supports_overlays, to_bevy,
+        OverlayRendering, TextLayer,
+    },
+    prelude::*,
+    scrollbar::{ScrollbarState, Scrollbars},
+};
 use crate::{cosmic_edit::*, BufferMutExtras};
 use bevy::render::render_resource::Extent3d;
-use image::{imageops::FilterType, GenericImageView};
-use render_implementations::CosmicWidgetSize;
+use cosmic_text::Wrap;
+use render_implementations::{
+    CosmicWidgetSize, FixedContentSize, Quad, QuadEntities, QuadTarget, WidgetQuads,
+};
 
 /// System set for cosmic text rendering systems. Runs in [`PostUpdate`]
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
@@ -13,7 +33,12 @@ pub(crate) fn plugin(app: &mut App) {
         First,
         update_internal_target_handles.pipe(render_implementations::debug_error),
     )
-    .add_systems(PostUpdate, (render_texture,).in_set(RenderSet));
+    .add_systems(PostUpdate, (render_texture,).in_set(RenderSet))
+    .register_type::<OverlayRendering>()
+    .register_required_components::<CosmicEditBuffer, RenderedInputs>()
+    .register_required_components::<CosmicEditBuffer, TextLayer>()
+    .register_required_components::<CosmicEditBuffer, DrawnPixels>()
+    .register_required_components::<CosmicEditBuffer, WidgetQuads>();
 }
 
 /// Every frame updates the output (in [`CosmicRenderOutput`]) to its receiver
@@ -68,55 +93,88 @@ fn draw_pixel(
 
     let fg = Srgba::rgba_u8(color.r(), color.g(), color.b(), color.a());
 
-    let premul = (fg * fg.alpha).with_alpha(color.a() as f32 / 255.0);
+    let bg = bg.to_srgba();
 
-    let out = premul + (bg.to_srgba() * (1.0 - fg.alpha));
+    // "over" with straight alpha, so glyphs keep their colour on a transparent background
+    let alpha = fg.alpha + bg.alpha * (1.0 - fg.alpha);
+    let bg_weight = bg.alpha * (1.0 - fg.alpha);
+    let blend =
+        |fg_channel: f32, bg_channel: f32| (fg_channel * fg.alpha + bg_channel * bg_weight) / alpha;
+    let out = Srgba::new(
+        blend(fg.red, bg.red),
+        blend(fg.green, bg.green),
+        blend(fg.blue, bg.blue),
+        alpha,
+    );
 
-    buffer[offset] = (out.red * 255.0) as u8;
-    buffer[offset + 1] = (out.green * 255.0) as u8;
-    buffer[offset + 2] = (out.blue * 255.0) as u8;
-    buffer[offset + 3] = (out.alpha * 255.0) as u8;
+    buffer[offset..offset + 4].copy_from_slice(&out.to_u8_array());
 }
 
 pub(crate) struct WidgetBufferCoordTransformation {
     /// Padding between the top of the render target and the
-    /// top of the buffer
+    /// top of the buffer, in logical pixels
     top_padding: f32,
 
-    render_target_size: Vec2,
+    /// Padding between the left of the render target and the
+    /// left of the buffer, from [`CosmicPadding`], in logical pixels
+    left_padding: f32,
+
+    /// How far the buffer is scrolled to the right,
+    /// only non-zero for [`CosmicWrap::InfiniteLine`]
+    horizontal_scroll: f32,
+
+    /// Physical pixels per logical pixel, see [`ScaleFactor`](render_implementations::ScaleFactor).
+    /// Buffer coordinates are physical
+    scale: f32,
 }
 
 impl WidgetBufferCoordTransformation {
-    pub fn new(vertical_align: VerticalAlign, render_target_size: Vec2, buffer_size: Vec2) -> Self {
-        let top_padding = match vertical_align {
-            VerticalAlign::Top => 0.0,
-            VerticalAlign::Bottom => (render_target_size.y - buffer_size.y).max(0.0),
-            VerticalAlign::Center => ((render_target_size.y - buffer_size.y) / 2.0).max(0.0),
-        };
+    /// `content_rect` is where text goes within the render target,
+    /// see [`CosmicWidgetSizeItem::content_rect`](render_implementations::CosmicWidgetSizeItem::content_rect),
+    /// and `buffer_size` is in physical pixels
+    pub fn new(
+        vertical_align: VerticalAlign,
+        content_rect: Rect,
+        buffer_size: Vec2,
+        horizontal_scroll: f32,
+        scale: f32,
+    ) -> Self {
+        let free_height = content_rect.height() - buffer_size.y / scale;
+        let top_padding = content_rect.min.y
+            + match vertical_align {
+                VerticalAlign::Top => 0.0,
+                VerticalAlign::Bottom => free_height.max(0.0),
+                VerticalAlign::Center => (free_height / 2.0).max(0.0),
+            };
         // debug!(?top_padding, ?render_target_height, ?buffer_height);
         Self {
             top_padding,
-            render_target_size,
+            left_padding: content_rect.min.x,
+            horizontal_scroll,
+            scale,
         }
     }
 
     /// If you have the buffer coord, used for rendering
     // Confusing ngl, but it works
     pub fn buffer_to_widget(&self, buffer: Vec2) -> Vec2 {
-        Vec2::new(buffer.x, buffer.y + self.top_padding)
+        self.buffer_to_physical(buffer) / self.scale
     }
 
-    /// If you have the relative widget coord centered (0, 0) in the middle of the widget,
-    /// returns the buffer coord starting (0, 0) top left and working downward
-    pub fn widget_origined_to_buffer_topleft(&self, widget: Vec2) -> Vec2 {
+    /// Like [`Self::buffer_to_widget`], but in physical pixels from the top left of the widget,
+    /// as its rendered image is
+    pub fn buffer_to_physical(&self, buffer: Vec2) -> Vec2 {
         Vec2::new(
-            widget.x + self.render_target_size.x / 2.,
-            -widget.y + self.render_target_size.y / 2. - self.top_padding,
+            buffer.x - self.horizontal_scroll + self.left_padding * self.scale,
+            buffer.y + self.top_padding * self.scale,
         )
     }
 
     pub fn widget_topleft_to_buffer_topleft(&self, widget: Vec2) -> Vec2 {
-        Vec2::new(widget.x, widget.y - self.top_padding)
+        Vec2::new(
+            (widget.x - self.left_padding) * self.scale + self.horizontal_scroll,
+            (widget.y - self.top_padding) * self.scale,
+        )
     }
 
     #[allow(dead_code)]
@@ -125,48 +183,100 @@ impl WidgetBufferCoordTransformation {
     }
 }
 
-/// Renders to the [CosmicRenderOutput]
+/// Keeps the horizontal scroll of an unwrapped buffer within its text,
+/// e.g. after text was deleted while scrolled to the right.
+///
+/// Lines that overflow a centered or right aligned buffer start at negative x,
+/// so the scroll can be negative too
+fn clamp_horizontal_scroll(buffer: &mut Buffer, width: f32) {
+    let (left, right) = horizontal_extent(buffer).unwrap_or_default();
+    let mut scroll = buffer.scroll();
+    scroll.horizontal = match right - left > width {
+        true => scroll.horizontal.clamp(left, right - width),
+        false => 0.,
+    };
+    if scroll != buffer.scroll() {
+        buffer.set_scroll(scroll);
+    }
+}
+
+/// Redraws the [CosmicRenderOutput] of widgets whose text or [`RenderInputs`] changed,
+/// and places their caret and selection overlays
+#[allow(clippy::too_many_arguments)]
 fn render_texture(
     mut query: Query<(
         EditorBuffer,
         &DefaultAttrs,
-        &CosmicBackgroundImage,
-        &CosmicBackgroundColor,
-        &CursorColor,
-        &SelectionColor,
-        Option<&SelectedTextColor>,
+        Background,
+        (&CursorColor, &SelectionColor, Option<&SelectedTextColor>),
         &CosmicRenderOutput,
         CosmicWidgetSize,
         Option<&ReadOnly>,
         &CosmicTextAlign,
         &CosmicWrap,
+        (Option<&ImePreedit>, Option<&TextDecorations>),
+        Option<(Ref<Scrollbars>, &mut ScrollbarState, &mut MeasuredScroll)>,
+        (
+            Has<GlyphAtlasRendering>,
+            Has<OverlayRendering>,
+            Has<GpuRowUploads>,
+        ),
+        &mut RenderedInputs,
+        (
+            QuadTarget,
+            &mut TextLayer,
+            &mut DrawnPixels,
+            Has<FixedContentSize>,
+        ),
     )>,
+    mut quad_entities: QuadEntities,
     mut font_system: ResMut<CosmicFontSystem>,
     mut images: ResMut<Assets<Image>>,
+    mut fitted_images: ResMut<FittedImages>,
     mut swash_cache_state: ResMut<SwashCache>,
+    time: Res<Time<Real>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut row_uploads: Option<ResMut<RowUploads>>,
+    #[cfg(feature = "internal-debugging")] mut redraws: ResMut<crate::debug::RedrawCounter>,
 ) {
+    let modified_images = modified_images(&mut image_events);
     for (
         mut editor,
         attrs,
-        background_image,
-        fill_color,
-        cursor_color,
-        selection_color,
-        selected_text_color_option,
+        background,
+        (cursor_color, selection_color, selected_text_color_option),
         canvas,
         size,
         readonly_opt,
         text_align,
         wrap,
+        (preedit, decorations),
+        mut scrollbars,
+        (glyph_atlas, overlays, gpu_row_uploads),
+        mut rendered,
+        (mut target, mut text_layer, mut drawn, auto_sized),
     ) in query.iter_mut()
     {
+        // drawn by `glyph_atlas::draw_glyph_quads` instead
+        if glyph_atlas && size.supports_quads() {
+            continue;
+        }
         let font_system = &mut font_system.0;
         let Ok(render_target_size) = size.logical_size() else {
             continue;
         };
+        let Ok(content_rect) = size.content_rect() else {
+            continue;
+        };
+        // the image is rendered at physical resolution and shown at the logical size
+        let scale = size.scale_factor();
+        let Ok(image_size) = size.physical_size() else {
+            continue;
+        };
+        let physical_size = image_size.as_vec2();
 
         // avoids a panic
-        if render_target_size.x == 0. || render_target_size.y == 0. {
+        if image_size.x == 0 || image_size.y == 0 {
             debug!(
                 message = "Size of buffer is zero, skipping",
                 // once = "This log only appears once"
@@ -174,193 +284,863 @@ fn render_texture(
             continue;
         }
 
-        // Draw background
-        let mut pixels = vec![0; render_target_size.x as usize * render_target_size.y as usize * 4];
-        if let Some(bg_image) = background_image.0.clone() {
-            if let Some(image) = images.get(&bg_image) {
-                let mut dynamic_image = image.clone().try_into_dynamic().unwrap();
-                if image.size() != render_target_size.as_uvec2() {
-                    dynamic_image = dynamic_image.resize_to_fill(
-                        render_target_size.x as u32,
-                        render_target_size.y as u32,
-                        FilterType::Triangle,
-                    );
-                }
-                for (i, (_, _, rgba)) in dynamic_image.pixels().enumerate() {
-                    if let Some(p) = pixels.get_mut(i * 4..(i + 1) * 4) {
-                        p[0] = rgba[0];
-                        p[1] = rgba[1];
-                        p[2] = rgba[2];
-                        p[3] = rgba[3];
-                    }
-                }
-            }
-        } else {
-            let bg = fill_color.0.to_cosmic();
-            for pixel in pixels.chunks_exact_mut(4) {
-                pixel[0] = bg.r(); // Red component
-                pixel[1] = bg.g(); // Green component
-                pixel[2] = bg.b(); // Blue component
-                pixel[3] = bg.a(); // Alpha component
-            }
-        }
-
         let font_color = attrs
             .0
             .color_opt
             .unwrap_or(cosmic_text::Color::rgb(0, 0, 0));
 
-        // compute y-offset
-        let buffer_size = editor.borrow_with(font_system).expected_size();
-        let transformation = WidgetBufferCoordTransformation::new(
-            text_align.vertical,
+        let transformation = layout_text(
+            &mut editor,
+            font_system,
+            content_rect,
+            scale,
+            text_align,
+            wrap,
+        );
+        let cursor_visible = match editor.editor() {
+            Some(editor) => editor.cursor_visible && readonly_opt.is_none(),
+            None => {
+                editor.shape_until_scroll(font_system, false);
+                false
+            }
+        };
+        let colors = TextColors::new(
+            font_color,
+            cursor_color,
+            selection_color,
+            selected_text_color_option,
+            cursor_visible,
+        );
+        let (selection, cursor) = editor
+            .editor()
+            .map(|editor| (editor.selection_bounds(), editor.cursor_position()))
+            .unwrap_or_default();
+
+        let overlays = overlays && supports_overlays(&target, auto_sized);
+        let (renderer, text_colors, input_colors) = match overlays {
+            true => {
+                let text_colors = colors.without_overlays();
+                (Renderer::Overlays, text_colors, text_colors)
+            }
+            // blinking only redraws the rows of the caret, see `DrawnPixels::caret_changed`
+            false => (
+                Renderer::Image,
+                colors,
+                TextColors {
+                    cursor: cursor_color.0.to_cosmic(),
+                    ..colors
+                },
+            ),
+        };
+
+        let scrollbars_changed = match scrollbars {
+            Some((ref scrollbars, ref mut state, ref mut measured)) => {
+                let metrics = editor
+                    .borrow_with(font_system)
+                    .with_buffer_mut(|b| measured.get(b));
+                let moved = state.update(
+                    scrollbars,
+                    metrics,
+                    render_target_size,
+                    content_rect.size() * scale,
+                    time.elapsed(),
+                );
+                moved || scrollbars.is_changed()
+            }
+            None => false,
+        };
+        let inputs_changed = rendered.update(RenderInputs::new(
             render_target_size,
-            buffer_size,
+            scale,
+            content_rect,
+            (text_align, wrap),
+            background.key(&images),
+            input_colors,
+            editor.editor().is_some(),
+            preedit,
+            renderer,
+        ));
+        let background_modified = background.image_modified(&modified_images);
+        let background_changed = inputs_changed || background_modified;
+
+        // glyphs are clipped to the padded area
+        let clip = physical_rect(content_rect, scale);
+        if overlays {
+            let text_image = text_layer.image(&mut images);
+            let whole = IRect::from_corners(IVec2::ZERO, image_size.as_ivec2());
+            let mut quads = vec![Quad {
+                rect: whole,
+                color: Color::WHITE,
+                texture: Some((text_image, whole.as_urect())),
+                layer: layer::GLYPHS,
+            }];
+            let mut push = |rect: IRect, color, layer| {
+                let min = transformation
+                    .buffer_to_physical(rect.min.as_vec2())
+                    .as_ivec2();
+                let rect = IRect::from_corners(min, min + rect.size());
+                quads.extend(Quad::fill(rect, color, layer).clipped(clip));
+            };
+            if let Some(decorations) = decorations {
+                for run in editor.layout_runs() {
+                    decorations.highlight_rects(&editor, &run, |rect, color| {
+                        push(rect, to_bevy(color), layer::HIGHLIGHTS)
+                    });
+                }
+            }
+            cursor_and_selection(&editor, selection, cursor, &colors, push);
+            let Ok(()) = target.draw(quads, render_target_size, scale, &mut quad_entities) else {
+                continue;
+            };
+        } else if target.supports_quads() {
+            // hides the overlays of a widget that just stopped using them
+            let Ok(()) = target.draw(Vec::new(), render_target_size, scale, &mut quad_entities)
+            else {
+                continue;
+            };
+        }
+
+        let caret = (!overlays && colors.cursor.a() > 0)
+            .then_some(cursor)
+            .flatten();
+        if !(background_changed
+            || scrollbars_changed
+            || editor.redraw()
+            || drawn.caret_changed(caret))
+        {
+            continue;
+        }
+        editor.set_redraw(false);
+        if overlays && background_changed {
+            let pixels = background.draw(image_size, scale, &images, &mut fitted_images);
+            write_pixels(&mut images, &canvas.0, &pixels, physical_size);
+        }
+        let output = match overlays {
+            true => text_layer.image(&mut images),
+            false => canvas.0.clone_weak(),
+        };
+
+        // moving the caret or selection redraws the buffer, but only changes
+        // the rows they are in, or none at all with overlays
+        let runs = run_keys(
+            &editor,
+            &transformation,
+            RunExtras {
+                selection: selection.filter(|_| !overlays),
+                recolored: selection.filter(|_| text_colors.selected_text != text_colors.font),
+                caret,
+                decorations,
+                highlights: !overlays,
+            },
         );
+        let full_redraw =
+            inputs_changed || scrollbars_changed || (background_modified && !overlays);
+        let dirty = match full_redraw {
+            true => None,
+            false => drawn.dirty_rows(output.id(), image_size, &runs),
+        };
+        if dirty.as_ref().is_some_and(Vec::is_empty) {
+            drawn.keep(runs, caret);
+            continue;
+        }
+        let pixel_count = image_size.x as usize * image_size.y as usize * PIXEL_SIZE;
+        if dirty.is_none() {
+            let new_background = drawn.background_mut();
+            match overlays {
+                true => {
+                    new_background.clear();
+                    new_background.resize(pixel_count, 0);
+                }
+                false => {
+                    *new_background =
+                        background.draw(image_size, scale, &images, &mut fitted_images)
+                }
+            }
+        }
+        // only rows of an image already on the GPU can be uploaded on their own
+        let uploads = match (gpu_row_uploads, &dirty) {
+            (true, Some(_)) => row_uploads.as_deref_mut(),
+            _ => None,
+        };
+        let dirty: Vec<_> = dirty.unwrap_or_else(|| std::iter::once(0..image_size.y).collect());
+        // the rows from the first to the last dirty one
+        let rows = dirty[0].start..dirty[dirty.len() - 1].end;
+        let row_size = image_size.x as usize * PIXEL_SIZE;
+        let first_row = rows.start as i32;
+        let mut uploaded_pixels;
+        let pixels = match uploads {
+            Some(_) => {
+                uploaded_pixels = vec![0; rows.len() * row_size];
+                uploaded_pixels.as_mut_slice()
+            }
+            None => {
+                let Some(image) = images.get_mut(&output) else {
+                    continue;
+                };
+                if image.size() != image_size || image.data.len() != pixel_count {
+                    image.data.resize(pixel_count, 0);
+                    image.resize(Extent3d {
+                        width: image_size.x,
+                        height: image_size.y,
+                        depth_or_array_layers: 1,
+                    });
+                }
+                &mut image.data[rows.start as usize * row_size..rows.end as usize * row_size]
+            }
+        };
+        drawn.clear(pixels, image_size.x, rows.start, &dirty);
+        let pixel_rows = rows.len() as i32;
+        let redrawn_rows = RowMask::new(image_size.y, Some(&dirty));
+        // glyphs can reach a line height past their line, see `DrawnPixels::dirty_rows`
+        let run_redrawn = |run: &cosmic_text::LayoutRun| {
+            let top = transformation
+                .buffer_to_physical(Vec2::new(0., run.line_top))
+                .y;
+            let margin = run.line_height;
+            redrawn_rows.overlaps(
+                (top - margin).floor() as i32..(top + run.line_height + margin).ceil() as i32,
+            )
+        };
 
-        // let mut actually_rendered_max = IVec2::ZERO;
-        // let mut actually_rendered_min = IVec2::new(i32::MAX, i32::MAX);
-        let draw_closure = |x, y, w, h, color| {
+        let mut draw_closure = |x, y, w, h, color| {
             for row in 0..h as i32 {
                 for col in 0..w as i32 {
                     let buffer_coord = IVec2::new(x + col, y + row);
-                    // actually_rendered_max = actually_rendered_max.max(buffer_coord);
-                    // actually_rendered_min = actually_rendered_min.min(buffer_coord);
-
-                    // compute padding_top
                     let widget_coord = transformation
-                        .buffer_to_widget(buffer_coord.as_vec2())
+                        .buffer_to_physical(buffer_coord.as_vec2())
                         .as_ivec2();
 
-                    // actually draw pixel
+                    // the max edge of `clip` is outside of it
+                    let clipped =
+                        widget_coord.cmplt(clip.min).any() || widget_coord.cmpge(clip.max).any();
+                    if clipped || !redrawn_rows.contains(widget_coord.y) {
+                        continue;
+                    }
+
                     draw_pixel(
-                        &mut pixels,
-                        render_target_size.x as i32,
-                        render_target_size.y as i32,
+                        pixels,
+                        image_size.x as i32,
+                        pixel_rows,
                         widget_coord.x,
-                        widget_coord.y,
+                        widget_coord.y - first_row,
                         color,
                     );
                 }
             }
         };
 
-        // BUG: overflow when using center/right/end aligned infinite wrap
-        editor.set_size(
-            font_system,
-            Some(match wrap {
-                CosmicWrap::Wrap => render_target_size.x,
-                // probably high enough
-                CosmicWrap::InfiniteLine => f32::MAX / 10f32.powi(3),
-            }),
-            Some(render_target_size.y),
-        );
-        if let Some(alignment) = text_align.horizontal {
-            for line in &mut editor.lines {
-                line.set_align(Some(alignment.into()));
+        // Highlights go under the glyphs, or are overlays under the selection
+        if let (Some(decorations), false) = (decorations, overlays) {
+            for run in editor.layout_runs().filter(|run| run_redrawn(run)) {
+                decorations.highlight_rects(&editor, &run, |rect, color| {
+                    draw_closure(
+                        rect.min.x,
+                        rect.min.y,
+                        rect.width() as u32,
+                        rect.height() as u32,
+                        color,
+                    )
+                });
             }
         }
 
-        // Draw glyphs
         if let Some(editor) = editor.editor() {
-            // todo: optimizations (see below comments)
-            editor.set_redraw(true);
-            if !editor.redraw() {
-                continue;
-            }
+            editor.borrow_with(font_system).shape_as_needed(false);
+        }
+        draw_text(
+            &editor,
+            font_system,
+            &mut swash_cache_state.0,
+            &text_colors,
+            (selection, cursor),
+            run_redrawn,
+            &mut draw_closure,
+        );
+        if let Some(decorations) = decorations {
+            draw_decoration_lines(&editor, decorations, run_redrawn, &mut draw_closure);
+        }
+        // Underline text that is still being composed with an IME
+        if let Some(preedit) = preedit {
+            preedit_underlines(&editor, preedit, |x, y, w, h| {
+                draw_closure(x, y, w, h, font_color)
+            });
+        }
 
-            let cursor_color = cursor_color.0;
-            let cursor_opacity = if editor.cursor_visible && readonly_opt.is_none() {
-                cursor_color.alpha()
-            } else {
-                0.
-            };
+        if let Some((scrollbars, state, _)) = scrollbars {
+            state.draw(&scrollbars, |rect, color| {
+                let color = color.to_cosmic();
+                let rect = physical_rect(rect, scale);
+                for y in (rect.min.y..rect.max.y).filter(|y| redrawn_rows.contains(*y)) {
+                    for x in rect.min.x..rect.max.x {
+                        draw_pixel(
+                            pixels,
+                            image_size.x as i32,
+                            pixel_rows,
+                            x,
+                            y - first_row,
+                            color,
+                        );
+                    }
+                }
+            });
+        }
+
+        if let Some(uploads) = uploads {
+            uploads.queue(output.id(), image_size.x, pixels, rows.start, &dirty);
+        }
+        drawn.remember(output.id(), image_size, runs, caret);
+        #[cfg(feature = "internal-debugging")]
+        redraws.count();
+    }
+}
+
+/// Images changed since last frame, e.g. a hot reloaded background
+pub(crate) fn modified_images(
+    image_events: &mut EventReader<AssetEvent<Image>>,
+) -> bevy::utils::HashSet<AssetId<Image>> {
+    image_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Everything besides the text a widget is drawn with. Changes to the text,
+/// cursor and selection are tracked by [`Buffer::redraw`] instead
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RenderInputs {
+    pub size: Vec2,
+    pub scale: f32,
+    pub content_rect: Rect,
+    pub vertical_align: VerticalAlign,
+    /// Realigning lines doesn't mark the buffer for a redraw
+    pub horizontal_align: Option<HorizontalAlign>,
+    pub wrap: CosmicWrap,
+    pub background: BackgroundKey,
+    pub colors: TextColors,
+    pub has_editor: bool,
+    pub preedit: Option<(cosmic_text::Cursor, cosmic_text::Cursor)>,
+    /// Switching renderers redraws the widget
+    pub renderer: Renderer,
+}
+
+/// How the text, caret and selection of a widget are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Renderer {
+    /// All into its [`CosmicRenderOutput`]
+    Image,
+    /// The text into a [`TextLayer`], and the caret and selection as quads
+    Overlays,
+    /// See [`GlyphAtlasRendering`]
+    GlyphAtlas,
+}
+
+impl RenderInputs {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        size: Vec2,
+        scale: f32,
+        content_rect: Rect,
+        (text_align, wrap): (&CosmicTextAlign, &CosmicWrap),
+        background: BackgroundKey,
+        colors: TextColors,
+        has_editor: bool,
+        preedit: Option<&ImePreedit>,
+        renderer: Renderer,
+    ) -> Self {
+        Self {
+            size,
+            scale,
+            content_rect,
+            vertical_align: text_align.vertical,
+            horizontal_align: text_align.horizontal,
+            wrap: wrap.clone(),
+            background,
+            colors,
+            has_editor,
+            preedit: preedit.and_then(ImePreedit::spliced_range),
+            renderer,
+        }
+    }
+}
+
+/// The [`RenderInputs`] a widget was last drawn with
+#[derive(Component, Default, Debug)]
+pub(crate) struct RenderedInputs(pub Option<RenderInputs>);
+
+impl RenderedInputs {
+    /// Remembers `inputs`, returning whether they differ from the last ones
+    pub fn update(&mut self, inputs: RenderInputs) -> bool {
+        if self.0.as_ref() == Some(&inputs) {
+            return false;
+        }
+        self.0 = Some(inputs);
+        true
+    }
+}
 
-            let cursor_color = cursor_color.with_alpha(cursor_opacity).to_cosmic();
+/// Updates the stored asset image with the computed pixels
+pub(crate) fn write_pixels(
+    images: &mut Assets<Image>,
+    output: &Handle<Image>,
+    pixels: &[u8],
+    size: Vec2,
+) {
+    if let Some(prev_image) = images.get_mut(output) {
+        if prev_image.size() == size.as_uvec2() && prev_image.data.len() == pixels.len() {
+            prev_image.data.copy_from_slice(pixels);
+            return;
+        }
+        prev_image.data.clear();
+        prev_image.data.extend_from_slice(pixels);
+        prev_image.resize(Extent3d {
+            width: size.x as u32,
+            height: size.y as u32,
+            depth_or_array_layers: 1,
+        });
+    }
+}
 
-            let selection_color = selection_color.0.to_cosmic();
+/// Colors the text, caret and selection of a widget are drawn in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TextColors {
+    pub font: cosmic_text::Color,
+    /// Fully transparent while the caret is hidden
+    pub cursor: cosmic_text::Color,
+    pub selection: cosmic_text::Color,
+    pub selected_text: cosmic_text::Color,
+}
 
-            let selected_text_color = selected_text_color_option
+impl TextColors {
+    pub fn new(
+        font: cosmic_text::Color,
+        cursor: &CursorColor,
+        selection: &SelectionColor,
+        selected_text: Option<&SelectedTextColor>,
+        cursor_visible: bool,
+    ) -> Self {
+        let cursor_opacity = match cursor_visible {
+            true => cursor.alpha(),
+            false => 0.,
+        };
+        Self {
+            font,
+            cursor: cursor.with_alpha(cursor_opacity).to_cosmic(),
+            selection: selection.0.to_cosmic(),
+            selected_text: selected_text
                 .map(|selected_text_color| selected_text_color.0.to_cosmic())
-                .unwrap_or(font_color);
-
-            // try to fix annoying scroll behaviour
-            // by only allowing vertical scrolling if the buffer is actually larger than the canvas
-            // let mut scroll = editor.with_buffer(|b| b.scroll());
-            // if buffer_size.y + 10.0 < render_target_size.y {
-            //     trace!(
-            //         message = "Ignoring vertical scroll as buffer is smaller than canvas",
-            //         ?buffer_size.y,
-            //         ?render_target_size.y
-            //     );
-            //     scroll.vertical = 0.0;
-            // }
-            // editor.with_buffer_mut(|b| b.set_scroll(scroll));
-
-            // let new_buffer_size = editor.expected_size();
-
-            let mut editor = editor.borrow_with(font_system);
-            editor.shape_as_needed(false);
-            editor.draw(
-                &mut swash_cache_state.0,
-                font_color,
-                cursor_color,
-                selection_color,
-                selected_text_color,
-                draw_closure,
-            );
+                .unwrap_or(font),
+        }
+    }
 
-            // if coord calculations seem to be buggy, this code may help you to debug
-            // let actually_rendered_buffer_size = actually_rendered_max - actually_rendered_min;
-            // trace!(
-            //     ?buffer_size,
-            //     ?new_buffer_size,
-            //     ?actually_rendered_buffer_size
-            // );
-            // transformation.debug_top_padding();
-            // debug check only
-            // if (new_buffer_size.as_ivec2() - actually_rendered_buffer_size)
-            //     .as_vec2()
-            //     .length()
-            //     > 5.0
-            // {
-            //     warn_once!(
-            //         message = "Calculations of buffer sizes are off by a significant amount",
-            //         note = "This is likely an internal bug with bevy_cosmic_edit"
-            //     );
-            // }
-
-            // PERF: Read all possible render-input changes and only redraw if necessary
-            // editor.set_redraw(false);
-        } else {
-            // todo: performance optimizations (see comments above/below)
-            editor.set_redraw(true);
-            if !editor.redraw() {
-                continue;
+    /// For text drawn under overlays, which show the caret and selection instead
+    pub fn without_overlays(self) -> Self {
+        let transparent = cosmic_text::Color::rgba(0, 0, 0, 0);
+        Self {
+            cursor: transparent,
+            selection: transparent,
+            ..self
+        }
+    }
+}
+
+/// Lays the text out to fit `content_rect` at `scale`, scrolling it to the cursor,
+/// and returns where the laid out buffer sits within the widget
+pub(crate) fn layout_text(
+    editor: &mut crate::EditorBufferItem,
+    font_system: &mut cosmic_text::FontSystem,
+    content_rect: Rect,
+    scale: f32,
+    text_align: &CosmicTextAlign,
+    wrap: &CosmicWrap,
+) -> WidgetBufferCoordTransformation {
+    let content_size = content_rect.size() * scale;
+    let layout_width = match wrap {
+        CosmicWrap::Wrap => content_size.x,
+        // leaves room for the caret after the last glyph when scrolled to the end
+        CosmicWrap::InfiniteLine => (content_size.x - 1.).max(0.),
+    };
+    editor.set_size(font_system, Some(layout_width), Some(content_size.y));
+    match wrap {
+        CosmicWrap::Wrap => {
+            editor.set_wrap(font_system, Wrap::WordOrGlyph);
+            let mut scroll = editor.scroll();
+            if scroll.horizontal != 0. {
+                scroll.horizontal = 0.;
+                editor.set_scroll(scroll);
             }
+        }
+        // scrolls horizontally to follow the cursor instead
+        CosmicWrap::InfiniteLine => editor.set_wrap(font_system, Wrap::None),
+    }
+    if let Some(alignment) = text_align.horizontal {
+        for line in &mut editor.lines {
+            line.set_align(Some(alignment.into()));
+        }
+    }
 
-            // editor.borrow_with(font_system).compute_everything();
-            editor.shape_until_scroll(font_system, false);
-            editor.draw(
-                font_system,
-                &mut swash_cache_state.0,
-                font_color,
-                draw_closure,
-            );
+    // scrolls to the cursor before the transformation is computed
+    if let Some(editor) = editor.editor() {
+        editor.borrow_with(font_system).shape_as_needed(false);
+    }
 
-            // PERF: Read all possible render-input changes and only redraw if necessary
-            // buffer.set_redraw(false);
-        }
+    // compute y-offset
+    let buffer_size = editor.borrow_with(font_system).expected_size();
+    if *wrap == CosmicWrap::InfiniteLine {
+        clamp_horizontal_scroll(editor, layout_width);
+    }
+    WidgetBufferCoordTransformation::new(
+        text_align.vertical,
+        content_rect,
+        buffer_size,
+        editor.scroll().horizontal,
+        scale,
+    )
+}
 
-        if let Some(prev_image) = images.get_mut(&canvas.0) {
-            prev_image.data.clear();
-            // Updates the stored asset image with the computed pixels
-            prev_image.data.extend_from_slice(pixels.as_slice());
-            prev_image.resize(Extent3d {
-                width: render_target_size.x as u32,
-                height: render_target_size.y as u32,
-                depth_or_array_layers: 1,
+/// `rect` in logical pixels, scaled to the physical pixels of a rendered image
+pub(crate) fn physical_rect(rect: Rect, scale: f32) -> IRect {
+    IRect::from_corners(
+        (rect.min * scale).round().as_ivec2(),
+        (rect.max * scale).round().as_ivec2(),
+    )
+}
+
+/// Draws the glyphs, selection and caret of the layout runs of `buffer` that are `redrawn`
+/// with `draw`, which takes a rectangle in buffer coordinates,
+/// like [`cosmic_text::Editor::draw`] does for all of them
+fn draw_text(
+    buffer: &Buffer,
+    font_system: &mut cosmic_text::FontSystem,
+    swash_cache: &mut cosmic_text::SwashCache,
+    colors: &TextColors,
+    (selection, cursor): (
+        Option<(cosmic_text::Cursor, cosmic_text::Cursor)>,
+        Option<(i32, i32)>,
+    ),
+    redrawn: impl Fn(&cosmic_text::LayoutRun) -> bool,
+    mut draw: impl FnMut(i32, i32, u32, u32, cosmic_text::Color),
+) {
+    for run in buffer.layout_runs().filter(|run| redrawn(run)) {
+        if let Some(selection) = selection {
+            selection_rects(buffer, &run, selection, |rect| {
+                draw(
+                    rect.min.x,
+                    rect.min.y,
+                    rect.width() as u32,
+                    rect.height() as u32,
+                    colors.selection,
+                )
             });
         }
+        if let Some((x, y)) = cursor.filter(|(_, y)| *y == run.line_top as i32) {
+            draw(x, y, 1, run.line_height as u32, colors.cursor);
+        }
+        for glyph in run.glyphs.iter() {
+            let physical = glyph.physical((0., 0.), 1.0);
+            let selected = colors.selected_text != colors.font
+                && selection.is_some_and(|selection| is_selected(run.line_i, glyph, selection));
+            let color = match (selected, glyph.color_opt) {
+                (true, _) => colors.selected_text,
+                (false, Some(color)) => color,
+                (false, None) => colors.font,
+            };
+            swash_cache.with_pixels(font_system, physical.cache_key, color, |x, y, color| {
+                draw(
+                    physical.x + x,
+                    run.line_y as i32 + physical.y + y,
+                    1,
+                    1,
+                    color,
+                )
+            });
+        }
+    }
+}
+
+/// Draws the underlines and strikethroughs of `decorations` in the `redrawn` layout runs
+/// with `draw`, which takes a rectangle in buffer coordinates like [`Buffer::draw`]
+fn draw_decoration_lines(
+    buffer: &Buffer,
+    decorations: &TextDecorations,
+    redrawn: impl Fn(&cosmic_text::LayoutRun) -> bool,
+    mut draw: impl FnMut(i32, i32, u32, u32, cosmic_text::Color),
+) {
+    for run in buffer.layout_runs().filter(|run| redrawn(run)) {
+        decorations.line_rects(&run, |rect, color| {
+            draw(
+                rect.min.x,
+                rect.min.y,
+                rect.width() as u32,
+                rect.height() as u32,
+                color,
+            )
+        });
+    }
+}
+
+/// Calls `underline` with the rectangle, in buffer coordinates, under each glyph
+/// that is still being composed with an IME
+pub(crate) fn preedit_underlines(
+    buffer: &Buffer,
+    preedit: &ImePreedit,
+    mut underline: impl FnMut(i32, i32, u32, u32),
+) {
+    let Some((start, end)) = preedit.spliced_range() else {
+        return;
+    };
+    for run in buffer.layout_runs() {
+        let thickness = (run.line_height / 16.).max(1.);
+        for glyph in run.glyphs.iter() {
+            let after_start = (run.line_i, glyph.start) >= (start.line, start.index);
+            let before_end = (run.line_i, glyph.end) <= (end.line, end.index);
+            if after_start && before_end {
+                underline(
+                    glyph.x as i32,
+                    (run.line_y + thickness) as i32,
+                    glyph.w.ceil() as u32,
+                    thickness as u32,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmic_text::{Attrs, Metrics, Shaping, SwashCache};
+
+    use super::*;
+    use crate::{
+        input::scroll::CosmicScroll,
+        primary::{create_cosmic_font_system, tests::headless_app},
+    };
+
+    /// Times the image of a widget was drawn
+    #[derive(Resource, Default)]
+    struct Redraws(usize);
+
+    fn count_redraws(
+        q: Query<&CosmicRenderOutput>,
+        mut image_events: EventReader<AssetEvent<Image>>,
+        mut redraws: ResMut<Redraws>,
+    ) {
+        let modified = modified_images(&mut image_events);
+        redraws.0 += q
+            .iter()
+            .filter(|output| modified.contains(&output.id()))
+            .count();
+    }
+
+    /// A sprite widget in an app with the whole plugin
+    fn app() -> (App, Entity) {
+        let mut app = headless_app();
+        app.init_resource::<Redraws>()
+            .add_systems(Last, count_redraws.after(bevy::asset::AssetEvents));
+        let world = app.world_mut();
+        let mut font_system = world.resource_mut::<CosmicFontSystem>();
+        // more lines than fit, to scroll through
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(20., 20.)).with_text(
+            &mut font_system,
+            "one\ntwo\nthree\nfour",
+            Attrs::new(),
+        );
+        let entity = world
+            .spawn((
+                TextEdit2d,
+                buffer,
+                Sprite {
+                    custom_size: Some(Vec2::new(200., 40.)),
+                    ..default()
+                },
+            ))
+            .id();
+        // the first frame draws the widget
+        app.update();
+        app.world_mut().resource_mut::<Redraws>().0 = 0;
+        (app, entity)
+    }
+
+    /// Runs a frame, returning the number of redraws in it
+    fn frame(app: &mut App) -> usize {
+        app.update();
+        std::mem::take(&mut app.world_mut().resource_mut::<Redraws>().0)
+    }
+
+    #[test]
+    fn unchanged_widgets_are_not_redrawn() {
+        let (mut app, _) = app();
+        for _ in 0..3 {
+            assert_eq!(frame(&mut app), 0);
+        }
+    }
+
+    #[test]
+    fn colors_redraw() {
+        let (mut app, entity) = app();
+        app.world_mut()
+            .entity_mut(entity)
+            .insert(CosmicBackgroundColor(Color::BLACK));
+        assert_eq!(frame(&mut app), 1);
+        assert_eq!(frame(&mut app), 0);
+    }
+
+    #[test]
+    fn scrolling_redraws() {
+        let (mut app, entity) = app();
+        let mut scroll = app.world_mut().get_mut::<CosmicScroll>(entity).unwrap();
+        scroll.smoothing = None;
+        scroll.scroll_to_end();
+        assert_eq!(frame(&mut app), 1);
+        assert_eq!(frame(&mut app), 0);
+    }
+
+    #[test]
+    fn resizing_redraws() {
+        let (mut app, entity) = app();
+        app.world_mut()
+            .get_mut::<Sprite>(entity)
+            .unwrap()
+            .custom_size = Some(Vec2::new(300., 40.));
+        assert_eq!(frame(&mut app), 1);
+        assert_eq!(frame(&mut app), 0);
+    }
+
+    #[test]
+    fn focusing_redraws() {
+        let (mut app, entity) = app();
+        app.world_mut().resource_mut::<FocusedWidget>().0 = Some(entity);
+        // the editor is added after rendering, so the caret shows up a frame later
+        assert_eq!(frame(&mut app) + frame(&mut app), 1);
+    }
+
+    #[test]
+    fn scale_factor_changes_redraw() {
+        let (mut app, _) = app();
+        let mut window = Window::default();
+        window.resolution.set_scale_factor_override(Some(2.));
+        app.world_mut().spawn((window, bevy::window::PrimaryWindow));
+        assert_eq!(frame(&mut app), 1);
+        assert_eq!(frame(&mut app), 0);
+    }
+
+    /// `color` drawn over a single `background` pixel
+    fn blend(background: [u8; 4], color: cosmic_text::Color) -> [u8; 4] {
+        let mut pixel = background;
+        draw_pixel(&mut pixel, 1, 1, 0, 0, color);
+        pixel
+    }
+
+    #[test]
+    fn pixels_are_blended_with_straight_alpha() {
+        let red = cosmic_text::Color::rgba(255, 0, 0, 128);
+        // keeps its colour on a transparent background, instead of darkening
+        assert_eq!(blend([0; 4], red), [255, 0, 0, 128]);
+        assert_eq!(blend([0, 0, 255, 255], red), [128, 0, 127, 255]);
+        // partial over partial: alpha is 0.5 + 0.5 * 0.5, and the background
+        // contributes a third of the colour
+        let half_blue = [0, 0, 255, 128];
+        assert_eq!(blend(half_blue, red), [170, 0, 85, 192]);
+        assert_eq!(
+            blend(half_blue, cosmic_text::Color::rgba(255, 0, 0, 255)),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            blend(half_blue, cosmic_text::Color::rgba(255, 0, 0, 0)),
+            half_blue
+        );
+    }
+
+    #[test]
+    fn text_is_inset_by_padding() {
+        let mut app = headless_app();
+        let world = app.world_mut();
+        let mut font_system = world.resource_mut::<CosmicFontSystem>();
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(20., 20.)).with_text(
+            &mut font_system,
+            "Wrapped and clipped in the space left by the padding",
+            Attrs::new(),
+        );
+        let entity = world
+            .spawn((
+                TextEdit2d,
+                buffer,
+                CosmicBackgroundColor(Color::WHITE),
+                CosmicTextAlign::top_left(),
+                CosmicPadding {
+                    left: 30.,
+                    right: 10.,
+                    top: 15.,
+                    bottom: 5.,
+                },
+                Sprite {
+                    custom_size: Some(Vec2::new(200., 60.)),
+                    ..default()
+                },
+            ))
+            .id();
+        app.update();
+
+        let world = app.world();
+        let output = world.get::<CosmicRenderOutput>(entity).unwrap();
+        let image = world.resource::<Assets<Image>>().get(&output.0).unwrap();
+        let width = image.width();
+        // the top left corner is padding
+        let background = &image.data[..PIXEL_SIZE];
+        let inked: Vec<UVec2> = image
+            .data
+            .chunks(PIXEL_SIZE)
+            .enumerate()
+            .filter(|(_, pixel)| pixel != &background)
+            .map(|(i, _)| UVec2::new(i as u32 % width, i as u32 / width))
+            .collect();
+        assert!(!inked.is_empty());
+        assert!(
+            inked
+                .iter()
+                .all(|pixel| (30..190).contains(&pixel.x) && (15..55).contains(&pixel.y)),
+            "{inked:?}"
+        );
+        // the text starts at the top left of the content
+        let min = inked.iter().copied().reduce(UVec2::min).unwrap();
+        assert!(min.x < 35 && min.y < 25, "{min}");
+    }
+
+    #[test]
+    fn only_redrawn_runs_are_drawn() {
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(20., 20.));
+        buffer.set_size(&mut font_system, Some(200.), Some(100.));
+        buffer.set_text(
+            &mut font_system,
+            "first\nsecond",
+            Attrs::new(),
+            Shaping::Advanced,
+        );
+        buffer.shape_until_scroll(&mut font_system, false);
+        let colors = TextColors {
+            font: cosmic_text::Color::rgb(0, 0, 0),
+            cursor: cosmic_text::Color::rgb(0, 0, 255),
+            selection: cosmic_text::Color::rgb(0, 255, 0),
+            selected_text: cosmic_text::Color::rgb(0, 0, 0),
+        };
+        let mut rows = Vec::new();
+        draw_text(
+            &buffer,
+            &mut font_system,
+            &mut SwashCache::new(),
+            &colors,
+            (None, Some((0, 0))),
+            |run| run.line_i == 1,
+            |_, y, _, h, _| rows.push(y..y + h as i32),
+        );
+        assert!(!rows.is_empty());
+        // the caret is on the first line, which isn't redrawn
+        assert!(rows.iter().all(|rows| rows.start >= 20), "{rows:?}");
     }
 }
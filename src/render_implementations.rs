@@ -10,6 +10,21 @@
 //!
 //! ## UI: [`TextEdit`]
 //! Requires [`ImageNode`] for rendering
+//!
+//! ## 3D: `TextEdit3d`
+//! Needs the `3d` feature. Requires a `Mesh3d` with UVs and renders into the texture
+//! of its `StandardMaterial`
+//!
+//! ## Your own: [`CosmicRenderTarget`]
+//! Anything else, registered with [`CosmicRenderTargetAppExt::add_cosmic_render_target`]
+//!
+//! ## Reading the image back
+//! The [`Image`] asset shown by each target is kept up to date, even when only the
+//! rows of some lines are redrawn. Widgets with [`GpuRowUploads`] write those rows
+//! straight to the GPU texture instead, leaving the asset with the pixels of their
+//! last full redraw.
+//!
+//! [`GpuRowUploads`]: crate::dirty_rows::GpuRowUploads
 // TODO: Remove `CosmicWidgetSize`?
 
 mod prelude {
@@ -45,10 +60,28 @@ mod error {
         /// When using [`SourceType::Sprite`], you must set [`Sprite.custom_size`]
         SpriteCustomSizeNotSet,
 
-        SpriteUnexpectedNormal,
+        /// When a [`SourceType::Sprite`] or [`SourceType::Mesh`] is scaled to zero
+        /// along an axis, so a hit on it can't be mapped back onto it
+        DegenerateTransform,
+
+        /// When a point on a [`SourceType::Mesh`] couldn't be mapped to its UVs,
+        /// e.g. because the mesh hasn't loaded yet
+        MeshUvNotFound,
+
+        /// When the pointer's ray from the camera never crosses a
+        /// [`SourceType::Sprite`] or [`SourceType::Mesh`]
+        PointerRayMissed,
+
+        /// When a [`CosmicRenderTarget`] couldn't report its size
+        /// or map a hit, or the operation isn't supported for it
+        CustomTargetUnavailable,
 
         SpriteExpectedHitdataPosition,
 
+        /// When projecting a [`SourceType::Sprite`] or [`SourceType::Mesh`] onto the window,
+        /// no active [`Camera`](bevy::prelude::Camera) could see it
+        SpriteNotVisibleFromCamera,
+
         UiExpectedCursorPosition,
     }
 
@@ -69,9 +102,33 @@ pub(crate) use widget_size::*;
 mod widget_size;
 pub(crate) use scan::*;
 mod scan;
+pub(crate) use quads::*;
+mod quads;
+#[cfg(feature = "3d")]
+pub use mesh::TextEdit3d;
+#[cfg(feature = "3d")]
+pub(crate) use mesh::*;
+#[cfg(feature = "3d")]
+mod mesh;
+pub(crate) use custom::*;
+pub use custom::{CosmicRenderTarget, CosmicRenderTargetAppExt};
+mod custom;
+pub(crate) use scale_factor::*;
+mod scale_factor;
+
+use bevy::{
+    ecs::{component::ComponentId, world::DeferredWorld},
+    ui::widget::NodeImageMode,
+};
 
 use crate::prelude::*;
 
+pub(crate) fn plugin(app: &mut App) {
+    app.add_plugins(scale_factor::plugin);
+    #[cfg(feature = "3d")]
+    app.add_plugins(mesh::plugin);
+}
+
 /// The top level UI text edit component
 ///
 /// Adding [`TextEdit`] will pull in the required components for setting up
@@ -79,10 +136,24 @@ use crate::prelude::*;
 ///
 /// Hopefully this API will eventually mirror [`bevy::prelude::Text`].
 /// See [`CosmicEditBuffer`] for more information.
+///
+/// Size it with its [`Node`], or with [`AutoSize`](crate::auto_size::AutoSize).
+/// Its image is rendered at physical resolution, so it isn't used to measure the node
 #[derive(Component)]
 #[require(ImageNode, Button, bevy::ui::RelativeCursorPosition, CosmicEditBuffer)]
+#[component(on_add = stretch_image_node)]
 pub struct TextEdit;
 
+/// Stops bevy_ui from measuring UI widgets by their rendered image,
+/// which is the size of the widget last frame, in physical pixels
+fn stretch_image_node(mut world: DeferredWorld, entity: Entity, _: ComponentId) {
+    if let Some(mut image_node) = world.get_mut::<ImageNode>(entity) {
+        if matches!(image_node.image_mode, NodeImageMode::Auto) {
+            image_node.image_mode = NodeImageMode::Stretch;
+        }
+    }
+}
+
 /// The top-level 2D text edit component
 ///
 /// Adding [`TextEdit2d`] will pull in the required components for setting up
@@ -1,10 +1,13 @@
 use bevy::ecs::query::QueryData;
 use bevy::picking::backend::HitData;
+use bevy::picking::pointer::Location;
 use bevy::ui::RelativeCursorPosition;
 use render_implementations::prelude::*;
 
 use crate::render::WidgetBufferCoordTransformation;
-use crate::render_implementations::CosmicWidgetSize;
+#[cfg(feature = "3d")]
+use crate::render_implementations::MeshTriangles;
+use crate::render_implementations::{CosmicWidgetSize, CustomTargetState};
 use crate::{prelude::*, CosmicTextAlign};
 
 /// Responsible for translating a world coordinate to a buffer coordinate
@@ -13,8 +16,12 @@ pub(crate) struct RelativeQuery {
     widget_size: CosmicWidgetSize,
     text_align: &'static CosmicTextAlign,
 
-    sprite_global_transform: &'static GlobalTransform,
+    global_transform: &'static GlobalTransform,
+    sprite: Option<&'static Sprite>,
     ui_cursor_position: Option<&'static RelativeCursorPosition>,
+    #[cfg(feature = "3d")]
+    mesh_triangles: Option<&'static MeshTriangles>,
+    custom: Option<&'static CustomTargetState>,
 }
 
 impl<'s> std::ops::Deref for RelativeQueryItem<'s> {
@@ -26,69 +33,501 @@ impl<'s> std::ops::Deref for RelativeQueryItem<'s> {
 }
 
 impl RelativeQueryItem<'_> {
-    pub fn compute_buffer_coord(&self, hit_data: &HitData, buffer_size: Vec2) -> Result<Vec2> {
+    /// Position of a pointer hit relative to the top left of the widget, in logical pixels
+    pub fn compute_widget_coord(&self, hit_data: &HitData) -> Result<Vec2> {
+        let render_target_size = self.widget_size.logical_size()?;
         match self.scan()? {
             SourceType::Sprite => {
-                if hit_data.normal != Some(Vec3::Z) {
-                    warn!(?hit_data, "Normal is not out of screen, skipping");
-                    return Err(RenderTargetError::SpriteUnexpectedNormal);
-                }
-
                 let world_position = hit_data
                     .position
                     .ok_or(RenderTargetError::SpriteExpectedHitdataPosition)?;
-                let RelativeQueryItem {
-                    sprite_global_transform,
-                    text_align,
-                    widget_size,
-                    ..
-                } = self;
-
-                let position_transform =
-                    GlobalTransform::from(Transform::from_translation(world_position));
-                let relative_transform = position_transform.reparented_to(sprite_global_transform);
-                let relative_position = relative_transform.translation.xy();
-
-                let render_target_size = widget_size.logical_size()?;
-                let transformation = WidgetBufferCoordTransformation::new(
-                    text_align.vertical,
-                    render_target_size,
-                    buffer_size,
-                );
-                // .xy swizzle depends on normal vector being perfectly out of screen
-                let buffer_coord =
-                    transformation.widget_origined_to_buffer_topleft(relative_position);
-
-                Ok(buffer_coord)
+                self.sprite_frame(render_target_size)?
+                    .world_to_widget(self.global_transform, world_position)
+            }
+            SourceType::Ui => self.ui_widget_coord(render_target_size),
+            #[cfg(feature = "3d")]
+            SourceType::Mesh => {
+                let world_position = hit_data
+                    .position
+                    .ok_or(RenderTargetError::SpriteExpectedHitdataPosition)?;
+                let local_position = world_to_local(self.global_transform, world_position)?;
+                let uv = self
+                    .mesh_triangles()?
+                    .uv_at(local_position)
+                    .ok_or(RenderTargetError::MeshUvNotFound)?;
+                Ok(uv * render_target_size)
+            }
+            SourceType::Custom => self
+                .custom
+                .and_then(|custom| custom.widget_coord(hit_data))
+                .ok_or(RenderTargetError::CustomTargetUnavailable),
+        }
+    }
+
+    /// Where the pointer is relative to the top left of the widget, in logical pixels,
+    /// even when it has left the widget.
+    ///
+    /// Sprites and meshes cast a ray from `camera`, which should be the camera
+    /// the widget was hit through. Custom targets only know where the pointer
+    /// last was over them
+    pub fn compute_pointer_widget_coord(
+        &self,
+        pointer: &Location,
+        camera: (&Camera, &GlobalTransform),
+    ) -> Result<Vec2> {
+        let render_target_size = self.widget_size.logical_size()?;
+        let ray = || {
+            let (camera, camera_transform) = camera;
+            let viewport_offset = camera
+                .logical_viewport_rect()
+                .map(|rect| rect.min)
+                .unwrap_or_default();
+            let ray = camera
+                .viewport_to_world(camera_transform, pointer.position - viewport_offset)
+                .map_err(|_| RenderTargetError::PointerRayMissed)?;
+            local_ray(self.global_transform, ray)
+        };
+        match self.scan()? {
+            SourceType::Sprite => {
+                let (origin, direction) = ray()?;
+                // the sprite lies on its local z = 0 plane
+                if direction.z == 0. {
+                    return Err(RenderTargetError::PointerRayMissed);
+                }
+                let local_position = origin - direction * (origin.z / direction.z);
+                Ok(self
+                    .sprite_frame(render_target_size)?
+                    .local_to_widget(local_position.xy()))
+            }
+            SourceType::Ui => self.ui_widget_coord(render_target_size),
+            #[cfg(feature = "3d")]
+            SourceType::Mesh => {
+                let (origin, direction) = ray()?;
+                let uv = self
+                    .mesh_triangles()?
+                    .uv_along_ray(origin, direction)
+                    .ok_or(RenderTargetError::PointerRayMissed)?;
+                Ok(uv * render_target_size)
             }
+            SourceType::Custom => self
+                .custom
+                .and_then(CustomTargetState::last_widget_coord)
+                .ok_or(RenderTargetError::CustomTargetUnavailable),
+        }
+    }
+
+    /// `horizontal_scroll` is the buffer's [`Scroll::horizontal`](cosmic_text::Scroll::horizontal)
+    pub fn compute_buffer_coord(
+        &self,
+        hit_data: &HitData,
+        buffer_size: Vec2,
+        horizontal_scroll: f32,
+    ) -> Result<Vec2> {
+        let widget_coord = self.compute_widget_coord(hit_data)?;
+        self.widget_coord_to_buffer(widget_coord, buffer_size, horizontal_scroll)
+    }
+
+    /// Translates a coordinate from [`Self::compute_widget_coord`] to a buffer coordinate
+    pub fn widget_coord_to_buffer(
+        &self,
+        widget_coord: Vec2,
+        buffer_size: Vec2,
+        horizontal_scroll: f32,
+    ) -> Result<Vec2> {
+        let transformation = WidgetBufferCoordTransformation::new(
+            self.text_align.vertical,
+            self.widget_size.content_rect()?,
+            buffer_size,
+            horizontal_scroll,
+            self.widget_size.scale_factor(),
+        );
+
+        Ok(transformation.widget_topleft_to_buffer_topleft(widget_coord))
+    }
+
+    fn ui_widget_coord(&self, render_target_size: Vec2) -> Result<Vec2> {
+        let cursor_position_normalized = self
+            .ui_cursor_position
+            .ok_or(RenderTargetError::required_component_missing::<
+                RelativeCursorPosition,
+            >())?
+            .normalized
+            .ok_or(RenderTargetError::UiExpectedCursorPosition)?;
+
+        Ok(cursor_position_normalized * render_target_size)
+    }
+
+    fn sprite_frame(&self, size: Vec2) -> Result<SpriteFrame> {
+        let sprite = self
+            .sprite
+            .ok_or(RenderTargetError::required_component_missing::<Sprite>())?;
+        Ok(SpriteFrame::new(sprite, size))
+    }
+
+    #[cfg(feature = "3d")]
+    fn mesh_triangles(&self) -> Result<&MeshTriangles> {
+        self.mesh_triangles
+            .ok_or(RenderTargetError::required_component_missing::<MeshTriangles>())
+    }
+
+    /// Where text goes within the widget, in logical pixels from its top left
+    pub fn content_rect(&self) -> Result<Rect> {
+        self.widget_size.content_rect()
+    }
+
+    /// Physical pixels per logical pixel, which buffer coordinates are in
+    pub fn scale_factor(&self) -> f32 {
+        self.widget_size.scale_factor()
+    }
+
+    /// Inverse of [`Self::compute_buffer_coord`], returning logical window coordinates
+    /// (top left origin) for a buffer coordinate.
+    ///
+    /// Sprites and meshes need a `camera` to be projected onto the window.
+    pub fn buffer_coord_to_window(
+        &self,
+        buffer_coord: Vec2,
+        buffer_size: Vec2,
+        horizontal_scroll: f32,
+        camera: Option<(&Camera, &GlobalTransform)>,
+    ) -> Result<Vec2> {
+        let render_target_size = self.widget_size.logical_size()?;
+        let transformation = WidgetBufferCoordTransformation::new(
+            self.text_align.vertical,
+            self.widget_size.content_rect()?,
+            buffer_size,
+            horizontal_scroll,
+            self.widget_size.scale_factor(),
+        );
+        let widget_coord = transformation.buffer_to_widget(buffer_coord);
+        let world_position = match self.scan()? {
+            SourceType::Sprite => self
+                .sprite_frame(render_target_size)?
+                .widget_to_world(self.global_transform, widget_coord),
             SourceType::Ui => {
-                let RelativeQueryItem {
-                    widget_size,
-                    text_align,
-                    ui_cursor_position,
-                    ..
-                } = self;
-                let cursor_position_normalized = ui_cursor_position
-                    .ok_or(RenderTargetError::required_component_missing::<
-                        RelativeCursorPosition,
-                    >())?
-                    .normalized
-                    .ok_or(RenderTargetError::UiExpectedCursorPosition)?;
-
-                let widget_size = widget_size.logical_size()?;
-                let relative_position = cursor_position_normalized * widget_size;
-
-                let transformation = WidgetBufferCoordTransformation::new(
-                    text_align.vertical,
-                    widget_size,
-                    buffer_size,
-                );
-
-                let buffer_coord =
-                    transformation.widget_topleft_to_buffer_topleft(relative_position);
-
-                Ok(buffer_coord)
+                // bevy_ui transforms are in physical pixels, centered on the node
+                let center = self.global_transform.translation().xy()
+                    * self.widget_size.ui_inverse_scale_factor()?;
+                let top_left = center - render_target_size / 2.;
+                return Ok(top_left + widget_coord);
             }
+            #[cfg(feature = "3d")]
+            SourceType::Mesh => {
+                let local_position = self
+                    .mesh_triangles()?
+                    .position_at(widget_coord / render_target_size)
+                    .ok_or(RenderTargetError::MeshUvNotFound)?;
+                self.global_transform.transform_point(local_position)
+            }
+            SourceType::Custom => return Err(RenderTargetError::CustomTargetUnavailable),
+        };
+        let (camera, camera_transform) =
+            camera.ok_or(RenderTargetError::SpriteNotVisibleFromCamera)?;
+        let viewport_position = camera
+            .world_to_viewport(camera_transform, world_position)
+            .map_err(|_| RenderTargetError::SpriteNotVisibleFromCamera)?;
+        let viewport_offset = camera
+            .logical_viewport_rect()
+            .map(|rect| rect.min)
+            .unwrap_or_default();
+        Ok(viewport_position + viewport_offset)
+    }
+}
+
+/// Undoes `transform` exactly, even with rotation, non-uniform scale or mirroring
+fn world_to_local(transform: &GlobalTransform, world_position: Vec3) -> Result<Vec3> {
+    let affine = transform.affine();
+    if affine.matrix3.determinant() == 0. {
+        return Err(RenderTargetError::DegenerateTransform);
+    }
+    Ok(affine.inverse().transform_point3(world_position))
+}
+
+/// Origin and direction of `ray` in the local space of `transform`
+fn local_ray(transform: &GlobalTransform, ray: Ray3d) -> Result<(Vec3, Vec3)> {
+    let affine = transform.affine();
+    if affine.matrix3.determinant() == 0. {
+        return Err(RenderTargetError::DegenerateTransform);
+    }
+    let inverse = affine.inverse();
+    Ok((
+        inverse.transform_point3(ray.origin),
+        inverse.transform_vector3(*ray.direction),
+    ))
+}
+
+/// Maps between the local space of a [`Sprite`], before its [`GlobalTransform`],
+/// and widget coordinates from the top left of its (possibly flipped) image
+#[derive(Debug, Clone, Copy)]
+pub(super) struct SpriteFrame {
+    size: Vec2,
+    /// See [`Anchor::as_vec`](bevy::sprite::Anchor::as_vec)
+    anchor: Vec2,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl SpriteFrame {
+    pub(super) fn new(sprite: &Sprite, size: Vec2) -> Self {
+        Self {
+            size,
+            anchor: sprite.anchor.as_vec(),
+            flip_x: sprite.flip_x,
+            flip_y: sprite.flip_y,
+        }
+    }
+
+    /// The top left of the sprite's quad, which spans from `(-0.5 - anchor) * size`
+    /// to `(0.5 - anchor) * size` with y up
+    fn top_left(&self) -> Vec2 {
+        Vec2::new(-0.5 - self.anchor.x, 0.5 - self.anchor.y) * self.size
+    }
+
+    fn flip(&self, widget: Vec2) -> Vec2 {
+        Vec2::new(
+            match self.flip_x {
+                true => self.size.x - widget.x,
+                false => widget.x,
+            },
+            match self.flip_y {
+                true => self.size.y - widget.y,
+                false => widget.y,
+            },
+        )
+    }
+
+    fn local_to_widget(&self, local: Vec2) -> Vec2 {
+        let top_left = self.top_left();
+        self.flip(Vec2::new(local.x - top_left.x, top_left.y - local.y))
+    }
+
+    pub(super) fn widget_to_local(&self, widget: Vec2) -> Vec2 {
+        let top_left = self.top_left();
+        let widget = self.flip(widget);
+        Vec2::new(top_left.x + widget.x, top_left.y - widget.y)
+    }
+
+    /// Projects `world_position` onto the sprite's plane along its local z axis,
+    /// so any rotation, scale or mirroring is undone exactly
+    fn world_to_widget(&self, transform: &GlobalTransform, world_position: Vec3) -> Result<Vec2> {
+        let local = world_to_local(transform, world_position)?;
+        Ok(self.local_to_widget(local.xy()))
+    }
+
+    fn widget_to_world(&self, transform: &GlobalTransform, widget: Vec2) -> Vec3 {
+        transform.transform_point(self.widget_to_local(widget).extend(0.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use bevy::sprite::Anchor;
+
+    use crate::cosmic_edit::VerticalAlign;
+
+    use super::*;
+
+    const SIZE: Vec2 = Vec2::new(200., 100.);
+
+    fn frame(anchor: Anchor) -> SpriteFrame {
+        SpriteFrame::new(
+            &Sprite {
+                anchor,
+                ..default()
+            },
+            SIZE,
+        )
+    }
+
+    fn assert_close(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-3),
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    /// Where a hit on `widget` lands after going to the world and back
+    fn round_trip(frame: SpriteFrame, transform: Transform, widget: Vec2) -> Vec2 {
+        let transform = GlobalTransform::from(transform);
+        let world = frame.widget_to_world(&transform, widget);
+        frame.world_to_widget(&transform, world).unwrap()
+    }
+
+    #[test]
+    fn centered_sprite() {
+        let frame = frame(Anchor::Center);
+        let transform = GlobalTransform::from_xyz(10., 20., 0.);
+        assert_close(
+            frame
+                .world_to_widget(&transform, Vec3::new(10., 20., 0.))
+                .unwrap(),
+            SIZE / 2.,
+        );
+        assert_close(
+            frame
+                .world_to_widget(&transform, Vec3::new(-90., 70., 0.))
+                .unwrap(),
+            Vec2::ZERO,
+        );
+    }
+
+    #[test]
+    fn every_anchor() {
+        let anchors = [
+            (Anchor::Center, SIZE / 2.),
+            (Anchor::BottomLeft, Vec2::new(0., SIZE.y)),
+            (Anchor::BottomCenter, Vec2::new(SIZE.x / 2., SIZE.y)),
+            (Anchor::BottomRight, SIZE),
+            (Anchor::CenterLeft, Vec2::new(0., SIZE.y / 2.)),
+            (Anchor::CenterRight, Vec2::new(SIZE.x, SIZE.y / 2.)),
+            (Anchor::TopLeft, Vec2::ZERO),
+            (Anchor::TopCenter, Vec2::new(SIZE.x / 2., 0.)),
+            (Anchor::TopRight, Vec2::new(SIZE.x, 0.)),
+            (Anchor::Custom(Vec2::new(0.25, -0.25)), Vec2::new(150., 75.)),
+        ];
+        for (anchor, origin) in anchors {
+            let frame = frame(anchor);
+            // the transform's translation is at the anchor
+            assert_close(
+                frame
+                    .world_to_widget(&GlobalTransform::IDENTITY, Vec3::ZERO)
+                    .unwrap(),
+                origin,
+            );
+            assert_close(
+                frame
+                    .world_to_widget(&GlobalTransform::IDENTITY, Vec3::new(10., -5., 0.))
+                    .unwrap(),
+                origin + Vec2::new(10., 5.),
+            );
         }
     }
+
+    #[test]
+    fn scaled_buffer() {
+        let content = Rect::new(10., 5., 190., 95.);
+        // 40 logical pixels of text, scrolled 30 physical pixels to the right
+        let transformation = WidgetBufferCoordTransformation::new(
+            VerticalAlign::Center,
+            content,
+            Vec2::new(400., 80.),
+            30.,
+            2.,
+        );
+        assert_close(
+            transformation.buffer_to_widget(Vec2::ZERO),
+            Vec2::new(-5., 30.),
+        );
+        assert_close(
+            transformation.buffer_to_physical(Vec2::new(50., 10.)),
+            Vec2::new(40., 70.),
+        );
+        assert_close(
+            transformation.widget_topleft_to_buffer_topleft(Vec2::new(-5., 30.)),
+            Vec2::ZERO,
+        );
+    }
+
+    #[test]
+    fn rotated_sprite() {
+        let frame = frame(Anchor::Center);
+        let transform =
+            GlobalTransform::from(Transform::from_rotation(Quat::from_rotation_z(FRAC_PI_2)));
+        // the top left corner is rotated a quarter turn counter clockwise
+        assert_close(
+            frame
+                .world_to_widget(&transform, Vec3::new(-50., -100., 0.))
+                .unwrap(),
+            Vec2::ZERO,
+        );
+        assert_close(
+            round_trip(
+                frame,
+                Transform::from_rotation(Quat::from_rotation_z(0.3)),
+                Vec2::new(30., 80.),
+            ),
+            Vec2::new(30., 80.),
+        );
+    }
+
+    #[test]
+    fn tilted_out_of_the_screen() {
+        let frame = frame(Anchor::TopLeft);
+        let transform = Transform::from_rotation(Quat::from_rotation_x(FRAC_PI_4));
+        // hits off the sprite's plane are projected onto it
+        let global = GlobalTransform::from(transform);
+        let world = frame.widget_to_world(&global, Vec2::new(40., 60.)) + global.back() * 3.;
+        assert_close(
+            frame.world_to_widget(&global, world).unwrap(),
+            Vec2::new(40., 60.),
+        );
+    }
+
+    #[test]
+    fn non_uniform_scale() {
+        let frame = frame(Anchor::Center);
+        let transform = GlobalTransform::from(Transform::from_scale(Vec3::new(2., 0.5, 1.)));
+        assert_close(
+            frame
+                .world_to_widget(&transform, Vec3::new(-200., 25., 0.))
+                .unwrap(),
+            Vec2::ZERO,
+        );
+        // shears when combined with a rotated parent
+        let parent = Transform::from_rotation(Quat::from_rotation_z(FRAC_PI_4));
+        let child =
+            Transform::from_scale(Vec3::new(3., 0.5, 1.)).with_rotation(Quat::from_rotation_z(0.2));
+        let sheared = GlobalTransform::from(parent) * GlobalTransform::from(child);
+        let world = frame.widget_to_world(&sheared, Vec2::new(120., 30.));
+        assert_close(
+            frame.world_to_widget(&sheared, world).unwrap(),
+            Vec2::new(120., 30.),
+        );
+    }
+
+    #[test]
+    fn mirrored_by_negative_scale() {
+        let frame = frame(Anchor::Center);
+        let transform = GlobalTransform::from(Transform::from_scale(Vec3::new(-1., 1., 1.)));
+        // the image's left edge is now on the right
+        assert_close(
+            frame
+                .world_to_widget(&transform, Vec3::new(100., 50., 0.))
+                .unwrap(),
+            Vec2::ZERO,
+        );
+    }
+
+    #[test]
+    fn flipped_sprite() {
+        let frame = SpriteFrame::new(
+            &Sprite {
+                anchor: Anchor::TopLeft,
+                flip_x: true,
+                flip_y: true,
+                ..default()
+            },
+            SIZE,
+        );
+        assert_close(
+            frame
+                .world_to_widget(&GlobalTransform::IDENTITY, Vec3::new(10., -20., 0.))
+                .unwrap(),
+            Vec2::new(190., 80.),
+        );
+        assert_close(
+            round_trip(frame, Transform::from_xyz(5., 5., 0.), Vec2::new(10., 20.)),
+            Vec2::new(10., 20.),
+        );
+    }
+
+    #[test]
+    fn degenerate_transform() {
+        let transform = GlobalTransform::from(Transform::from_scale(Vec3::new(0., 1., 1.)));
+        assert!(frame(Anchor::Center)
+            .world_to_widget(&transform, Vec3::ZERO)
+            .is_err());
+    }
 }
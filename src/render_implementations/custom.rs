@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    ecs::{
+        query::{QueryData, QueryItem, ReadOnlyQueryData},
+        system::{StaticSystemParam, SystemParam, SystemParamItem},
+    },
+    picking::{
+        backend::HitData,
+        events::pointer_events,
+        focus::{update_focus, HoverMap},
+        PickSet,
+    },
+};
+
+use crate::{prelude::*, render::RenderSet};
+
+/// A render target of your own, for rendering editors into a render-to-texture camera,
+/// a custom material or a third party UI crate.
+///
+/// Implement this for a marker component and register it with
+/// [`add_cosmic_render_target`](CosmicRenderTargetAppExt::add_cosmic_render_target).
+/// Adding the marker then sets up a [`CosmicEditBuffer`], just like [`TextEdit2d`] does.
+///
+/// ```
+/// # use bevy::{ecs::system::SystemParamItem, picking::backend::HitData, prelude::*};
+/// # use bevy_cosmic_edit::prelude::*;
+/// use bevy_cosmic_edit::render_implementations::{CosmicRenderTarget, CosmicRenderTargetAppExt};
+///
+/// /// Shows an editor in a square of a paint canvas
+/// #[derive(Component)]
+/// struct CanvasTextEdit {
+///     size: Vec2,
+/// }
+///
+/// #[derive(Component)]
+/// struct CanvasImage(Handle<Image>);
+///
+/// impl CosmicRenderTarget for CanvasTextEdit {
+///     type Data = &'static GlobalTransform;
+///     type Output = &'static mut CanvasImage;
+///     type Param = ();
+///
+///     fn logical_size(&self, _: &GlobalTransform) -> Option<Vec2> {
+///         Some(self.size)
+///     }
+///
+///     fn hit_to_widget(&self, transform: &GlobalTransform, hit: &HitData) -> Option<Vec2> {
+///         let local = transform.affine().inverse().transform_point3(hit.position?);
+///         Some(Vec2::new(local.x, -local.y) + self.size / 2.)
+///     }
+///
+///     fn write_image(
+///         &self,
+///         mut output: Mut<CanvasImage>,
+///         _: &mut SystemParamItem<()>,
+///         image: &Handle<Image>,
+///     ) {
+///         output.0 = image.clone_weak();
+///     }
+/// }
+///
+/// # fn build(app: &mut App) {
+/// app.add_cosmic_render_target::<CanvasTextEdit>();
+/// # }
+/// ```
+pub trait CosmicRenderTarget: Component + Sized {
+    /// Components read to size the widget and map hits onto it, e.g. its [`GlobalTransform`]
+    type Data: ReadOnlyQueryData;
+    /// Components the rendered image is written into
+    type Output: QueryData;
+    /// Resources needed to write the rendered image, e.g. `ResMut<Assets<MyMaterial>>`
+    type Param: SystemParam;
+
+    /// Size of the rendered image in logical pixels, `None` if it isn't known yet
+    fn logical_size(&self, data: QueryItem<Self::Data>) -> Option<Vec2>;
+
+    /// Position of a pointer hit relative to the top left of the rendered image,
+    /// in logical pixels
+    fn hit_to_widget(&self, data: QueryItem<Self::Data>, hit: &HitData) -> Option<Vec2>;
+
+    /// Shows the rendered image, called every frame as its contents may have changed.
+    ///
+    /// The image is rendered at the scale factor of the primary window,
+    /// so it's that many times the [`logical_size`](Self::logical_size)
+    fn write_image(
+        &self,
+        output: QueryItem<Self::Output>,
+        param: &mut SystemParamItem<Self::Param>,
+        image: &Handle<Image>,
+    );
+}
+
+/// Registers [`CosmicRenderTarget`]s
+pub trait CosmicRenderTargetAppExt {
+    fn add_cosmic_render_target<T: CosmicRenderTarget>(&mut self) -> &mut Self;
+}
+
+impl CosmicRenderTargetAppExt for App {
+    fn add_cosmic_render_target<T: CosmicRenderTarget>(&mut self) -> &mut Self {
+        self.register_required_components::<T, CustomTargetState>()
+            .register_required_components::<T, CosmicEditBuffer>()
+            .add_systems(First, (update_size::<T>, write_image::<T>))
+            .add_systems(
+                PreUpdate,
+                map_hovered_hits::<T>
+                    .in_set(PickSet::Focus)
+                    .after(update_focus)
+                    .before(pointer_events),
+            )
+            .add_systems(PostUpdate, update_size::<T>.before(RenderSet))
+    }
+}
+
+/// How many hits are remembered per widget, so a hit from a press can still
+/// be mapped when the drag it starts is
+const REMEMBERED_HITS: usize = 32;
+
+/// What a [`CosmicRenderTarget`] last reported, for code that can't be generic over it
+#[derive(Component, Default, Debug)]
+pub(crate) struct CustomTargetState {
+    logical_size: Option<Vec2>,
+    /// Most recent first
+    hits: VecDeque<(HitData, Vec2)>,
+}
+
+impl CustomTargetState {
+    pub fn logical_size(&self) -> Option<Vec2> {
+        self.logical_size
+    }
+
+    pub fn widget_coord(&self, hit: &HitData) -> Option<Vec2> {
+        self.hits
+            .iter()
+            .find(|(mapped, _)| mapped == hit)
+            .map(|(_, widget_coord)| *widget_coord)
+    }
+
+    /// Where the pointer was last seen over the widget
+    pub fn last_widget_coord(&self) -> Option<Vec2> {
+        self.hits.front().map(|(_, widget_coord)| *widget_coord)
+    }
+}
+
+fn update_size<T: CosmicRenderTarget>(mut q: Query<(&T, T::Data, &mut CustomTargetState)>) {
+    for (target, data, mut state) in q.iter_mut() {
+        let logical_size = target.logical_size(data);
+        if state.logical_size != logical_size {
+            state.logical_size = logical_size;
+        }
+    }
+}
+
+fn write_image<T: CosmicRenderTarget>(
+    mut q: Query<(&T, &CosmicRenderOutput, T::Output)>,
+    param: StaticSystemParam<T::Param>,
+) {
+    let mut param = param.into_inner();
+    for (target, CosmicRenderOutput(image), output) in q.iter_mut() {
+        target.write_image(output, &mut param, image);
+    }
+}
+
+/// Maps the hits of this frame before picking events are sent with them
+fn map_hovered_hits<T: CosmicRenderTarget>(
+    hover_map: Res<HoverMap>,
+    mut q: Query<(&T, T::Data, &mut CustomTargetState)>,
+) {
+    for hits in hover_map.values() {
+        for (entity, hit) in hits {
+            let Ok((target, data, mut state)) = q.get_mut(*entity) else {
+                continue;
+            };
+            if let Some(index) = state.hits.iter().position(|(mapped, _)| mapped == hit) {
+                // still the latest hit, e.g. for a pointer that hasn't moved
+                if let Some(known) = state.hits.remove(index) {
+                    state.hits.push_front(known);
+                }
+                continue;
+            }
+            if let Some(widget_coord) = target.hit_to_widget(data, hit) {
+                state.hits.push_front((hit.clone(), widget_coord));
+                state.hits.truncate(REMEMBERED_HITS);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primary::tests::headless_app;
+
+    #[derive(Component)]
+    struct TestTarget {
+        size: Vec2,
+    }
+
+    /// What [`TestTarget::write_image`] was given
+    #[derive(Component, Default)]
+    struct Written {
+        image: Option<Handle<Image>>,
+        size: Option<UVec2>,
+    }
+
+    impl CosmicRenderTarget for TestTarget {
+        type Data = ();
+        type Output = &'static mut Written;
+        type Param = Res<'static, Assets<Image>>;
+
+        fn logical_size(&self, _: ()) -> Option<Vec2> {
+            Some(self.size)
+        }
+
+        fn hit_to_widget(&self, _: (), hit: &HitData) -> Option<Vec2> {
+            hit.position.map(|position| position.xy())
+        }
+
+        fn write_image(
+            &self,
+            mut output: Mut<Written>,
+            images: &mut SystemParamItem<Self::Param>,
+            image: &Handle<Image>,
+        ) {
+            output.image = Some(image.clone_weak());
+            output.size = images.get(image).map(Image::size);
+        }
+    }
+
+    #[test]
+    fn targets_receive_the_rendered_image() {
+        let mut app = headless_app();
+        app.add_cosmic_render_target::<TestTarget>();
+        let entity = app
+            .world_mut()
+            .spawn((
+                TestTarget {
+                    size: Vec2::new(120., 40.),
+                },
+                Written::default(),
+            ))
+            .id();
+        // the image is written before it's rendered, so it shows up a frame later
+        app.update();
+        app.update();
+
+        let world = app.world();
+        let written = world.get::<Written>(entity).unwrap();
+        let output = world.get::<CosmicRenderOutput>(entity).unwrap();
+        assert_eq!(written.image.as_ref(), Some(&output.0));
+        assert_eq!(written.size, Some(UVec2::new(120, 40)));
+
+        app.world_mut().get_mut::<TestTarget>(entity).unwrap().size = Vec2::new(60., 20.);
+        app.update();
+        app.update();
+        let written = app.world().get::<Written>(entity).unwrap();
+        assert_eq!(written.size, Some(UVec2::new(60, 20)));
+    }
+}
@@ -0,0 +1,308 @@
+use bevy::{
+    ecs::{component::ComponentId, world::DeferredWorld},
+    render::mesh::{PrimitiveTopology, VertexAttributeValues},
+    utils::HashSet,
+};
+
+use crate::{prelude::*, render::RenderSet};
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_type::<TextEdit3d>()
+        .add_systems(First, cache_mesh_triangles)
+        .add_systems(PostUpdate, write_material_texture.after(RenderSet));
+}
+
+/// The top-level 3D text edit component
+///
+/// Adding [`TextEdit3d`] will pull in the required components for setting up
+/// a text editor on a [`Mesh3d`], e.g. a [`Rectangle`] or any mesh with
+/// [`Mesh::ATTRIBUTE_UV_0`]. The text is rendered into the
+/// [`StandardMaterial::base_color_texture`] of its [`MeshMaterial3d`],
+/// which is an unlit material unless you add your own.
+///
+/// The UVs place the texture on the mesh, with `(0, 0)` at the top left of the text.
+/// Clicking and hovering need a picking backend for meshes, e.g. bevy's `MeshPickingPlugin`.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[require(
+    Mesh3d,
+    MeshMaterial3d<StandardMaterial>,
+    MeshTriangles,
+    CosmicEditBuffer
+)]
+#[component(on_add = add_default_material)]
+pub struct TextEdit3d {
+    /// Size of the texture the text is rendered into, in pixels.
+    ///
+    /// Used like the size of a 2D widget, e.g. to wrap the text
+    pub size: Vec2,
+}
+
+impl TextEdit3d {
+    pub fn new(size: Vec2) -> Self {
+        Self { size }
+    }
+}
+
+/// Without this, every [`TextEdit3d`] would share the default material
+fn add_default_material(mut world: DeferredWorld, entity: Entity, _: ComponentId) {
+    let has_default_material = world
+        .get::<MeshMaterial3d<StandardMaterial>>(entity)
+        .is_some_and(|material| material.0 == Handle::default());
+    if !has_default_material {
+        return;
+    }
+    let material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(StandardMaterial {
+            unlit: true,
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        });
+    if let Some(mut target) = world.get_mut::<MeshMaterial3d<StandardMaterial>>(entity) {
+        target.0 = material;
+    }
+}
+
+/// Points each material's texture at its [`CosmicRenderOutput`], and marks the
+/// material as changed whenever the output is redrawn so it is picked up
+fn write_material_texture(
+    q: Query<(&CosmicRenderOutput, &MeshMaterial3d<StandardMaterial>), With<TextEdit3d>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+) {
+    let modified = crate::render::modified_images(&mut image_events);
+    for (CosmicRenderOutput(output), material) in q.iter() {
+        let Some(current) = materials.get(&material.0) else {
+            continue;
+        };
+        let outdated = current.base_color_texture.as_ref() != Some(output);
+        if outdated || modified.contains(&output.id()) {
+            if let Some(material) = materials.get_mut(&material.0) {
+                material.base_color_texture = Some(output.clone_weak());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: Vec3,
+    uv: Vec2,
+}
+
+/// The triangles of a [`TextEdit3d`]'s mesh, to map between positions and UVs
+#[derive(Component, Default, Debug)]
+pub(crate) struct MeshTriangles {
+    mesh: Option<AssetId<Mesh>>,
+    triangles: Vec<[Vertex; 3]>,
+}
+
+/// Keeps [`MeshTriangles`] in sync with the [`Mesh3d`] of each [`TextEdit3d`]
+fn cache_mesh_triangles(
+    mut q: Query<(&Mesh3d, &mut MeshTriangles)>,
+    meshes: Res<Assets<Mesh>>,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
+) {
+    let modified: HashSet<AssetId<Mesh>> = mesh_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    for (mesh_3d, mut triangles) in q.iter_mut() {
+        let id = mesh_3d.id();
+        if triangles.mesh == Some(id) && !modified.contains(&id) {
+            continue;
+        }
+        if let Some(mesh) = meshes.get(id) {
+            *triangles = MeshTriangles::new(id, mesh);
+        }
+    }
+}
+
+/// How far outside of a triangle a point can be and still count as inside,
+/// to allow for rounding on shared edges
+const EDGE_TOLERANCE: f32 = 1e-4;
+
+impl MeshTriangles {
+    fn new(id: AssetId<Mesh>, mesh: &Mesh) -> Self {
+        let mut triangles = Self {
+            mesh: Some(id),
+            triangles: Vec::new(),
+        };
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            warn!(
+                message =
+                    "Only meshes with a triangle list topology can be used for a `TextEdit3d`"
+            );
+            return triangles;
+        }
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+        )
+        else {
+            warn!(message = "The mesh of a `TextEdit3d` needs positions and UVs");
+            return triangles;
+        };
+        let vertex = |index: usize| {
+            Some(Vertex {
+                position: Vec3::from(*positions.get(index)?),
+                uv: Vec2::from(*uvs.get(index)?),
+            })
+        };
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..positions.len()).collect(),
+        };
+        triangles.triangles = indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                Some([
+                    vertex(triangle[0])?,
+                    vertex(triangle[1])?,
+                    vertex(triangle[2])?,
+                ])
+            })
+            .collect();
+        triangles
+    }
+
+    /// UV of the point on the mesh closest to `point`, in the mesh's local space
+    pub fn uv_at(&self, point: Vec3) -> Option<Vec2> {
+        let candidates = self.triangles.iter().filter_map(|triangle| {
+            let [a, b, c] = triangle.map(|vertex| vertex.position);
+            let normal = (b - a).cross(c - a).try_normalize()?;
+            let weights = barycentric([a, b, c], point)?;
+            Some((triangle, weights, normal.dot(point - a).abs()))
+        });
+        let (triangle, weights) = best_candidate(candidates)?;
+        Some(interpolate(triangle.map(|vertex| vertex.uv), weights))
+    }
+
+    /// UV where a ray, in the mesh's local space, crosses the mesh.
+    ///
+    /// When the ray misses, the UVs of the nearest triangle are extended past
+    /// its edges, so a pointer dragged off a quad keeps moving across the text
+    pub fn uv_along_ray(&self, origin: Vec3, direction: Vec3) -> Option<Vec2> {
+        let candidates = self.triangles.iter().filter_map(|triangle| {
+            let [a, b, c] = triangle.map(|vertex| vertex.position);
+            let normal = (b - a).cross(c - a);
+            let facing = normal.dot(direction);
+            if facing.abs() <= f32::EPSILON {
+                return None;
+            }
+            let distance = normal.dot(a - origin) / facing;
+            if distance < 0. {
+                return None;
+            }
+            let weights = barycentric([a, b, c], origin + direction * distance)?;
+            Some((triangle, weights, distance))
+        });
+        let (triangle, weights) = best_candidate(candidates)?;
+        Some(interpolate(triangle.map(|vertex| vertex.uv), weights))
+    }
+
+    /// Position on the mesh, in its local space, with the given UV
+    pub fn position_at(&self, uv: Vec2) -> Option<Vec3> {
+        let candidates = self.triangles.iter().filter_map(|triangle| {
+            let uvs = triangle.map(|vertex| vertex.uv.extend(0.));
+            let weights = barycentric(uvs, uv.extend(0.))?;
+            Some((triangle, weights, 0.))
+        });
+        let (triangle, weights) = best_candidate(candidates)?;
+        Some(interpolate(triangle.map(|vertex| vertex.position), weights))
+    }
+}
+
+/// Barycentric weights of `point` projected onto the plane of `triangle`,
+/// negative when outside of it
+fn barycentric([a, b, c]: [Vec3; 3], point: Vec3) -> Option<Vec3> {
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let (d00, d01, d11) = (ab.dot(ab), ab.dot(ac), ac.dot(ac));
+    let (d20, d21) = (ap.dot(ab), ap.dot(ac));
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator.abs() <= f32::EPSILON {
+        return None;
+    }
+    let v = (d11 * d20 - d01 * d21) / denominator;
+    let w = (d00 * d21 - d01 * d20) / denominator;
+    Some(Vec3::new(1. - v - w, v, w))
+}
+
+fn interpolate<T>([a, b, c]: [T; 3], weights: Vec3) -> T
+where
+    T: std::ops::Mul<f32, Output = T> + std::ops::Add<Output = T>,
+{
+    a * weights.x + b * weights.y + c * weights.z
+}
+
+/// Picks the nearest triangle containing the point, or if there is none the
+/// triangle the point is least far outside of.
+///
+/// Candidates are a triangle, the point's barycentric weights and its distance
+fn best_candidate<'a>(
+    candidates: impl Iterator<Item = (&'a [Vertex; 3], Vec3, f32)>,
+) -> Option<(&'a [Vertex; 3], Vec3)> {
+    candidates
+        .min_by(|(_, a_weights, a_distance), (_, b_weights, b_distance)| {
+            let a_outside = (-a_weights.min_element() - EDGE_TOLERANCE).max(0.);
+            let b_outside = (-b_weights.min_element() - EDGE_TOLERANCE).max(0.);
+            a_outside
+                .total_cmp(&b_outside)
+                .then(a_distance.total_cmp(b_distance))
+        })
+        .map(|(triangle, weights, _)| (triangle, weights))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rectangle() -> MeshTriangles {
+        MeshTriangles::new(AssetId::default(), &Rectangle::new(2., 1.).into())
+    }
+
+    #[test]
+    fn uv_of_rectangle() {
+        let triangles = rectangle();
+        let uv = triangles.uv_at(Vec3::new(-1., 0.5, 0.)).unwrap();
+        assert!(uv.abs_diff_eq(Vec2::ZERO, 1e-5), "{uv}");
+        let uv = triangles.uv_at(Vec3::new(0.5, -0.25, 0.)).unwrap();
+        assert!(uv.abs_diff_eq(Vec2::new(0.75, 0.75), 1e-5), "{uv}");
+        // points off the mesh's surface are projected onto it
+        let uv = triangles.uv_at(Vec3::new(0., 0., 0.1)).unwrap();
+        assert!(uv.abs_diff_eq(Vec2::splat(0.5), 1e-5), "{uv}");
+    }
+
+    #[test]
+    fn position_of_uv() {
+        let position = rectangle().position_at(Vec2::new(0.25, 1.)).unwrap();
+        assert!(
+            position.abs_diff_eq(Vec3::new(-0.5, -0.5, 0.), 1e-5),
+            "{position}"
+        );
+    }
+
+    #[test]
+    fn ray_past_the_edge() {
+        let triangles = rectangle();
+        let uv = triangles
+            .uv_along_ray(Vec3::new(0.5, 0., 1.), Vec3::NEG_Z)
+            .unwrap();
+        assert!(uv.abs_diff_eq(Vec2::new(0.75, 0.5), 1e-5), "{uv}");
+        // keeps going past the right edge, for drags that leave the mesh
+        let uv = triangles
+            .uv_along_ray(Vec3::new(2., 0., 1.), Vec3::NEG_Z)
+            .unwrap();
+        assert!(uv.abs_diff_eq(Vec2::new(1.5, 0.5), 1e-5), "{uv}");
+        // pointing away from the mesh
+        assert!(triangles
+            .uv_along_ray(Vec3::new(0., 0., 1.), Vec3::Z)
+            .is_none());
+    }
+}
@@ -30,7 +30,10 @@ impl OutputToEntityItem<'_> {
                     .sprite_target
                     .as_mut()
                     .ok_or(RenderTargetError::required_component_missing::<Sprite>())?;
-                sprite.image = image.clone_weak();
+                // avoids marking the sprite as changed every frame
+                if sprite.image != *image {
+                    sprite.image = image.clone_weak();
+                }
                 Ok(())
             }
             SourceType::Ui => {
@@ -38,9 +41,16 @@ impl OutputToEntityItem<'_> {
                     .image_node_target
                     .as_mut()
                     .ok_or(RenderTargetError::required_component_missing::<ImageNode>())?;
-                image_node.image = image.clone_weak();
+                if image_node.image != *image {
+                    image_node.image = image.clone_weak();
+                }
                 Ok(())
             }
+            // materials are assets, see `mesh::write_material_texture`
+            #[cfg(feature = "3d")]
+            SourceType::Mesh => Ok(()),
+            // see `custom::write_image`
+            SourceType::Custom => Ok(()),
         }
     }
 }
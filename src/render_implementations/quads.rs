@@ -0,0 +1,373 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    ecs::{query::QueryData, system::SystemParam},
+    utils::HashMap,
+};
+use render_implementations::prelude::*;
+
+use crate::prelude::*;
+use crate::render_implementations::SpriteFrame;
+
+/// How far apart the layers of quads are along the local z axis of a sprite
+const LAYER_DEPTH: f32 = 1e-3;
+
+/// A rectangle drawn over a widget, filled with a colour or a part of a texture
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Quad {
+    /// In physical pixels from the top left of the widget, like its rendered image
+    pub rect: IRect,
+    /// Tints the texture, if any
+    pub color: Color,
+    /// An image and the part of it in pixels
+    pub texture: Option<(Handle<Image>, URect)>,
+    /// Quads in higher layers are drawn over those in lower ones
+    pub layer: u8,
+}
+
+impl Quad {
+    pub fn fill(rect: IRect, color: Color, layer: u8) -> Self {
+        Self {
+            rect,
+            color,
+            texture: None,
+            layer,
+        }
+    }
+
+    /// Crops the quad, and the part of its texture shown, to `clip`
+    pub fn clipped(mut self, clip: IRect) -> Option<Self> {
+        let rect = self.rect.intersect(clip);
+        if rect.is_empty() {
+            return None;
+        }
+        if let Some((_, part)) = &mut self.texture {
+            let min = part.min + (rect.min - self.rect.min).as_uvec2();
+            *part = URect::from_corners(min, min + rect.size().as_uvec2());
+        }
+        self.rect = rect;
+        Some(self)
+    }
+}
+
+/// Where a quad entity was last placed, to only touch its components when it moves
+#[derive(Debug, Clone, PartialEq)]
+enum Placement {
+    Sprite {
+        translation: Vec3,
+        size: Vec2,
+        flip_x: bool,
+        flip_y: bool,
+    },
+    Ui {
+        left: f32,
+        top: f32,
+        size: Vec2,
+    },
+}
+
+/// Marks an entity drawing one [`Quad`] of its parent widget
+#[derive(Component, Debug)]
+pub(crate) struct WidgetQuad;
+
+#[derive(Debug)]
+struct QuadEntity {
+    entity: Entity,
+    /// `None` while hidden
+    drawn: Option<(Quad, Placement)>,
+}
+
+/// The quad entities of a widget, reused from frame to frame
+#[derive(Component, Default, Debug)]
+pub(crate) struct WidgetQuads {
+    source: Option<SourceType>,
+    entities: Vec<QuadEntity>,
+}
+
+impl WidgetQuads {
+    /// Despawns all quads of the widget
+    pub fn clear(&mut self, commands: &mut Commands) {
+        let entities: Vec<Entity> = self.entities.drain(..).map(|quad| quad.entity).collect();
+        self.source = None;
+        // the widget, and its quads with it, may already be despawned
+        commands.queue(move |world: &mut World| {
+            for entity in entities {
+                if let Ok(entity) = world.get_entity_mut(entity) {
+                    entity.despawn_recursive();
+                }
+            }
+        });
+    }
+}
+
+/// Reaches the quad entities of all widgets
+#[derive(SystemParam)]
+pub(crate) struct QuadEntities<'w, 's> {
+    commands: Commands<'w, 's>,
+    sprites: Query<
+        'w,
+        's,
+        (
+            &'static mut Sprite,
+            &'static mut Transform,
+            &'static mut Visibility,
+        ),
+        (With<WidgetQuad>, Without<Node>, Without<WidgetQuads>),
+    >,
+    nodes: Query<
+        'w,
+        's,
+        (
+            &'static mut Node,
+            &'static mut ImageNode,
+            &'static mut ZIndex,
+            &'static mut Visibility,
+        ),
+        (With<WidgetQuad>, Without<WidgetQuads>),
+    >,
+}
+
+/// Draws [`Quad`]s over a widget as child entities, for [`SourceType::Sprite`]
+/// and [`SourceType::Ui`]
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub(crate) struct QuadTarget {
+    entity: Entity,
+    scan: RenderTypeScan,
+    sprite: Option<Ref<'static, Sprite>>,
+    ui: Option<Ref<'static, ComputedNode>>,
+    quads: &'static mut WidgetQuads,
+}
+
+impl RenderTypeScanItem<'_> {
+    /// Whether [`Quad`]s can be drawn over this kind of widget
+    pub fn supports_quads(&self) -> bool {
+        matches!(self.scan(), Ok(SourceType::Sprite | SourceType::Ui))
+    }
+}
+
+impl QuadTargetItem<'_> {
+    pub fn supports_quads(&self) -> bool {
+        self.scan.supports_quads()
+    }
+
+    pub fn is_ui(&self) -> bool {
+        matches!(self.scan.scan(), Ok(SourceType::Ui))
+    }
+
+    /// Whether the quads need to be placed again, even if they didn't change
+    pub fn placement_changed(&self) -> bool {
+        self.sprite.as_ref().is_some_and(Ref::is_changed)
+            || self.ui.as_ref().is_some_and(Ref::is_changed)
+    }
+
+    /// Shows `quads` over the widget of logical `size`, in their order within each layer,
+    /// and hides any quads left over from before.
+    ///
+    /// Quads are scaled down by `scale` to logical pixels
+    pub fn draw(
+        &mut self,
+        mut quads: Vec<Quad>,
+        size: Vec2,
+        scale: f32,
+        entities: &mut QuadEntities,
+    ) -> Result<()> {
+        let source = self.scan.scan()?;
+        if self.quads.source != Some(source) {
+            self.quads.clear(&mut entities.commands);
+            self.quads.source = Some(source);
+        }
+        quads.sort_by_key(|quad| quad.layer);
+
+        let place = |quad: &Quad| -> Result<Placement> {
+            let rect = quad.rect.as_rect();
+            let (min, quad_size) = (rect.min / scale, rect.size() / scale);
+            match source {
+                SourceType::Sprite => {
+                    let sprite = self
+                        .sprite
+                        .as_deref()
+                        .ok_or(RenderTargetError::required_component_missing::<Sprite>())?;
+                    let center =
+                        SpriteFrame::new(sprite, size).widget_to_local(min + quad_size / 2.);
+                    Ok(Placement::Sprite {
+                        translation: center.extend(LAYER_DEPTH * (quad.layer as f32 + 1.)),
+                        size: quad_size,
+                        flip_x: sprite.flip_x,
+                        flip_y: sprite.flip_y,
+                    })
+                }
+                SourceType::Ui => {
+                    let ui = self
+                        .ui
+                        .as_deref()
+                        .ok_or(RenderTargetError::required_component_missing::<ComputedNode>())?;
+                    // absolutely positioned children start inside the border
+                    let border = ui.border();
+                    let inverse_scale = ui.inverse_scale_factor();
+                    Ok(Placement::Ui {
+                        left: min.x - border.left * inverse_scale,
+                        top: min.y - border.top * inverse_scale,
+                        size: quad_size,
+                    })
+                }
+                _ => Err(RenderTargetError::NoTargetsAvailable),
+            }
+        };
+
+        let placed = quads
+            .into_iter()
+            .map(|quad| Ok((place(&quad)?, quad)))
+            .collect::<Result<Vec<_>>>()?;
+        // quads keep to the entities that showed their layer last time,
+        // so e.g. a blinking caret doesn't move every glyph to another entity
+        let mut by_layer: HashMap<u8, VecDeque<usize>> = HashMap::new();
+        for (index, existing) in self.quads.entities.iter().enumerate() {
+            if let Some((quad, _)) = &existing.drawn {
+                by_layer.entry(quad.layer).or_default().push_back(index);
+            }
+        }
+        let mut used = vec![false; self.quads.entities.len()];
+        let mut slots: Vec<Option<usize>> = placed
+            .iter()
+            .map(|(_, quad)| {
+                let index = by_layer.get_mut(&quad.layer)?.pop_front()?;
+                used[index] = true;
+                Some(index)
+            })
+            .collect();
+        let mut spare = (0..used.len())
+            .filter(|index| !used[*index])
+            .collect::<VecDeque<_>>();
+        for slot in slots.iter_mut().filter(|slot| slot.is_none()) {
+            *slot = spare.pop_front();
+        }
+
+        for ((placement, quad), slot) in placed.into_iter().zip(slots) {
+            match slot {
+                Some(index) => {
+                    let existing = &mut self.quads.entities[index];
+                    if existing.drawn.as_ref() == Some(&(quad.clone(), placement.clone())) {
+                        continue;
+                    }
+                    entities.update(existing.entity, &quad, &placement);
+                    existing.drawn = Some((quad, placement));
+                }
+                None => {
+                    let entity = entities.spawn(self.entity, &quad, &placement);
+                    self.quads.entities.push(QuadEntity {
+                        entity,
+                        drawn: Some((quad, placement)),
+                    });
+                }
+            }
+        }
+        for index in spare {
+            let unused = &mut self.quads.entities[index];
+            if unused.drawn.take().is_some() {
+                entities.hide(unused.entity);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl QuadEntities<'_, '_> {
+    fn sprite(quad: &Quad, size: Vec2, flip_x: bool, flip_y: bool) -> Sprite {
+        let (image, rect) = match &quad.texture {
+            Some((image, part)) => (image.clone_weak(), Some(part.as_rect())),
+            // the default image is white, so this is a plain fill
+            None => (Handle::default(), None),
+        };
+        Sprite {
+            image,
+            rect,
+            color: quad.color,
+            custom_size: Some(size),
+            flip_x,
+            flip_y,
+            ..default()
+        }
+    }
+
+    fn node(quad: &Quad, left: f32, top: f32, size: Vec2) -> (Node, ImageNode, ZIndex) {
+        let node = Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(left),
+            top: Val::Px(top),
+            width: Val::Px(size.x),
+            height: Val::Px(size.y),
+            ..default()
+        };
+        let image_node = match &quad.texture {
+            Some((image, part)) => ImageNode {
+                rect: Some(part.as_rect()),
+                ..ImageNode::new(image.clone_weak()).with_color(quad.color)
+            },
+            None => ImageNode::solid_color(quad.color),
+        };
+        // siblings are drawn in the order of their z index, then of the children
+        (node, image_node, ZIndex(quad.layer as i32))
+    }
+
+    fn spawn(&mut self, widget: Entity, quad: &Quad, placement: &Placement) -> Entity {
+        let mut entity = match *placement {
+            Placement::Sprite {
+                translation,
+                size,
+                flip_x,
+                flip_y,
+            } => self.commands.spawn((
+                WidgetQuad,
+                Self::sprite(quad, size, flip_x, flip_y),
+                Transform::from_translation(translation),
+            )),
+            Placement::Ui { left, top, size } => self
+                .commands
+                .spawn((WidgetQuad, Self::node(quad, left, top, size))),
+        };
+        entity.insert(PickingBehavior::IGNORE).set_parent(widget);
+        entity.id()
+    }
+
+    fn update(&mut self, entity: Entity, quad: &Quad, placement: &Placement) {
+        match *placement {
+            Placement::Sprite {
+                translation,
+                size,
+                flip_x,
+                flip_y,
+            } => {
+                if let Ok((mut sprite, mut transform, mut visibility)) =
+                    self.sprites.get_mut(entity)
+                {
+                    *sprite = Self::sprite(quad, size, flip_x, flip_y);
+                    transform.translation = translation;
+                    visibility.set_if_neq(Visibility::Inherited);
+                }
+            }
+            Placement::Ui { left, top, size } => {
+                if let Ok((mut node, mut image_node, mut z_index, mut visibility)) =
+                    self.nodes.get_mut(entity)
+                {
+                    (*node, *image_node, *z_index) = Self::node(quad, left, top, size);
+                    visibility.set_if_neq(Visibility::Inherited);
+                }
+            }
+        }
+    }
+
+    fn hide(&mut self, entity: Entity) {
+        let visibility = match self.sprites.get_mut(entity) {
+            Ok((_, _, visibility)) => Some(visibility),
+            Err(_) => self
+                .nodes
+                .get_mut(entity)
+                .ok()
+                .map(|(_, _, _, visibility)| visibility),
+        };
+        if let Some(mut visibility) = visibility {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::{
+    render::{camera::CameraUpdateSystem, view::RenderLayers},
+    ui::UiSystem,
+    window::PrimaryWindow,
+};
+use render_implementations::prelude::*;
+
+use crate::prelude::*;
+
+pub(super) fn plugin(app: &mut App) {
+    app.register_required_components::<CosmicEditBuffer, ScaleFactor>()
+        .add_systems(
+            PostUpdate,
+            update_scale_factors
+                .after(CameraUpdateSystem)
+                .before(UiSystem::Prepare),
+        );
+}
+
+/// How many physical pixels a logical pixel of the widget covers, which its text
+/// is laid out and rendered at.
+///
+/// The metrics of the widget's buffer are scaled by it, so buffer coordinates
+/// are in physical pixels, while widget coordinates stay logical
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ScaleFactor(pub f32);
+
+impl Default for ScaleFactor {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Rescales the metrics of a widget's buffer when the scale factor of what it's shown on
+/// changes, e.g. when its window moves to another monitor or it's spawned on a secondary window
+fn update_scale_factors(
+    mut q: Query<(
+        RenderTypeScan,
+        Option<&ComputedNode>,
+        Option<&RenderLayers>,
+        &mut ScaleFactor,
+        EditorBuffer,
+    )>,
+    cameras: Query<(&Camera, Option<&RenderLayers>)>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    mut font_system: ResMut<CosmicFontSystem>,
+) {
+    let primary_scale = primary_window
+        .get_single()
+        .map(Window::scale_factor)
+        .unwrap_or(1.);
+    let default_layers = RenderLayers::default();
+    for (scan, ui, layers, mut scale, mut buffer) in q.iter_mut() {
+        // e.g. a buffer whose widget hasn't been set up yet
+        let Ok(source) = scan.scan() else {
+            continue;
+        };
+        // the sharpest of the cameras that can see the widget
+        let sharpest_camera = || {
+            let layers = layers.unwrap_or(&default_layers);
+            cameras
+                .iter()
+                .filter(|(camera, camera_layers)| {
+                    camera.is_active && layers.intersects(camera_layers.unwrap_or(&default_layers))
+                })
+                .filter_map(|(camera, _)| camera.target_scaling_factor())
+                .reduce(f32::max)
+                .unwrap_or(primary_scale)
+        };
+        let target = match source {
+            // includes `UiScale`, as bevy_ui lays out in physical pixels
+            SourceType::Ui => match ui {
+                Some(node) => node.inverse_scale_factor().recip(),
+                None => {
+                    let err = RenderTargetError::required_component_missing::<ComputedNode>();
+                    debug!(message = "Skipping widget without a scale factor", ?err);
+                    continue;
+                }
+            },
+            SourceType::Sprite => sharpest_camera(),
+            #[cfg(feature = "3d")]
+            SourceType::Mesh => sharpest_camera(),
+            SourceType::Custom => primary_scale,
+        };
+        if !target.is_finite() || target <= 0. || target == scale.0 {
+            continue;
+        }
+
+        let factor = target / scale.0;
+        let metrics = buffer.metrics().scale(factor);
+        buffer.set_metrics(&mut font_system, metrics);
+        let mut scroll = buffer.scroll();
+        scroll.vertical *= factor;
+        scroll.horizontal *= factor;
+        buffer.set_scroll(scroll);
+        buffer.set_redraw(true);
+        scale.0 = target;
+    }
+}
@@ -1,20 +1,29 @@
 use bevy::ecs::query::QueryData;
 
 use crate::prelude::*;
-use crate::render_implementations::prelude::*;
+#[cfg(feature = "3d")]
+use crate::render_implementations::TextEdit3d;
+use crate::render_implementations::{prelude::*, CustomTargetState};
 
 /// TODO: Generalize implementations depending on this
-/// and add 3D
 #[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(in crate::render_implementations) enum SourceType {
     Ui,
     Sprite,
+    #[cfg(feature = "3d")]
+    Mesh,
+    /// A [`CosmicRenderTarget`](crate::render_implementations::CosmicRenderTarget)
+    Custom,
 }
 
 #[derive(QueryData)]
 pub struct RenderTypeScan {
     is_sprite: Has<TextEdit2d>,
     is_ui: Has<TextEdit>,
+    #[cfg(feature = "3d")]
+    is_mesh: Has<TextEdit3d>,
+    is_custom: Has<CustomTargetState>,
 }
 
 impl RenderTypeScanItem<'_> {
@@ -26,11 +35,18 @@ impl RenderTypeScanItem<'_> {
     }
 
     pub(in crate::render_implementations) fn scan(&self) -> Result<SourceType> {
-        match (self.is_sprite, self.is_ui) {
-            (true, false) => Ok(SourceType::Sprite),
-            (false, true) => Ok(SourceType::Ui),
-            (true, true) => Err(RenderTargetError::MoreThanOneTargetAvailable),
-            (false, false) => Err(RenderTargetError::NoTargetsAvailable),
+        #[cfg(feature = "3d")]
+        let is_mesh = self.is_mesh;
+        #[cfg(not(feature = "3d"))]
+        let is_mesh = false;
+        match (self.is_sprite, self.is_ui, is_mesh, self.is_custom) {
+            (true, false, false, false) => Ok(SourceType::Sprite),
+            (false, true, false, false) => Ok(SourceType::Ui),
+            #[cfg(feature = "3d")]
+            (false, false, true, false) => Ok(SourceType::Mesh),
+            (false, false, false, true) => Ok(SourceType::Custom),
+            (false, false, false, false) => Err(RenderTargetError::NoTargetsAvailable),
+            _ => Err(RenderTargetError::MoreThanOneTargetAvailable),
         }
     }
 }
@@ -1,5 +1,8 @@
 use bevy::ecs::query::{QueryData, QueryFilter};
-use render_implementations::{RenderTypeScan, RenderTypeScanItem};
+use bevy::ui::{ContentSize, FixedMeasure, NodeMeasure};
+#[cfg(feature = "3d")]
+use render_implementations::TextEdit3d;
+use render_implementations::{CustomTargetState, RenderTypeScan, RenderTypeScanItem, ScaleFactor};
 
 use crate::prelude::*;
 use render_implementations::prelude::*;
@@ -11,6 +14,11 @@ pub struct CosmicWidgetSize {
 
     sprite: Option<&'static Sprite>,
     ui: Option<&'static ComputedNode>,
+    #[cfg(feature = "3d")]
+    mesh: Option<&'static TextEdit3d>,
+    custom: Option<&'static CustomTargetState>,
+    padding: Option<&'static CosmicPadding>,
+    scale: Option<&'static ScaleFactor>,
 }
 
 /// Allows `.scan()` to be called on a [`CosmicWidgetSize`] through deref
@@ -49,6 +57,49 @@ impl CosmicWidgetSizeItem<'_> {
         ret
     }
 
+    /// Physical pixels per logical pixel the widget is rendered at
+    pub fn scale_factor(&self) -> f32 {
+        self.scale.copied().unwrap_or_default().0
+    }
+
+    /// Size of the widget's rendered image, [`Self::logical_size`] in physical pixels
+    pub fn physical_size(&self) -> Result<UVec2> {
+        Ok((self.logical_size()? * self.scale_factor())
+            .round()
+            .as_uvec2())
+    }
+
+    /// Where text goes within the widget, inset by [`CosmicPadding`] and for UI
+    /// by the [`Node`] padding and border.
+    ///
+    /// In logical pixels from the top left of the widget
+    pub fn content_rect(&self) -> Result<Rect> {
+        let size = self.logical_size()?;
+        let mut padding = self.padding.copied().unwrap_or_default();
+        if let SourceType::Ui = self.scan.scan()? {
+            let ui = self
+                .ui
+                .ok_or(RenderTargetError::required_component_missing::<ComputedNode>())?;
+            let inset = ui.content_inset();
+            let scale = ui.inverse_scale_factor();
+            padding.left += inset.left * scale;
+            padding.right += inset.right * scale;
+            padding.top += inset.top * scale;
+            padding.bottom += inset.bottom * scale;
+        }
+        let min = Vec2::new(padding.left, padding.top).min(size);
+        let max = (size - Vec2::new(padding.right, padding.bottom)).max(min);
+        Ok(Rect::from_corners(min, max))
+    }
+
+    /// Converts the physical pixels of bevy_ui into logical pixels
+    pub(in crate::render_implementations) fn ui_inverse_scale_factor(&self) -> Result<f32> {
+        let ui = self
+            .ui
+            .ok_or(RenderTargetError::required_component_missing::<ComputedNode>())?;
+        Ok(ui.inverse_scale_factor())
+    }
+
     fn _logical_size(&self) -> Result<Vec2> {
         let source_type = self.scan.scan()?;
         match source_type {
@@ -66,6 +117,79 @@ impl CosmicWidgetSizeItem<'_> {
                     .custom_size
                     .ok_or(RenderTargetError::SpriteCustomSizeNotSet)?)
             }
+            #[cfg(feature = "3d")]
+            SourceType::Mesh => {
+                let mesh = self
+                    .mesh
+                    .ok_or(RenderTargetError::required_component_missing::<TextEdit3d>())?;
+                Ok(mesh.size)
+            }
+            SourceType::Custom => self
+                .custom
+                .and_then(CustomTargetState::logical_size)
+                .ok_or(RenderTargetError::CustomTargetUnavailable),
+        }
+    }
+}
+
+/// Last size given to bevy_ui by [`CosmicWidgetSizeMut`], in physical pixels
+#[derive(Component, Default, Debug)]
+pub(crate) struct FixedContentSize(Option<Vec2>);
+
+/// Sets the (logical) size of a widget
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub(crate) struct CosmicWidgetSizeMut {
+    scan: RenderTypeScan,
+
+    sprite: Option<&'static mut Sprite>,
+    ui: Option<(
+        &'static mut ContentSize,
+        &'static mut FixedContentSize,
+        Ref<'static, ImageNode>,
+        &'static ComputedNode,
+    )>,
+}
+
+impl CosmicWidgetSizeMutItem<'_> {
+    /// Sets [`Sprite.custom_size`], or gives bevy_ui a fixed [`ContentSize`].
+    ///
+    /// `TextEdit3d` widgets keep their size, as their text would stretch over the mesh otherwise,
+    /// as do [`CosmicRenderTarget`](render_implementations::CosmicRenderTarget)s.
+    ///
+    /// The [`ImageNode`] of a UI widget shouldn't use [`NodeImageMode::Auto`](bevy::ui::widget::NodeImageMode::Auto),
+    /// or bevy_ui measures it by the size of the last rendered image instead
+    pub fn set_logical_size(&mut self, size: Vec2) -> Result<()> {
+        match self.scan.scan()? {
+            SourceType::Sprite => {
+                let sprite = self
+                    .sprite
+                    .as_mut()
+                    .ok_or(RenderTargetError::required_component_missing::<Sprite>())?;
+                if sprite.custom_size != Some(size) {
+                    sprite.custom_size = Some(size);
+                }
+                Ok(())
+            }
+            SourceType::Ui => {
+                let (content_size, fixed, image_node, node) = self
+                    .ui
+                    .as_mut()
+                    .ok_or(RenderTargetError::required_component_missing::<ContentSize>())?;
+                // bevy_ui measures in physical pixels
+                let physical_size = size / node.inverse_scale_factor();
+                // bevy_ui clears the measure whenever the image node changes
+                if fixed.0 != Some(physical_size) || image_node.is_changed() {
+                    fixed.0 = Some(physical_size);
+                    content_size.set(NodeMeasure::Fixed(FixedMeasure {
+                        size: physical_size,
+                    }));
+                }
+                Ok(())
+            }
+            #[cfg(feature = "3d")]
+            SourceType::Mesh => Ok(()),
+            SourceType::Custom => Ok(()),
         }
     }
 }
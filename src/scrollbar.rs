@@ -0,0 +1,469 @@
+//! Scrollbars drawn over a widget's text
+//!
+//! Add [`Scrollbars`] to a widget to show how far it is scrolled.
+//! The thumbs can be dragged, and clicking a track scrolls by a page towards the pointer.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::scrollbar::{AutoHide, Scrollbars};
+//!
+//! # fn setup(mut commands: Commands) {
+//! commands.spawn((
+//!     TextEdit2d,
+//!     CosmicEditBuffer::default(),
+//!     Scrollbars {
+//!         thickness: 6.,
+//!         auto_hide: AutoHide::WhenIdle(std::time::Duration::from_secs(1)),
+//!         ..default()
+//!     },
+//! ));
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use bevy::ecs::{component::ComponentId, world::DeferredWorld};
+use render_implementations::RelativeQuery;
+
+use crate::{
+    input::scroll::{CosmicScroll, ScrollMetrics},
+    prelude::*,
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.register_type::<Scrollbars>();
+}
+
+/// Shows scrollbars on a widget, see the [module docs](self)
+#[derive(Component, Reflect, Debug, Clone)]
+#[require(ScrollbarState)]
+#[component(on_add = add_scrollbar_observers)]
+pub struct Scrollbars {
+    /// Show a vertical scrollbar along the right edge
+    pub vertical: bool,
+    /// Show a horizontal scrollbar along the bottom edge.
+    ///
+    /// Only [`CosmicWrap::InfiniteLine`] widgets can overflow horizontally
+    pub horizontal: bool,
+    /// Width of a vertical scrollbar, or height of a horizontal one, in logical pixels
+    pub thickness: f32,
+    /// Shortest length of a thumb, so it can still be grabbed in long texts
+    pub min_thumb_length: f32,
+    pub track_color: Color,
+    pub thumb_color: Color,
+    pub auto_hide: AutoHide,
+}
+
+impl Default for Scrollbars {
+    fn default() -> Self {
+        Self {
+            vertical: true,
+            horizontal: true,
+            thickness: 8.,
+            min_thumb_length: 16.,
+            track_color: Color::srgba(0., 0., 0., 0.1),
+            thumb_color: Color::srgba(0., 0., 0., 0.4),
+            auto_hide: AutoHide::default(),
+        }
+    }
+}
+
+/// When [`Scrollbars`] are hidden
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq)]
+pub enum AutoHide {
+    /// Always shown, even if the text fits
+    Never,
+    /// Hidden while the text fits, so there is nothing to scroll
+    #[default]
+    WhenNotOverflowing,
+    /// Like [`AutoHide::WhenNotOverflowing`], but also hidden once the widget
+    /// hasn't scrolled for this long
+    WhenIdle(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    Vertical,
+    Horizontal,
+}
+
+/// A visible scrollbar, in logical pixels from the top left of the widget
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bar {
+    axis: Axis,
+    track: Rect,
+    thumb: Rect,
+    /// Range of scroll offsets, see [`ScrollMetrics`]
+    min_offset: f32,
+    max_offset: f32,
+    /// Page size, the length of the view along this axis
+    page: f32,
+}
+
+impl Bar {
+    fn along(&self, point: Vec2) -> f32 {
+        match self.axis {
+            Axis::Vertical => point.y,
+            Axis::Horizontal => point.x,
+        }
+    }
+
+    /// Scroll offset per pixel the thumb moves
+    fn offset_per_pixel(&self) -> f32 {
+        let free = self.along(self.track.size()) - self.along(self.thumb.size());
+        match free > 0. {
+            true => (self.max_offset - self.min_offset) / free,
+            false => 0.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ThumbDrag {
+    axis: Axis,
+    start_offset: f32,
+}
+
+/// Layout and interaction state of [`Scrollbars`], updated while rendering
+#[derive(Component, Default, Debug)]
+pub(crate) struct ScrollbarState {
+    bars: Vec<Bar>,
+    metrics: ScrollMetrics,
+    last_scrolled: Duration,
+    drag: Option<ThumbDrag>,
+}
+
+impl ScrollbarState {
+    /// Whether `widget_coord`, from the top left of the widget, is on a visible scrollbar
+    pub fn contains(&self, widget_coord: Vec2) -> bool {
+        self.bars.iter().any(|bar| bar.track.contains(widget_coord))
+    }
+
+    fn offset(&self, axis: Axis) -> f32 {
+        match axis {
+            Axis::Vertical => self.metrics.vertical,
+            Axis::Horizontal => self.metrics.horizontal,
+        }
+    }
+
+    /// Lays out the scrollbars along the edges of a widget of `widget_size`,
+    /// showing `view_size` of the text at a time.
+    ///
+    /// Returns whether they need to be redrawn
+    pub fn update(
+        &mut self,
+        scrollbars: &Scrollbars,
+        metrics: ScrollMetrics,
+        widget_size: Vec2,
+        view_size: Vec2,
+        now: Duration,
+    ) -> bool {
+        if metrics.vertical != self.metrics.vertical
+            || metrics.horizontal != self.metrics.horizontal
+            || self.drag.is_some()
+        {
+            self.last_scrolled = now;
+        }
+        self.metrics = metrics;
+
+        let overflows_vertically = metrics.content_height > view_size.y;
+        let overflows_horizontally = metrics.content_right - metrics.content_left > view_size.x;
+        let visible = |enabled: bool, overflows: bool| {
+            enabled
+                && match scrollbars.auto_hide {
+                    AutoHide::Never => true,
+                    AutoHide::WhenNotOverflowing => overflows,
+                    AutoHide::WhenIdle(linger) => {
+                        overflows && now.saturating_sub(self.last_scrolled) < linger
+                    }
+                }
+        };
+        let vertical = visible(scrollbars.vertical, overflows_vertically);
+        let horizontal = visible(scrollbars.horizontal, overflows_horizontally);
+
+        let thickness = scrollbars.thickness;
+        // leaves the corner free when both are shown
+        let corner = Vec2::new(
+            if vertical { thickness } else { 0. },
+            if horizontal { thickness } else { 0. },
+        );
+        let thumb_length = |track_length: f32, content: f32, view: f32| {
+            let fraction = match content > 0. {
+                true => (view / content).min(1.),
+                false => 1.,
+            };
+            (track_length * fraction)
+                .max(scrollbars.min_thumb_length)
+                .min(track_length)
+        };
+        let thumb_start =
+            |track_length: f32, thumb_length: f32, offset: f32, min: f32, max: f32| match max > min
+            {
+                true => {
+                    (offset - min).clamp(0., max - min) / (max - min)
+                        * (track_length - thumb_length)
+                }
+                false => 0.,
+            };
+
+        let previous = std::mem::take(&mut self.bars);
+        if vertical {
+            let track = Rect::new(
+                widget_size.x - thickness,
+                0.,
+                widget_size.x,
+                widget_size.y - corner.y,
+            );
+            let max_offset = (metrics.content_height - view_size.y).max(0.);
+            let length = thumb_length(track.height(), metrics.content_height, view_size.y);
+            let start = thumb_start(track.height(), length, metrics.vertical, 0., max_offset);
+            self.bars.push(Bar {
+                axis: Axis::Vertical,
+                track,
+                thumb: Rect::new(
+                    track.min.x,
+                    track.min.y + start,
+                    track.max.x,
+                    track.min.y + start + length,
+                ),
+                min_offset: 0.,
+                max_offset,
+                page: view_size.y,
+            });
+        }
+        if horizontal {
+            let track = Rect::new(
+                0.,
+                widget_size.y - thickness,
+                widget_size.x - corner.x,
+                widget_size.y,
+            );
+            let content_width = metrics.content_right - metrics.content_left;
+            let (min_offset, max_offset) = match content_width > view_size.x {
+                true => (metrics.content_left, metrics.content_right - view_size.x),
+                false => (0., 0.),
+            };
+            let length = thumb_length(track.width(), content_width, view_size.x);
+            let start = thumb_start(
+                track.width(),
+                length,
+                metrics.horizontal,
+                min_offset,
+                max_offset,
+            );
+            self.bars.push(Bar {
+                axis: Axis::Horizontal,
+                track,
+                thumb: Rect::new(
+                    track.min.x + start,
+                    track.min.y,
+                    track.min.x + start + length,
+                    track.max.y,
+                ),
+                min_offset,
+                max_offset,
+                page: view_size.x,
+            });
+        }
+        self.bars != previous
+    }
+
+    /// Calls `fill` with each rectangle to draw and its colour
+    pub fn draw(&self, scrollbars: &Scrollbars, mut fill: impl FnMut(Rect, Color)) {
+        for bar in &self.bars {
+            fill(bar.track, scrollbars.track_color);
+            fill(bar.thumb, scrollbars.thumb_color);
+        }
+    }
+}
+
+fn add_scrollbar_observers(mut world: DeferredWorld, targeted_entity: Entity, _: ComponentId) {
+    let mut observers = [
+        Observer::new(press_scrollbar.pipe(render_implementations::debug_error)),
+        Observer::new(drag_thumb),
+        Observer::new(release_thumb::<Up>),
+        Observer::new(release_thumb::<DragEnd>),
+    ];
+    for observer in &mut observers {
+        observer.watch_entity(targeted_entity);
+    }
+    world.commands().spawn_batch(observers);
+}
+
+fn scroll_to(scroll: &mut CosmicScroll, axis: Axis, offset: f32) {
+    let mut target = scroll.offset();
+    match axis {
+        Axis::Vertical => target.y = offset,
+        Axis::Horizontal => target.x = offset,
+    }
+    scroll.set_offset(target);
+}
+
+/// Grabs a thumb, or scrolls a page towards the pointer when a track is pressed
+fn press_scrollbar(
+    trigger: Trigger<Pointer<Down>>,
+    mut q: Query<(&mut ScrollbarState, &mut CosmicScroll, RelativeQuery)>,
+) -> render_implementations::Result<()> {
+    if trigger.event().button != PointerButton::Primary {
+        return Ok(());
+    }
+    let Ok((mut state, mut scroll, relative)) = q.get_mut(trigger.target) else {
+        return Ok(());
+    };
+    let widget_coord = relative.compute_widget_coord(&trigger.event().hit)?;
+    let Some(bar) = state
+        .bars
+        .iter()
+        .find(|bar| bar.track.contains(widget_coord))
+        .copied()
+    else {
+        return Ok(());
+    };
+
+    let offset = state.offset(bar.axis);
+    if bar.thumb.contains(widget_coord) {
+        state.drag = Some(ThumbDrag {
+            axis: bar.axis,
+            start_offset: offset,
+        });
+        return Ok(());
+    }
+
+    let page = match bar.along(widget_coord) < bar.along(bar.thumb.min) {
+        true => -bar.page,
+        false => bar.page,
+    };
+    scroll.scroll_by(match bar.axis {
+        Axis::Vertical => Vec2::new(0., page),
+        Axis::Horizontal => Vec2::new(page, 0.),
+    });
+    Ok(())
+}
+
+fn drag_thumb(trigger: Trigger<Pointer<Drag>>, mut q: Query<(&ScrollbarState, &mut CosmicScroll)>) {
+    let Ok((state, mut scroll)) = q.get_mut(trigger.target) else {
+        return;
+    };
+    let Some(drag) = state.drag else {
+        return;
+    };
+    let Some(bar) = state.bars.iter().find(|bar| bar.axis == drag.axis) else {
+        return;
+    };
+    let distance = bar.along(trigger.event().distance);
+    let offset = (drag.start_offset + distance * bar.offset_per_pixel())
+        .clamp(bar.min_offset, bar.max_offset);
+    scroll_to(&mut scroll, drag.axis, offset);
+}
+
+/// Releases a grabbed thumb, on [`Up`] or on [`DragEnd`] if the pointer left the widget
+fn release_thumb<E: std::fmt::Debug + Clone + Reflect>(
+    trigger: Trigger<Pointer<E>>,
+    mut q: Query<&mut ScrollbarState>,
+) {
+    if let Ok(mut state) = q.get_mut(trigger.target) {
+        state.drag = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDGET: Vec2 = Vec2::new(100., 50.);
+
+    fn layout(scrollbars: &Scrollbars, metrics: ScrollMetrics) -> Vec<Bar> {
+        let mut state = ScrollbarState::default();
+        state.update(scrollbars, metrics, WIDGET, WIDGET, Duration::ZERO);
+        state.bars
+    }
+
+    fn bar(scrollbars: &Scrollbars, metrics: ScrollMetrics) -> Bar {
+        let bars = layout(scrollbars, metrics);
+        assert_eq!(bars.len(), 1);
+        bars[0]
+    }
+
+    #[test]
+    fn vertical_thumb_follows_the_scroll() {
+        let scrollbars = Scrollbars {
+            min_thumb_length: 4.,
+            ..default()
+        };
+        // a quarter of the text is shown, scrolled halfway
+        let metrics = ScrollMetrics {
+            content_height: 200.,
+            vertical: 75.,
+            content_right: 80.,
+            ..default()
+        };
+        let bar = bar(&scrollbars, metrics);
+        assert_eq!(bar.axis, Axis::Vertical);
+        assert_eq!(bar.track, Rect::new(92., 0., 100., 50.));
+        assert_eq!(bar.thumb, Rect::new(92., 18.75, 100., 31.25));
+        assert_eq!(bar.max_offset, 150.);
+        assert_eq!(bar.offset_per_pixel(), 4.);
+    }
+
+    #[test]
+    fn horizontal_thumb_covers_lines_left_of_the_view() {
+        // e.g. a centered line twice as wide as the view, not scrolled
+        let metrics = ScrollMetrics {
+            content_height: 20.,
+            content_left: -50.,
+            content_right: 150.,
+            ..default()
+        };
+        let bar = bar(&Scrollbars::default(), metrics);
+        assert_eq!(bar.axis, Axis::Horizontal);
+        assert_eq!(bar.track, Rect::new(0., 42., 100., 50.));
+        assert_eq!(bar.thumb, Rect::new(25., 42., 75., 50.));
+        assert_eq!((bar.min_offset, bar.max_offset), (-50., 50.));
+    }
+
+    #[test]
+    fn thumbs_are_kept_long_enough_to_grab() {
+        let metrics = ScrollMetrics {
+            content_height: 10_000.,
+            vertical: 9_950.,
+            ..default()
+        };
+        let bar = bar(&Scrollbars::default(), metrics);
+        // scrolled to the end
+        assert_eq!(bar.thumb, Rect::new(92., 34., 100., 50.));
+    }
+
+    #[test]
+    fn tracks_leave_the_corner_free() {
+        let metrics = ScrollMetrics {
+            content_height: 100.,
+            content_right: 200.,
+            ..default()
+        };
+        let bars = layout(&Scrollbars::default(), metrics);
+        let tracks: Vec<_> = bars.iter().map(|bar| bar.track).collect();
+        assert_eq!(
+            tracks,
+            [Rect::new(92., 0., 100., 42.), Rect::new(0., 42., 92., 50.)]
+        );
+    }
+
+    #[test]
+    fn text_that_fits_hides_the_scrollbars() {
+        let metrics = ScrollMetrics {
+            content_height: 40.,
+            content_right: 90.,
+            ..default()
+        };
+        assert_eq!(layout(&Scrollbars::default(), metrics), []);
+
+        let always = Scrollbars {
+            auto_hide: AutoHide::Never,
+            horizontal: false,
+            ..default()
+        };
+        let bar = bar(&always, metrics);
+        assert_eq!(bar.thumb, bar.track);
+    }
+}
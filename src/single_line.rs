@@ -0,0 +1,243 @@
+//! Single line text inputs that can be submitted
+//!
+//! A [`SingleLine`] widget never wraps or holds more than one line, scrolls
+//! horizontally to keep the caret visible, and sends a [`CosmicTextSubmitted`]
+//! when Enter is pressed instead of inserting a new line.
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use bevy_cosmic_edit::prelude::*;
+//! use bevy_cosmic_edit::single_line::{CosmicTextSubmitted, SingleLine};
+//!
+//! # fn setup(mut commands: Commands) {
+//! commands
+//!     .spawn((
+//!         TextEdit2d,
+//!         CosmicEditBuffer::default(),
+//!         SingleLine {
+//!             clear_on_submit: true,
+//!             ..default()
+//!         },
+//!     ))
+//!     .observe(|trigger: Trigger<CosmicTextSubmitted>| {
+//!         info!("Sent message: {}", trigger.event().1);
+//!     });
+//! # }
+//! ```
+
+use std::borrow::Cow;
+
+use crate::{
+    input::{keymap::EditorAction, keymap::FiredActions, InputSet},
+    placeholder::Placeholder,
+    prelude::*,
+    MaxLines,
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.add_event::<CosmicTextSubmitted>()
+        .register_type::<SingleLine>()
+        .register_type::<CosmicTextSubmitted>()
+        .add_systems(
+            Update,
+            submit_on_enter
+                .after(crate::password::restore_password_text)
+                .before(crate::input::keyboard::kb_input_text)
+                .in_set(InputSet),
+        );
+}
+
+/// Makes a widget a single line input, see the [module docs](self).
+///
+/// Sets [`CosmicWrap::InfiniteLine`] and [`MaxLines`] to 1 when added
+#[derive(Component, Reflect, Debug, Clone)]
+#[component(on_add = on_single_line_add)]
+pub struct SingleLine {
+    /// What happens when Enter is pressed with Shift held
+    pub shift_enter: ShiftEnter,
+    /// How new lines in pasted or inserted text are handled
+    pub newlines: NewlineHandling,
+    /// Clears the text after it is submitted
+    pub clear_on_submit: bool,
+    /// Unfocuses the widget after its text is submitted
+    pub unfocus_on_submit: bool,
+}
+
+impl Default for SingleLine {
+    fn default() -> Self {
+        Self {
+            shift_enter: ShiftEnter::Submit,
+            newlines: NewlineHandling::Space,
+            clear_on_submit: false,
+            unfocus_on_submit: false,
+        }
+    }
+}
+
+/// What Shift+Enter does in a [`SingleLine`] widget
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftEnter {
+    /// Submits, just like Enter
+    #[default]
+    Submit,
+    /// Does nothing, e.g. to leave Shift+Enter to your own systems
+    Ignore,
+}
+
+/// How new lines in text inserted into a [`SingleLine`] widget are handled
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewlineHandling {
+    /// Each line break becomes a space
+    #[default]
+    Space,
+    /// Line breaks are removed, joining the lines
+    Remove,
+}
+
+impl NewlineHandling {
+    /// Replaces the line breaks in `text`
+    pub fn flatten<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !text.contains(['\n', '\r']) {
+            return Cow::Borrowed(text);
+        }
+        let replacement = match self {
+            NewlineHandling::Space => " ",
+            NewlineHandling::Remove => "",
+        };
+        Cow::Owned(
+            text.replace("\r\n", "\n")
+                .replace(['\n', '\r'], replacement),
+        )
+    }
+}
+
+/// Sent, and triggered on the widget, when Enter is pressed in a [`SingleLine`] widget.
+///
+/// Contains the widget and its text at the time it was submitted
+#[derive(Event, Reflect, Debug, Clone)]
+pub struct CosmicTextSubmitted(pub Entity, pub String);
+
+fn on_single_line_add(
+    mut world: bevy::ecs::world::DeferredWorld,
+    target: Entity,
+    _: bevy::ecs::component::ComponentId,
+) {
+    world
+        .commands()
+        .entity(target)
+        .insert((CosmicWrap::InfiniteLine, MaxLines(1)));
+}
+
+/// Turns [`EditorAction::Newline`] into a submission for [`SingleLine`] widgets
+fn submit_on_enter(
+    mut focused: ResMut<FocusedWidget>,
+    mut fired: ResMut<FiredActions>,
+    keys: Res<ButtonInput<KeyCode>>,
+    q: Query<(
+        &SingleLine,
+        &CosmicEditBuffer,
+        Option<&CosmicEditor>,
+        Option<&Placeholder>,
+    )>,
+    mut evw_submitted: EventWriter<CosmicTextSubmitted>,
+    mut commands: Commands,
+) {
+    let Some(entity) = focused.0 else {
+        return;
+    };
+    let Ok((single_line, buffer, editor, placeholder)) = q.get(entity) else {
+        return;
+    };
+    if !fired.remove(EditorAction::Newline) {
+        return;
+    }
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if shift && single_line.shift_enter == ShiftEnter::Ignore {
+        return;
+    }
+
+    let text = match (placeholder, editor) {
+        (Some(placeholder), _) if placeholder.is_active() => String::new(),
+        (_, Some(editor)) => editor.get_text(),
+        (_, None) => buffer.get_text(),
+    };
+    evw_submitted.send(CosmicTextSubmitted(entity, text.clone()));
+    commands.trigger_targets(CosmicTextSubmitted(entity, text), entity);
+
+    if single_line.clear_on_submit {
+        commands.trigger_targets(EditCommand::SetText(String::new()), entity);
+    }
+    if single_line.unfocus_on_submit {
+        focused.0 = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use cosmic_text::Metrics;
+
+    use super::*;
+    use crate::{
+        input::{
+            filter::CosmicTextRejected,
+            keyboard::{insert_text, InputConstraints, InsertEvents},
+        },
+        primary::create_cosmic_font_system,
+        LimitReached,
+    };
+
+    #[test]
+    fn newlines_are_flattened() {
+        let text = "one\ntwo\r\nthree\rfour";
+        assert_eq!(NewlineHandling::Space.flatten(text), "one two three four");
+        assert_eq!(NewlineHandling::Remove.flatten(text), "onetwothreefour");
+        assert!(matches!(
+            NewlineHandling::Space.flatten("one"),
+            Cow::Borrowed("one")
+        ));
+    }
+
+    #[test]
+    fn pasted_newlines_are_flattened() {
+        let mut world = World::new();
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(14., 18.));
+        let editor = CosmicEditor::clone_from_buffer(&buffer);
+        world.insert_resource(CosmicFontSystem(font_system));
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<Events<CosmicTextRejected>>();
+        world.init_resource::<Events<LimitReached>>();
+        let entity = world.spawn((buffer, editor, SingleLine::default())).id();
+        world.flush();
+        assert_eq!(world.get::<MaxLines>(entity).unwrap().0, 1);
+
+        world
+            .run_system_once(
+                |mut q: Query<(&mut CosmicEditor, InputConstraints)>,
+                 mut font_system: ResMut<CosmicFontSystem>,
+                 mut events: InsertEvents| {
+                    let (mut editor, constraints) = q.single_mut();
+                    // what `clipboard::paste` does with the clipboard's text
+                    let pasted = "one\ntwo\r\nthree";
+                    insert_text(
+                        &mut editor,
+                        &mut font_system,
+                        pasted,
+                        &constraints,
+                        &mut events,
+                    );
+                },
+            )
+            .unwrap();
+
+        let editor = world.get::<CosmicEditor>(entity).unwrap();
+        assert_eq!(editor.get_text(), "one two three");
+        assert_eq!(editor.with_buffer(|b| b.lines.len()), 1);
+        assert!(world.resource::<Events<LimitReached>>().is_empty());
+    }
+}
@@ -0,0 +1,549 @@
+//! Undo/redo history for [`CosmicEditBuffer`]s
+//!
+//! Every edit in a widget's [`EditLog`] is stored as an [`UndoEntry`]
+//! in the widget's [`UndoHistory`]. This catches edits from keyboard input,
+//! the clipboard and the placeholder/password rewrites alike.
+//!
+//! Because the history lives on the widget entity and not on the
+//! [`CosmicEditor`], it survives the editor being dropped and recreated
+//! by [`crate::focus`].
+
+use std::time::Duration;
+
+use cosmic_text::{Cursor, Edit, Selection, Shaping};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    edit_log::{offset_to_cursor, take_changes, EditLog, EditLogSet},
+    input::{
+        keymap::{EditorAction, FiredActions},
+        CosmicTextChanged, InputSet,
+    },
+    password::PasswordSet,
+    placeholder::Placeholder,
+    prelude::*,
+    render::RenderSet,
+};
+
+pub(crate) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        kb_undo_redo
+            .after(crate::input::clipboard::kb_clipboard)
+            .in_set(InputSet),
+    )
+    .add_systems(
+        PostUpdate,
+        record_history
+            .after(EditLogSet)
+            .before(PasswordSet)
+            .before(RenderSet),
+    );
+}
+
+/// A single undoable edit.
+///
+/// Offsets are byte offsets into the text of the whole buffer,
+/// with lines joined by `\n` (see [`BufferRefExtras::get_text`](crate::BufferRefExtras::get_text)).
+#[derive(Debug, Clone)]
+pub struct UndoEntry {
+    /// Where the edit starts
+    pub offset: usize,
+    /// Text that was removed at [`UndoEntry::offset`]
+    pub removed: String,
+    /// Text that was inserted at [`UndoEntry::offset`]
+    pub inserted: String,
+    pub cursor_before: Cursor,
+    pub selection_before: Selection,
+    pub cursor_after: Cursor,
+    pub selection_after: Selection,
+}
+
+/// What kind of operation an [`UndoEntry`] represents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    /// e.g. typing or pasting over a selection
+    Replace,
+}
+
+impl UndoEntry {
+    pub fn kind(&self) -> EditKind {
+        match (self.removed.is_empty(), self.inserted.is_empty()) {
+            (true, _) => EditKind::Insert,
+            (false, true) => EditKind::Delete,
+            (false, false) => EditKind::Replace,
+        }
+    }
+
+    fn is_single_grapheme(text: &str) -> bool {
+        text != "\n" && text.graphemes(true).count() == 1
+    }
+
+    /// Tries to merge a newer edit into this one, e.g. consecutive typing
+    fn try_merge(&mut self, newer: &UndoEntry) -> bool {
+        let merged = match newer.kind() {
+            // typing
+            EditKind::Insert
+                if Self::is_single_grapheme(&newer.inserted)
+                    && self.kind() != EditKind::Delete
+                    && self.offset + self.inserted.len() == newer.offset =>
+            {
+                self.inserted.push_str(&newer.inserted);
+                true
+            }
+            // backspace
+            EditKind::Delete
+                if Self::is_single_grapheme(&newer.removed)
+                    && self.kind() == EditKind::Delete
+                    && newer.offset + newer.removed.len() == self.offset =>
+            {
+                self.offset = newer.offset;
+                self.removed.insert_str(0, &newer.removed);
+                true
+            }
+            // forward delete
+            EditKind::Delete
+                if Self::is_single_grapheme(&newer.removed)
+                    && self.kind() == EditKind::Delete
+                    && newer.offset == self.offset =>
+            {
+                self.removed.push_str(&newer.removed);
+                true
+            }
+            _ => false,
+        };
+        if merged {
+            self.cursor_after = newer.cursor_after;
+            self.selection_after = newer.selection_after;
+        }
+        merged
+    }
+}
+
+/// Per-widget edit history, enabling Ctrl+Z / Ctrl+Shift+Z / Ctrl+Y
+/// (Cmd on macOS) while the widget is focused.
+/// See [`EditorAction::Undo`] and [`EditorAction::Redo`] to rebind these.
+///
+/// Consecutive typing or deleting is merged into one step,
+/// as long as the cursor isn't moved in between and no more than
+/// [`UndoHistory::merge_timeout`] passes between keystrokes.
+#[derive(Component, Debug)]
+pub struct UndoHistory {
+    /// Maximum number of undo steps kept, oldest are dropped first.
+    /// `0` means unlimited
+    pub max_entries: usize,
+    /// Maximum pause between two keystrokes for them to be merged into one step
+    pub merge_timeout: Duration,
+
+    undo_stack: Vec<UndoEntry>,
+    redo_stack: Vec<UndoEntry>,
+
+    last_cursor: Cursor,
+    last_selection: Selection,
+    last_edit: Duration,
+    /// Prevents the next edit from merging into the previous step
+    sealed: bool,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self {
+            max_entries: 100,
+            merge_timeout: Duration::from_secs(1),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            last_cursor: Cursor::default(),
+            last_selection: Selection::None,
+            last_edit: Duration::ZERO,
+            sealed: true,
+        }
+    }
+}
+
+impl UndoHistory {
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Steps that would be reverted by undo, oldest first
+    pub fn undo_entries(&self) -> &[UndoEntry] {
+        &self.undo_stack
+    }
+
+    /// Steps that would be re-applied by redo, most recently undone last
+    pub fn redo_entries(&self) -> &[UndoEntry] {
+        &self.redo_stack
+    }
+
+    /// Forgets all history, e.g. after loading a new document into the widget
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.sealed = true;
+    }
+
+    /// Starts a new undo step on the next edit, even if it would be merged otherwise
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    fn push(&mut self, entry: UndoEntry, now: Duration) {
+        self.redo_stack.clear();
+
+        let within_timeout = now.saturating_sub(self.last_edit) <= self.merge_timeout;
+        let merged = !self.sealed
+            && within_timeout
+            && self
+                .undo_stack
+                .last_mut()
+                .is_some_and(|last| last.try_merge(&entry));
+        if !merged {
+            self.undo_stack.push(entry);
+        }
+
+        if self.max_entries != 0 && self.undo_stack.len() > self.max_entries {
+            let overflow = self.undo_stack.len() - self.max_entries;
+            self.undo_stack.drain(..overflow);
+        }
+
+        self.last_edit = now;
+        self.sealed = false;
+    }
+
+    /// Pushes an [`UndoEntry`] for each edit in `log` that isn't in the history yet,
+    /// `cursor` and `selection` being where the last one left them
+    fn record(&mut self, log: &mut EditLog, cursor: Cursor, selection: Selection, now: Duration) {
+        let mut edits = log
+            .edits_mut()
+            .iter_mut()
+            .filter(|edit| !edit.in_history)
+            .peekable();
+        if edits.peek().is_none() {
+            // moving the cursor ends the current typing run
+            if cursor != self.last_cursor || selection != self.last_selection {
+                self.sealed = true;
+                self.last_cursor = cursor;
+                self.last_selection = selection;
+            }
+            return;
+        }
+
+        while let Some(edit) = edits.next() {
+            edit.in_history = true;
+            let (cursor_after, selection_after) = match edits.peek() {
+                Some(_) => (edit.cursor_after, Selection::None),
+                None => (cursor, selection),
+            };
+            let entry = UndoEntry {
+                offset: edit.offset,
+                removed: edit.removed.clone(),
+                inserted: edit.inserted.clone(),
+                cursor_before: self.last_cursor,
+                selection_before: self.last_selection,
+                cursor_after,
+                selection_after,
+            };
+            self.push(entry, now);
+            self.last_cursor = cursor_after;
+            self.last_selection = selection_after;
+        }
+    }
+}
+
+/// Replaces `remove_len` bytes at `offset` with `insert`.
+///
+/// If a [`Placeholder`] is showing, its text is swapped out for the real text instead.
+fn apply_edit(
+    editor: &mut CosmicEditor,
+    placeholder: Option<&mut Placeholder>,
+    attrs: &DefaultAttrs,
+    font_system: &mut cosmic_text::FontSystem,
+    text: &str,
+    (offset, remove_len, insert): (usize, usize, &str),
+) {
+    match placeholder {
+        Some(placeholder) if placeholder.is_active() => {
+            let mut new_text = text.to_string();
+            new_text.replace_range(offset..offset + remove_len, insert);
+            if new_text.is_empty() {
+                return;
+            }
+            placeholder.set_active(false);
+            editor.with_buffer_mut(|b| {
+                b.set_text(font_system, &new_text, attrs.as_attrs(), Shaping::Advanced)
+            });
+        }
+        _ => {
+            let start = offset_to_cursor(text, offset);
+            let end = offset_to_cursor(text, offset + remove_len);
+            editor.delete_range(start, end);
+            editor.insert_at(start, insert, None);
+        }
+    }
+    editor.set_redraw(true);
+}
+
+pub(crate) fn kb_undo_redo(
+    active_editor: Res<FocusedWidget>,
+    fired: Res<FiredActions>,
+    mut q: Query<
+        (
+            &mut CosmicEditor,
+            (&mut UndoHistory, &mut EditLog),
+            &DefaultAttrs,
+            Option<&mut Placeholder>,
+        ),
+        Without<ReadOnly>,
+    >,
+    mut evw_changed: EventWriter<CosmicTextChanged>,
+    mut font_system: ResMut<CosmicFontSystem>,
+    time: Res<Time>,
+) {
+    let Some(active_editor_entity) = active_editor.0 else {
+        return;
+    };
+    let Ok((mut editor, (mut history, mut log), attrs, mut placeholder)) =
+        q.get_mut(active_editor_entity)
+    else {
+        return;
+    };
+
+    let undo = fired.contains(EditorAction::Undo);
+    let redo = fired.contains(EditorAction::Redo);
+    if !undo && !redo {
+        return;
+    }
+
+    if undo_or_redo(
+        &mut editor,
+        (&mut history, &mut log),
+        attrs,
+        placeholder.as_deref_mut(),
+        &mut font_system,
+        undo,
+        time.elapsed(),
+    ) {
+        evw_changed.send(CosmicTextChanged((active_editor_entity, editor.get_text())));
+    }
+}
+
+/// Undoes (or redoes, if `undo` is false) the most recent entry of `history`.
+///
+/// Returns whether the text changed.
+pub(crate) fn undo_or_redo(
+    editor: &mut CosmicEditor,
+    (history, log): (&mut UndoHistory, &mut EditLog),
+    attrs: &DefaultAttrs,
+    mut placeholder: Option<&mut Placeholder>,
+    font_system: &mut cosmic_text::FontSystem,
+    undo: bool,
+    now: Duration,
+) -> bool {
+    let text = match placeholder.as_deref() {
+        Some(placeholder) if placeholder.is_active() => String::new(),
+        _ => editor.get_text(),
+    };
+    // edits made earlier this frame go into the history first
+    log.record(take_changes(editor), text.clone(), false);
+    history.record(log, editor.cursor(), editor.selection(), now);
+
+    let entry = if undo {
+        history.undo_stack.pop()
+    } else {
+        history.redo_stack.pop()
+    };
+    let Some(entry) = entry else {
+        return false;
+    };
+
+    let expected = if undo {
+        &entry.inserted
+    } else {
+        &entry.removed
+    };
+    if text.get(entry.offset..entry.offset + expected.len()) != Some(expected.as_str()) {
+        warn!(
+            message = "Undo history is out of sync with the buffer text, clearing it",
+            note = "This can happen if the text was replaced in the same frame"
+        );
+        history.clear();
+        return false;
+    }
+
+    let (remove_len, insert, cursor, selection) = if undo {
+        (
+            entry.inserted.len(),
+            &entry.removed,
+            entry.cursor_before,
+            entry.selection_before,
+        )
+    } else {
+        (
+            entry.removed.len(),
+            &entry.inserted,
+            entry.cursor_after,
+            entry.selection_after,
+        )
+    };
+    apply_edit(
+        editor,
+        placeholder.as_deref_mut(),
+        attrs,
+        font_system,
+        &text,
+        (entry.offset, remove_len, insert),
+    );
+    let text = match placeholder.as_deref() {
+        Some(placeholder) if placeholder.is_active() => String::new(),
+        _ => editor.get_text(),
+    };
+    log.record(take_changes(editor), text, true);
+    editor.set_cursor(cursor);
+    editor.set_selection(selection);
+    editor.cursor_visible = true;
+    editor.cursor_timer.reset();
+
+    if undo {
+        history.redo_stack.push(entry);
+    } else {
+        history.undo_stack.push(entry);
+    }
+    history.last_cursor = cursor;
+    history.last_selection = selection;
+    history.sealed = true;
+
+    true
+}
+
+pub(crate) fn record_history(
+    mut q: Query<(&mut UndoHistory, &mut EditLog, Option<&CosmicEditor>)>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed();
+    for (mut history, mut log, editor) in q.iter_mut() {
+        let (cursor, selection) = match editor {
+            Some(editor) => (editor.cursor(), editor.selection()),
+            None => (history.last_cursor, history.last_selection),
+        };
+        let unchanged = log.edits().iter().all(|edit| edit.in_history)
+            && cursor == history.last_cursor
+            && selection == history.last_selection;
+        if unchanged {
+            continue;
+        }
+        history.record(&mut log, cursor, selection, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmic_text::{Action, Attrs, Metrics};
+
+    use super::*;
+    use crate::primary::create_cosmic_font_system;
+
+    fn at(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Logs the edits turning the last known text into `text`, as done once a frame
+    fn edit(history: &mut UndoHistory, log: &mut EditLog, text: &str, now: Duration) {
+        log.record(Vec::new(), text.into(), false);
+        let end = log
+            .edits()
+            .last()
+            .map_or(Cursor::default(), |e| e.cursor_after);
+        history.record(log, end, Selection::None, now);
+    }
+
+    fn undo_texts(history: &UndoHistory) -> Vec<(&str, &str)> {
+        history
+            .undo_entries()
+            .iter()
+            .map(|e| (e.removed.as_str(), e.inserted.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn typed_runs_merge_until_paused() {
+        let (mut history, mut log) = (UndoHistory::default(), EditLog::default());
+        edit(&mut history, &mut log, "", at(0));
+        edit(&mut history, &mut log, "a", at(0));
+        edit(&mut history, &mut log, "ab", at(500));
+        // longer than `merge_timeout` since the last keystroke
+        edit(&mut history, &mut log, "abc", at(2000));
+        edit(&mut history, &mut log, "abcd", at(2100));
+        assert_eq!(undo_texts(&history), [("", "ab"), ("", "cd")]);
+
+        // backspacing is a run of its own
+        edit(&mut history, &mut log, "abc", at(2200));
+        edit(&mut history, &mut log, "ab", at(2300));
+        assert_eq!(undo_texts(&history), [("", "ab"), ("", "cd"), ("cd", "")]);
+    }
+
+    #[test]
+    fn moving_the_cursor_ends_a_run() {
+        let (mut history, mut log) = (UndoHistory::default(), EditLog::default());
+        edit(&mut history, &mut log, "", at(0));
+        edit(&mut history, &mut log, "a", at(0));
+        history.record(&mut log, Cursor::new(0, 0), Selection::None, at(100));
+        edit(&mut history, &mut log, "ab", at(200));
+        assert_eq!(undo_texts(&history), [("", "a"), ("", "b")]);
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let mut font_system = create_cosmic_font_system(CosmicFontConfig {
+            load_system_fonts: false,
+            ..default()
+        });
+        let buffer = CosmicEditBuffer::new(&mut font_system, Metrics::new(14., 18.)).with_text(
+            &mut font_system,
+            "hello",
+            Attrs::new(),
+        );
+        let mut editor = CosmicEditor::clone_from_buffer(&buffer);
+        let attrs = DefaultAttrs::default();
+        let (mut history, mut log) = (UndoHistory::default(), EditLog::default());
+        log.record(take_changes(&mut editor), editor.get_text(), false);
+
+        editor.set_cursor(Cursor::new(0, 5));
+        // a frame passes, see `record_history`
+        history.record(&mut log, editor.cursor(), editor.selection(), at(0));
+        for c in " world".chars() {
+            editor.action(&mut font_system, Action::Insert(c));
+        }
+        let mut undo_or_redo = |editor: &mut CosmicEditor, undo| {
+            undo_or_redo(
+                editor,
+                (&mut history, &mut log),
+                &attrs,
+                None,
+                &mut font_system,
+                undo,
+                at(0),
+            )
+        };
+
+        assert!(undo_or_redo(&mut editor, true));
+        assert_eq!(editor.get_text(), "hello");
+        assert_eq!(editor.cursor().index, 5);
+        assert!(undo_or_redo(&mut editor, false));
+        assert_eq!(editor.get_text(), "hello world");
+        assert_eq!(editor.cursor().index, 11);
+        assert!(undo_or_redo(&mut editor, true));
+        assert_eq!(editor.get_text(), "hello");
+        assert!(!undo_or_redo(&mut editor, true));
+
+        // undoing is logged for other readers, but not added to the history again
+        assert!(log.edits().iter().all(|e| e.in_history));
+        assert!(!history.can_undo());
+        assert!(history.can_redo());
+    }
+}